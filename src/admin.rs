@@ -0,0 +1,177 @@
+use std::{collections::HashSet, sync::Arc};
+
+use poise::{serenity_prelude as serenity, CreateReply};
+use serenity::all::{Command, CreateEmbed, GuildId};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::{config::Registration, err::ErrorKind, AppError, Context, Data};
+
+/// Guilds commands have been registered in since the bot started, so reconnects don't register
+/// them all over again.
+#[derive(Default)]
+pub struct Registered {
+    guilds: Mutex<HashSet<GuildId>>,
+}
+
+/// Registers commands in a guild the bot just became available in, if the registration mode
+/// wants that.
+pub async fn on_guild_create(
+    ctx: &serenity::Context,
+    commands: &[poise::Command<Arc<Data>, AppError>],
+    data: &Data,
+    guild_id: GuildId,
+) -> Result<(), AppError> {
+    let discord = &data.config.discord;
+    let wanted = match discord.registration {
+        Registration::Global => false,
+        Registration::Guilds => true,
+        Registration::Dev => discord.dev_guilds.contains(&guild_id),
+    };
+    if !wanted || !data.registered.guilds.lock().await.insert(guild_id) {
+        return Ok(());
+    }
+
+    if let Err(e) = poise::builtins::register_in_guild(ctx, commands, guild_id).await {
+        data.registered.guilds.lock().await.remove(&guild_id);
+        return Err(e.into());
+    }
+    info!("Registered commands in guild {}", guild_id);
+    Ok(())
+}
+
+/// Bot owner tools
+#[poise::command(
+    category = "Admin",
+    slash_command,
+    prefix_command,
+    subcommands("register", "unregister", "guilds", "shutdown"),
+    subcommand_required,
+    owners_only,
+    hide_in_help,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub async fn admin(_ctx: Context<'_>) -> Result<(), AppError> {
+    Ok(())
+}
+
+fn scope(ctx: Context<'_>, global: Option<bool>) -> Result<Option<GuildId>, AppError> {
+    if global.unwrap_or(false) {
+        return Ok(None);
+    }
+    match ctx.guild_id() {
+        Some(guild_id) => Ok(Some(guild_id)),
+        None => Err(ErrorKind::InvalidValue(
+            "outside a server, commands can only be registered globally".to_owned(),
+        )
+        .into()),
+    }
+}
+
+/// Registers the bot's commands in this server, or everywhere
+#[poise::command(slash_command, prefix_command, owners_only)]
+pub async fn register(
+    ctx: Context<'_>,
+    #[description = "Register globally instead of just here"] global: Option<bool>,
+) -> Result<(), AppError> {
+    let commands = &ctx.framework().options().commands;
+    match scope(ctx, global)? {
+        Some(guild_id) => {
+            poise::builtins::register_in_guild(ctx, commands, guild_id).await?;
+            ctx.data().registered.guilds.lock().await.insert(guild_id);
+            ctx.say(format!("Registered {} commands here", commands.len()))
+                .await?;
+        }
+        None => {
+            poise::builtins::register_globally(ctx, commands).await?;
+            ctx.say(format!(
+                "Registered {} commands globally, they can take a while to show up",
+                commands.len()
+            ))
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the bot's commands from this server, or everywhere
+#[poise::command(slash_command, prefix_command, owners_only)]
+pub async fn unregister(
+    ctx: Context<'_>,
+    #[description = "Unregister the global commands instead of this server's"] global: Option<bool>,
+) -> Result<(), AppError> {
+    match scope(ctx, global)? {
+        Some(guild_id) => {
+            guild_id.set_commands(ctx, Vec::new()).await?;
+            ctx.data().registered.guilds.lock().await.remove(&guild_id);
+            ctx.say("Removed this server's commands").await?;
+        }
+        None => {
+            Command::set_global_commands(ctx, Vec::new()).await?;
+            ctx.say("Removed the global commands").await?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the servers the bot is in
+#[poise::command(slash_command, prefix_command, owners_only)]
+pub async fn guilds(ctx: Context<'_>) -> Result<(), AppError> {
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let mut lines = Vec::new();
+    for guild_id in ctx.cache().guilds() {
+        let (name, members) = ctx
+            .cache()
+            .guild(guild_id)
+            .map(|g| (g.name.clone(), g.member_count))
+            .unwrap_or_else(|| ("Unknown".to_owned(), 0));
+        let mut line = format!("**{}** `{}` - {} members", name, guild_id, members);
+        if manager.get(guild_id).is_some() {
+            line.push_str(" - in voice");
+        }
+        lines.push(line);
+    }
+    lines.sort();
+
+    let total = lines.len();
+    let mut description = String::new();
+    for line in lines {
+        // embed descriptions are capped at 4096 characters
+        if description.len() + line.len() > 4000 {
+            description.push('…');
+            break;
+        }
+        description.push_str(&line);
+        description.push('\n');
+    }
+
+    let embed = CreateEmbed::default()
+        .title(format!("In {} servers", total))
+        .description(description);
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// Shuts the bot down
+#[poise::command(slash_command, prefix_command, owners_only)]
+pub async fn shutdown(ctx: Context<'_>) -> Result<(), AppError> {
+    warn!("Shutdown requested by {}", ctx.author().name);
+    ctx.say("Shutting down").await?;
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    crate::shutdown::shutdown(
+        ctx.serenity_context().http.clone(),
+        manager,
+        ctx.data().clone(),
+        ctx.framework().shard_manager().clone(),
+    )
+    .await;
+    Ok(())
+}
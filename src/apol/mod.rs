@@ -1,10 +1,12 @@
 use anyhow::Result;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, ORIGIN};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use token::AppleMusicToken;
 use tokio::sync::Mutex;
 
+pub mod playlist;
 pub mod search;
 pub mod token;
 #[derive(Clone, Debug)]
@@ -18,6 +20,11 @@ pub struct AppleMusicTokenManager {
     token_lifetime: Duration,
 }
 
+/// Apple Music turns away anything that doesn't look like a browser.
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/127.0.0.0 Safari/537.36";
+
+static USER_AGENT: OnceCell<String> = OnceCell::new();
+
 // Global static instance
 static TOKEN_MANAGER: Lazy<Arc<Mutex<AppleMusicTokenManager>>> =
     Lazy::new(|| Arc::new(Mutex::new(AppleMusicTokenManager::new())));
@@ -58,26 +65,47 @@ impl AppleMusicTokenManager {
     pub fn set_token_lifetime(&mut self, duration: Duration) {
         self.token_lifetime = duration;
     }
-
-    pub fn clear_token(&mut self) {
-        self.current_token = None;
-    }
 }
 
 // Public API functions
 pub async fn get_apple_music_token() -> Result<AppleMusicToken> {
     let mut manager = TOKEN_MANAGER.lock().await;
-    manager.get_token().await
+    let token = manager.get_token().await?;
+    crate::metrics::metrics().set_apple_music_ready();
+    Ok(token)
 }
 
-pub async fn set_token_lifetime(duration: Duration) -> Result<()> {
-    let mut manager = TOKEN_MANAGER.lock().await;
-    manager.set_token_lifetime(duration);
-    Ok(())
+/// Sets the user agent for every request to Apple Music. Only the first call has any effect.
+pub fn set_user_agent(user_agent: String) {
+    let _ = USER_AGENT.set(user_agent);
 }
 
-pub async fn clear_token() -> Result<()> {
+pub fn user_agent() -> &'static str {
+    USER_AGENT
+        .get()
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_USER_AGENT)
+}
+
+/// Headers for a request to the Apple Music catalog, signed with the current token.
+pub async fn catalog_headers() -> Result<HeaderMap> {
+    let tk = get_apple_music_token().await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(ORIGIN, HeaderValue::from_static("https://music.apple.com"));
+    headers.insert(
+        reqwest::header::USER_AGENT,
+        HeaderValue::from_str(user_agent())?,
+    );
+    headers.insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {}", tk.get_jwt()))?,
+    );
+    Ok(headers)
+}
+
+pub async fn set_token_lifetime(duration: Duration) -> Result<()> {
     let mut manager = TOKEN_MANAGER.lock().await;
-    manager.clear_token();
+    manager.set_token_lifetime(duration);
     Ok(())
 }
@@ -0,0 +1,124 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use tracing::{debug, instrument};
+
+use crate::apol::{catalog_headers, search::AppleMusicSongDatum};
+use crate::metrics::metrics;
+
+const API_URL: &str = "https://amp-api.music.apple.com";
+/// The most tracks a playlist is read up to, Apple Music pages through them 100 at a time.
+const MAX_TRACKS: usize = 1000;
+
+/// A page of a playlist's tracks.
+#[derive(Debug, Deserialize)]
+struct Page {
+    data: Vec<AppleMusicSongDatum>,
+    /// Path of the next page, if there is one.
+    next: Option<String>,
+}
+
+/// A link to a playlist on Apple Music.
+#[derive(Debug, PartialEq)]
+pub struct PlaylistLink {
+    pub storefront: String,
+    /// Going by the link, which is all there is to go on without looking it up.
+    pub name: String,
+    pub id: String,
+}
+
+/// Reads a link like
+/// `https://music.apple.com/us/playlist/todays-hits/pl.f4d106fed2bd41149aaacabb233eb5eb`.
+pub fn parse_playlist_url(url: &str) -> Option<PlaylistLink> {
+    let path = url
+        .strip_prefix("https://music.apple.com/")?
+        .split(['?', '#'])
+        .next()?;
+    let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let (storefront, name, id) = match segments.as_slice() {
+        [storefront, "playlist", name, id] => (storefront, Some(*name), id),
+        [storefront, "playlist", id] => (storefront, None, id),
+        _ => return None,
+    };
+    if storefront.len() != 2 || !id.starts_with("pl.") {
+        return None;
+    }
+
+    Some(PlaylistLink {
+        storefront: storefront.to_string(),
+        name: name
+            .map(|n| urlencoding::decode(n).map_or(n.to_owned(), |n| n.into_owned()))
+            .map_or_else(
+                || "Apple Music playlist".to_owned(),
+                |n| n.replace('-', " "),
+            ),
+        id: id.to_string(),
+    })
+}
+
+/// Every track in a catalog playlist, in order.
+#[instrument(
+    name = "apple_music_playlist",
+    skip_all,
+    fields(storefront = %storefront, id = %id)
+)]
+pub async fn playlist_tracks(storefront: &str, id: &str) -> Result<Vec<AppleMusicSongDatum>> {
+    debug!("Reading Apple Music playlist");
+
+    let headers = catalog_headers().await?;
+    let client = reqwest::Client::new();
+
+    let mut tracks = Vec::new();
+    let mut next = Some(format!(
+        "/v1/catalog/{}/playlists/{}/tracks?limit=100",
+        storefront,
+        urlencoding::encode(id)
+    ));
+    while let Some(path) = next.take() {
+        let response = metrics().apple_music(
+            client
+                .get(format!("{}{}", API_URL, path))
+                .headers(headers.clone())
+                .send()
+                .await,
+        )?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await?;
+            return Err(anyhow!("{}: {}", status, text));
+        }
+
+        let page: Page = response.json().await?;
+        tracks.extend(page.data);
+        if tracks.len() < MAX_TRACKS {
+            next = page.next;
+        }
+    }
+    tracks.truncate(MAX_TRACKS);
+
+    Ok(tracks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_playlist_links() {
+        assert_eq!(
+            parse_playlist_url(
+                "https://music.apple.com/gb/playlist/todays-hits/pl.f4d106fed2bd41149aaacabb233eb5eb?l=en"
+            ),
+            Some(PlaylistLink {
+                storefront: "gb".to_owned(),
+                name: "todays hits".to_owned(),
+                id: "pl.f4d106fed2bd41149aaacabb233eb5eb".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse_playlist_url("https://music.apple.com/us/playlist/pl.u-abc").map(|p| p.id),
+            Some("pl.u-abc".to_owned())
+        );
+        assert!(parse_playlist_url("https://music.apple.com/us/album/x/1440857781").is_none());
+        assert!(parse_playlist_url("https://open.spotify.com/playlist/abc").is_none());
+    }
+}
@@ -1,44 +1,41 @@
 use anyhow::Result;
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, ORIGIN, USER_AGENT};
 use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
 
-use crate::apol::get_apple_music_token;
+use crate::apol::catalog_headers;
+use crate::metrics::metrics;
 
-// Modified search function
-pub async fn search_track(query: String) -> Result<Option<AppleMusicSong>> {
-    println!("searching for {}", query);
-
-    let tk = get_apple_music_token().await?;
-
-    let mut headers = HeaderMap::new();
-    headers.insert(ORIGIN, HeaderValue::from_static("https://music.apple.com"));
-    headers.insert(
-        USER_AGENT,
-        HeaderValue::from_static(
-            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/127.0.0.0 Safari/537.36"
-        ),
-    );
-    headers.insert(
-        AUTHORIZATION,
-        HeaderValue::from_str(&format!("Bearer {}", tk.get_jwt()))?,
-    );
+/// Looks up the best matching song in a storefront's catalog.
+pub async fn search_track(query: String, storefront: &str) -> Result<Option<AppleMusicSong>> {
+    search_tracks(query, storefront, 1).await
+}
 
+/// Looks up the `limit` best matching songs in a storefront's catalog, best first.
+#[instrument(
+    name = "apple_music_search",
+    skip_all,
+    fields(query = %query, storefront = %storefront)
+)]
+pub async fn search_tracks(
+    query: String,
+    storefront: &str,
+    limit: u8,
+) -> Result<Option<AppleMusicSong>> {
+    debug!("Searching Apple Music");
+
+    let headers = catalog_headers().await?;
     let client = reqwest::Client::new();
     let url = format!(
-        "https://amp-api.music.apple.com/v1/catalog/{}/search?term={}&limit=1&types=songs",
-        "us",
-        urlencoding::encode(&query)
+        "https://amp-api.music.apple.com/v1/catalog/{}/search?term={}&limit={}&types=songs",
+        storefront,
+        urlencoding::encode(&query),
+        limit
     );
 
-    let response = client.get(&url).headers(headers).send().await?;
+    let response = metrics().apple_music(client.get(&url).headers(headers).send().await)?;
 
     if response.status().is_success() {
-        let json: serde_json::Value = response.json().await?;
-        let songs = json
-            .get("results")
-            .and_then(|v| v.get("songs"))
-            .and_then(|v| serde_json::from_value(v.clone()).ok());
-        Ok(songs)
+        Ok(songs(response.json().await?))
     } else {
         let status = response.status();
         let text = response.text().await?;
@@ -46,6 +43,13 @@ pub async fn search_track(query: String) -> Result<Option<AppleMusicSong>> {
     }
 }
 
+/// The songs in a catalog search response.
+fn songs(json: serde_json::Value) -> Option<AppleMusicSong> {
+    json.get("results")
+        .and_then(|v| v.get("songs"))
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppleMusicSong {
     pub data: Option<Vec<AppleMusicSongDatum>>,
@@ -63,6 +67,7 @@ pub struct AppleMusicSongDatum {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Attributes {
     pub album_name: Option<String>,
     pub has_time_synced_lyrics: Option<bool>,
@@ -87,6 +92,7 @@ pub struct Attributes {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Artwork {
     pub width: Option<i32>,
     pub url: Option<String>,
@@ -111,11 +117,13 @@ pub struct Preview {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Meta {
     pub content_version: Option<ContentVersion>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub struct ContentVersion {
     pub mz_indexer: Option<i32>,
     pub rtci: Option<i32>,
@@ -140,3 +148,45 @@ pub struct AlbumsDatum {
     pub type_name: Option<String>,
     pub href: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_catalog_search_results() {
+        let json = serde_json::from_str(include_str!("testdata/search_songs.json")).unwrap();
+        let song = songs(json)
+            .and_then(|s| s.data)
+            .and_then(|data| data.into_iter().next())
+            .unwrap();
+        assert_eq!(song.id.as_deref(), Some("1440806041"));
+        assert_eq!(song.type_name.as_deref(), Some("songs"));
+
+        let attributes = song.attributes.unwrap();
+        assert_eq!(attributes.name.as_deref(), Some("Bohemian Rhapsody"));
+        assert_eq!(attributes.artist_name.as_deref(), Some("Queen"));
+        assert_eq!(
+            attributes.album_name.as_deref(),
+            Some("A Night At The Opera (2011 Remaster)")
+        );
+        assert_eq!(attributes.genre_names.unwrap(), ["Rock", "Music"]);
+        assert_eq!(attributes.duration_in_millis, Some(354947));
+        assert_eq!(attributes.isrc.as_deref(), Some("GBUM71029604"));
+        assert_eq!(
+            attributes.play_params.unwrap().kind.as_deref(),
+            Some("song")
+        );
+        assert_eq!(
+            attributes.artwork.unwrap().bg_color.as_deref(),
+            Some("1a1a1a")
+        );
+
+        let version = song.meta.unwrap().content_version.unwrap();
+        assert_eq!(version.mz_indexer, Some(1706063296));
+        assert_eq!(version.rtci, Some(1705944000));
+
+        let albums = song.relationships.unwrap().albums.unwrap().data.unwrap();
+        assert_eq!(albums[0].id.as_deref(), Some("1440806023"));
+    }
+}
@@ -2,72 +2,25 @@ use anyhow::{anyhow, Result};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use reqwest::header::{HeaderMap, HeaderValue};
-use serde::Deserialize;
-#[derive(Debug, Clone)]
-pub enum AppleMusicToken {
-    Unauthenticated {
-        jwt: String,
-    },
-    Authenticated {
-        jwt: String,
-        user_token: String,
-        store_id: String,
-    },
-}
+use tracing::instrument;
 
-#[derive(Deserialize)]
-struct Storefronts {
-    data: Vec<StorefrontData>,
-}
+use super::{user_agent, DEFAULT_USER_AGENT};
+use crate::metrics::metrics;
 
-#[derive(Deserialize)]
-struct StorefrontData {
-    id: String,
+/// The anonymous token music.apple.com's web player uses to read the catalog.
+#[derive(Debug, Clone)]
+pub struct AppleMusicToken {
+    jwt: String,
 }
 
 impl AppleMusicToken {
     pub async fn new() -> Result<Self> {
         let jwt = get_bearer_token().await?;
-        Ok(AppleMusicToken::Unauthenticated { jwt })
-    }
-
-    pub async fn authenticate(self, user_token: String) -> Result<Self> {
-        match self {
-            AppleMusicToken::Unauthenticated { jwt } => {
-                let store_id = get_storefront(&jwt, &user_token).await?;
-                Ok(AppleMusicToken::Authenticated {
-                    jwt,
-                    user_token,
-                    store_id,
-                })
-            }
-            AppleMusicToken::Authenticated { .. } => Err(anyhow!("Token is already authenticated")),
-        }
+        Ok(AppleMusicToken { jwt })
     }
 
     pub fn get_jwt(&self) -> &str {
-        match self {
-            AppleMusicToken::Unauthenticated { jwt } => jwt,
-            AppleMusicToken::Authenticated { jwt, .. } => jwt,
-        }
-    }
-
-    pub fn get_user_token(&self) -> Option<&str> {
-        match self {
-            AppleMusicToken::Unauthenticated { .. } => None,
-            AppleMusicToken::Authenticated { user_token, .. } => Some(user_token),
-        }
-    }
-
-    pub fn get_store_id(&self) -> Option<&str> {
-        match self {
-            AppleMusicToken::Unauthenticated { .. } => None,
-            AppleMusicToken::Authenticated { store_id, .. } => Some(store_id),
-        }
-    }
-
-    pub fn is_authenticated(&self) -> bool {
-        matches!(self, AppleMusicToken::Authenticated { .. })
+        &self.jwt
     }
 }
 
@@ -91,20 +44,27 @@ fn create_default_headers() -> HeaderMap {
         "Accept-Encoding",
         HeaderValue::from_static("gzip, deflate, br"),
     );
-    headers.insert("User-Agent", HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/110.0.0.0 Safari/537.36"));
+    headers.insert(
+        "User-Agent",
+        HeaderValue::from_str(user_agent())
+            .unwrap_or_else(|_| HeaderValue::from_static(DEFAULT_USER_AGENT)),
+    );
     headers
 }
 
+#[instrument(name = "apple_music_token")]
 pub async fn get_bearer_token() -> Result<String> {
     let client = reqwest::Client::new();
     let headers = create_default_headers();
 
     // Get main page
-    let main_page_response = client
-        .get("https://music.apple.com/us/browse")
-        .headers(headers.clone())
-        .send()
-        .await?;
+    let main_page_response = metrics().apple_music(
+        client
+            .get("https://music.apple.com/us/browse")
+            .headers(headers.clone())
+            .send()
+            .await,
+    )?;
 
     if !main_page_response.status().is_success() {
         return Err(anyhow!("Failed to send request to Apple Music"));
@@ -122,11 +82,13 @@ pub async fn get_bearer_token() -> Result<String> {
         .as_str();
 
     // Get JS file content
-    let js_file_response = client
-        .get(format!("https://music.apple.com/assets/{}", js_file))
-        .headers(headers)
-        .send()
-        .await?;
+    let js_file_response = metrics().apple_music(
+        client
+            .get(format!("https://music.apple.com/assets/{}", js_file))
+            .headers(headers)
+            .send()
+            .await,
+    )?;
 
     if !js_file_response.status().is_success() {
         return Err(anyhow!("Failed to send request to Apple Music"));
@@ -147,34 +109,3 @@ pub async fn get_bearer_token() -> Result<String> {
 
     Ok(jwt.to_string())
 }
-
-pub async fn get_storefront(jwt: &str, user_token: &str) -> Result<String> {
-    let client = reqwest::Client::new();
-    let mut headers = create_default_headers();
-
-    headers.insert(
-        "Authorization",
-        HeaderValue::from_str(&format!("Bearer {}", jwt))?,
-    );
-    headers.insert("media-user-token", HeaderValue::from_str(user_token)?);
-
-    let storefront_response = client
-        .get("https://amp-api.music.apple.com/v1/me/storefront")
-        .headers(headers)
-        .send()
-        .await?;
-
-    if !storefront_response.status().is_success() {
-        return Err(anyhow!("Failed to send request to Apple Music"));
-    }
-
-    let storefront: Storefronts = storefront_response.json().await?;
-    let store_id = storefront
-        .data
-        .first()
-        .ok_or_else(|| anyhow!("No storefront data found"))?
-        .id
-        .clone();
-
-    Ok(store_id)
-}
@@ -0,0 +1,719 @@
+use std::{
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use reqwest::Client as HttpClient;
+use serde::Deserialize;
+use serenity::all::{GatewayIntents, GuildId, UserId};
+use songbird::input::YoutubeDl;
+use thiserror::Error;
+
+/// Where the config file is looked for when no `--config` or `CONFIG_FILE` is given.
+pub const DEFAULT_PATH: &str = "config.toml";
+
+/// Everything that can be configured without a rebuild, read from a TOML file and overlaid by
+/// environment variables.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub discord: DiscordConfig,
+    /// Where saved state like radio stations and settings is kept.
+    pub data_dir: PathBuf,
+    /// Directory of audio files `/play` can play from.
+    pub media_dir: Option<PathBuf>,
+    pub cache: CacheConfig,
+    pub apple_music: AppleMusicConfig,
+    pub http: HttpConfig,
+    pub ytdl: YtdlConfig,
+    pub youtube: YoutubeConfig,
+    pub server: ServerConfig,
+    pub log: LogConfig,
+    pub dashboard: DashboardConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            discord: DiscordConfig::default(),
+            data_dir: PathBuf::from("data"),
+            media_dir: None,
+            cache: CacheConfig::default(),
+            apple_music: AppleMusicConfig::default(),
+            http: HttpConfig::default(),
+            ytdl: YtdlConfig::default(),
+            youtube: YoutubeConfig::default(),
+            server: ServerConfig::default(),
+            log: LogConfig::default(),
+            dashboard: DashboardConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscordConfig {
+    pub token: String,
+    /// Gateway intent names, like `guild_voice_states`, or `non_privileged` for all of those.
+    pub intents: Vec<String>,
+    pub registration: Registration,
+    /// Guilds commands are registered in when `registration` is `dev`.
+    pub dev_guilds: Vec<GuildId>,
+    /// People who can use `/admin`, on top of the application's owners.
+    pub owners: Vec<UserId>,
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            token: String::new(),
+            intents: vec!["non_privileged".to_owned(), "message_content".to_owned()],
+            registration: Registration::default(),
+            dev_guilds: Vec::new(),
+            owners: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for DiscordConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiscordConfig")
+            .field("token", &"<redacted>")
+            .field("intents", &self.intents)
+            .field("registration", &self.registration)
+            .field("dev_guilds", &self.dev_guilds)
+            .field("owners", &self.owners)
+            .finish()
+    }
+}
+
+/// Where slash commands get registered.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Registration {
+    /// Everywhere, which can take a while to show up.
+    Global,
+    /// In each guild as the bot joins it, which shows up right away.
+    #[default]
+    Guilds,
+    /// Only in `dev_guilds`, for testing changes before they go out.
+    Dev,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Where prefetched tracks are downloaded to, `<data_dir>/cache` when unset.
+    pub dir: Option<PathBuf>,
+    pub max_mb: u64,
+    pub max_age_hours: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            max_mb: 1024,
+            max_age_hours: 168,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn max_bytes(&self) -> u64 {
+        self.max_mb * 1024 * 1024
+    }
+
+    pub fn max_age(&self) -> Duration {
+        Duration::from_secs(self.max_age_hours * 60 * 60)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppleMusicConfig {
+    /// Storefront used for servers that haven't picked their own.
+    pub storefront: String,
+    /// Seconds a scraped token is reused for.
+    pub token_lifetime: u64,
+    pub user_agent: String,
+}
+
+impl Default for AppleMusicConfig {
+    fn default() -> Self {
+        Self {
+            storefront: "us".to_owned(),
+            token_lifetime: 3600,
+            user_agent: crate::apol::DEFAULT_USER_AGENT.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// User agent for fetching audio and metadata.
+    pub user_agent: String,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: concat!("marine/", env!("CARGO_PKG_VERSION")).to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct YtdlConfig {
+    /// yt-dlp, or anything that takes the same arguments.
+    pub program: String,
+    /// Passed to every run before our own arguments, e.g. `["--cookies", "cookies.txt"]`.
+    pub args: Vec<String>,
+}
+
+impl Default for YtdlConfig {
+    fn default() -> Self {
+        Self {
+            program: "yt-dlp".to_owned(),
+            args: Vec::new(),
+        }
+    }
+}
+
+impl YtdlConfig {
+    /// A songbird source for `url` using the configured program. The config lives as long as the
+    /// bot does, so the program name can be borrowed for good.
+    pub fn source(&'static self, http: HttpClient, url: String) -> YoutubeDl {
+        YoutubeDl::new_ytdl_like(&self.program, http, url).user_args(self.args.clone())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct YoutubeConfig {
+    /// Web client version sent when asking YouTube for a video's loudness. YouTube stops
+    /// answering old versions now and then, so bump it when loudness lookups start failing.
+    pub client_version: String,
+}
+
+impl Default for YoutubeConfig {
+    fn default() -> Self {
+        Self {
+            client_version: crate::voice::loudness::DEFAULT_CLIENT_VERSION.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Where to serve health checks and metrics, off when unset.
+    pub listen: Option<SocketAddr>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DashboardConfig {
+    /// OAuth2 client ID of the Discord application, the dashboard is off when unset.
+    pub client_id: Option<String>,
+    pub client_secret: String,
+    /// Where people reach the dashboard, which Discord redirects back to after logging in.
+    pub public_url: String,
+    /// Where Discord's OAuth2 and API endpoints are, only changed to test against a stand-in.
+    pub discord_url: String,
+}
+
+impl Default for DashboardConfig {
+    fn default() -> Self {
+        Self {
+            client_id: None,
+            client_secret: String::new(),
+            public_url: "http://localhost:8080".to_owned(),
+            discord_url: "https://discord.com".to_owned(),
+        }
+    }
+}
+
+impl fmt::Debug for DashboardConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DashboardConfig")
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"<redacted>")
+            .field("public_url", &self.public_url)
+            .field("discord_url", &self.discord_url)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// OpenTelemetry collector to send traces to, like `http://localhost:4318/v1/traces`. Needs
+    /// the `otlp` feature.
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Pretty,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+/// Everything wrong with the config, so it can all be fixed in one go.
+#[derive(Error, Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Reads the config file, overlays the environment and validates the result. A missing file
+    /// is only an error if it was asked for explicitly.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let env_path = std::env::var("CONFIG_FILE").ok().map(PathBuf::from);
+        let explicit = path.map(Path::to_path_buf).or(env_path);
+        let path = explicit
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_PATH));
+
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(text) => Self::from_toml(&text)
+                .map_err(|e| ConfigError(vec![format!("{}: {}", path.display(), e)]))?,
+            Err(_) if explicit.is_none() => Self::default(),
+            Err(e) => {
+                return Err(ConfigError(vec![format!(
+                    "couldn't read {}: {}",
+                    path.display(),
+                    e
+                )]))
+            }
+        };
+
+        let mut problems = config.apply_env(|key| std::env::var(key).ok());
+        problems.extend(config.validate());
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(problems))
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// Overrides values with any environment variables that are set, returning the ones that
+    /// couldn't be parsed.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut number = |key: &str, target: &mut u64| {
+            if let Some(value) = var(key) {
+                match value.trim().parse() {
+                    Ok(n) => *target = n,
+                    Err(_) => {
+                        problems.push(format!("{} has to be a whole number, not {:?}", key, value))
+                    }
+                }
+            }
+        };
+        number("CACHE_MAX_MB", &mut self.cache.max_mb);
+        number("CACHE_MAX_AGE_HOURS", &mut self.cache.max_age_hours);
+        number(
+            "APPLE_MUSIC_TOKEN_LIFETIME",
+            &mut self.apple_music.token_lifetime,
+        );
+
+        let list = |value: String| -> Vec<String> {
+            value
+                .split([',', ' '])
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned)
+                .collect()
+        };
+
+        if let Some(token) = var("DISCORD_TOKEN") {
+            self.discord.token = token;
+        }
+        if let Some(intents) = var("DISCORD_INTENTS") {
+            self.discord.intents = list(intents);
+        }
+        if let Some(registration) = var("COMMAND_REGISTRATION") {
+            match registration.trim().to_lowercase().as_str() {
+                "global" => self.discord.registration = Registration::Global,
+                "guilds" => self.discord.registration = Registration::Guilds,
+                "dev" => self.discord.registration = Registration::Dev,
+                _ => problems.push(format!(
+                    "COMMAND_REGISTRATION has to be global, guilds or dev, not {:?}",
+                    registration
+                )),
+            }
+        }
+        if let Some(guilds) = var("DEV_GUILDS") {
+            match list(guilds).iter().map(|id| id.parse()).collect() {
+                Ok(ids) => self.discord.dev_guilds = ids,
+                Err(_) => problems.push("DEV_GUILDS has to be a list of guild IDs".to_owned()),
+            }
+        }
+        if let Some(owners) = var("DISCORD_OWNERS") {
+            match list(owners).iter().map(|id| id.parse()).collect() {
+                Ok(ids) => self.discord.owners = ids,
+                Err(_) => problems.push("DISCORD_OWNERS has to be a list of user IDs".to_owned()),
+            }
+        }
+        if let Some(dir) = var("DATA_DIR") {
+            self.data_dir = PathBuf::from(dir);
+        }
+        if let Some(dir) = var("MEDIA_DIR") {
+            self.media_dir = Some(PathBuf::from(dir));
+        }
+        if let Some(dir) = var("CACHE_DIR") {
+            self.cache.dir = Some(PathBuf::from(dir));
+        }
+        if let Some(storefront) = var("APPLE_MUSIC_STOREFRONT") {
+            self.apple_music.storefront = storefront;
+        }
+        if let Some(user_agent) = var("APPLE_MUSIC_USER_AGENT") {
+            self.apple_music.user_agent = user_agent;
+        }
+        if let Some(user_agent) = var("HTTP_USER_AGENT") {
+            self.http.user_agent = user_agent;
+        }
+        if let Some(program) = var("YTDL_PROGRAM") {
+            self.ytdl.program = program;
+        }
+        if let Some(args) = var("YTDL_ARGS") {
+            self.ytdl.args = args.split_whitespace().map(str::to_owned).collect();
+        }
+        if let Some(version) = var("YOUTUBE_CLIENT_VERSION") {
+            self.youtube.client_version = version;
+        }
+        if let Some(format) = var("LOG_FORMAT") {
+            match format.trim().to_lowercase().as_str() {
+                "pretty" => self.log.format = LogFormat::Pretty,
+                "json" => self.log.format = LogFormat::Json,
+                _ => problems.push(format!(
+                    "LOG_FORMAT has to be pretty or json, not {:?}",
+                    format
+                )),
+            }
+        }
+        if let Some(endpoint) = var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            self.log.otlp_endpoint = Some(endpoint);
+        }
+        if let Some(client_id) = var("DASHBOARD_CLIENT_ID") {
+            self.dashboard.client_id = Some(client_id).filter(|id| !id.is_empty());
+        }
+        if let Some(secret) = var("DASHBOARD_CLIENT_SECRET") {
+            self.dashboard.client_secret = secret;
+        }
+        if let Some(url) = var("DASHBOARD_PUBLIC_URL") {
+            self.dashboard.public_url = url;
+        }
+        if let Some(url) = var("DASHBOARD_DISCORD_URL") {
+            self.dashboard.discord_url = url;
+        }
+        if let Some(listen) = var("SERVER_LISTEN") {
+            match listen.trim().parse() {
+                Ok(addr) => self.server.listen = Some(addr),
+                Err(_) => problems.push(format!(
+                    "SERVER_LISTEN has to be an address like 0.0.0.0:8080, not {:?}",
+                    listen
+                )),
+            }
+        }
+
+        problems
+    }
+
+    /// Everything that's wrong with the config, in words someone can act on.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.discord.token.trim().is_empty() {
+            problems.push(
+                "the Discord token is missing, set DISCORD_TOKEN or discord.token".to_owned(),
+            );
+        }
+        if self.discord.registration == Registration::Dev && self.discord.dev_guilds.is_empty() {
+            problems.push("registering commands in dev guilds needs discord.dev_guilds".to_owned());
+        }
+        for name in &self.discord.intents {
+            if parse_intent(name).is_none() {
+                problems.push(format!("{:?} isn't a gateway intent", name));
+            }
+        }
+        if self.data_dir.as_os_str().is_empty() {
+            problems.push("data_dir can't be empty".to_owned());
+        }
+        if let Some(dir) = &self.media_dir {
+            if !dir.is_dir() {
+                problems.push(format!("media_dir {} isn't a directory", dir.display()));
+            }
+        }
+        if self.cache.max_mb == 0 {
+            problems.push("cache.max_mb has to be more than 0".to_owned());
+        }
+        let storefront = &self.apple_music.storefront;
+        if storefront.len() != 2 || !storefront.chars().all(|c| c.is_ascii_lowercase()) {
+            problems.push(format!(
+                "apple_music.storefront has to be a lowercase two letter country code, not {:?}",
+                storefront
+            ));
+        }
+        if self.apple_music.token_lifetime < 60 {
+            problems.push("apple_music.token_lifetime has to be at least 60 seconds".to_owned());
+        }
+        for (name, user_agent) in [
+            ("apple_music.user_agent", &self.apple_music.user_agent),
+            ("http.user_agent", &self.http.user_agent),
+        ] {
+            if user_agent.trim().is_empty()
+                || reqwest::header::HeaderValue::from_str(user_agent).is_err()
+            {
+                problems.push(format!("{} isn't a valid user agent", name));
+            }
+        }
+        if self.ytdl.program.trim().is_empty() {
+            problems.push("ytdl.program can't be empty".to_owned());
+        }
+        if self.youtube.client_version.trim().is_empty() {
+            problems.push("youtube.client_version can't be empty".to_owned());
+        }
+        if self.dashboard.client_id.is_some() {
+            if self.dashboard.client_secret.trim().is_empty() {
+                problems.push("the dashboard needs dashboard.client_secret".to_owned());
+            }
+            if self.server.listen.is_none() {
+                problems.push("the dashboard needs server.listen to be served from".to_owned());
+            }
+            for (name, url) in [
+                ("dashboard.public_url", &self.dashboard.public_url),
+                ("dashboard.discord_url", &self.dashboard.discord_url),
+            ] {
+                if reqwest::Url::parse(url).is_err() {
+                    problems.push(format!("{} {:?} isn't a URL", name, url));
+                }
+            }
+        }
+        if let Some(endpoint) = &self.log.otlp_endpoint {
+            if !cfg!(feature = "otlp") {
+                problems.push("log.otlp_endpoint needs a build with the otlp feature".to_owned());
+            } else if reqwest::Url::parse(endpoint).is_err() {
+                problems.push(format!("log.otlp_endpoint {:?} isn't a URL", endpoint));
+            }
+        }
+
+        problems
+    }
+
+    /// The gateway intents to connect with. Only call this on a validated config.
+    pub fn intents(&self) -> GatewayIntents {
+        self.discord
+            .intents
+            .iter()
+            .filter_map(|name| parse_intent(name))
+            .fold(GatewayIntents::empty(), |all, intent| all | intent)
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        self.cache
+            .dir
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("cache"))
+    }
+}
+
+fn parse_intent(name: &str) -> Option<GatewayIntents> {
+    let name = name.trim().to_uppercase();
+    match name.as_str() {
+        "NON_PRIVILEGED" => Some(GatewayIntents::non_privileged()),
+        "ALL" => Some(GatewayIntents::all()),
+        _ => GatewayIntents::from_name(&name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    fn valid() -> Config {
+        let mut config = Config::from_toml("").unwrap();
+        config.discord.token = "token".to_owned();
+        config
+    }
+
+    #[test]
+    fn defaults_are_valid_once_there_is_a_token() {
+        assert_eq!(valid().validate(), Vec::<String>::new());
+        assert_eq!(valid().data_dir, PathBuf::from("data"));
+        assert_eq!(valid().cache_dir(), PathBuf::from("data/cache"));
+        assert_eq!(Config::from_toml("").unwrap().validate().len(), 1);
+    }
+
+    #[test]
+    fn reads_toml() {
+        let config = Config::from_toml(
+            r#"
+            data_dir = "/var/lib/marine"
+
+            [discord]
+            token = "abc"
+            intents = ["guilds", "guild_voice_states"]
+            registration = "global"
+            owners = ["80351110224678912"]
+
+            [ytdl]
+            args = ["--cookies", "cookies.txt"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.discord.registration, Registration::Global);
+        assert_eq!(config.discord.owners, [UserId::new(80351110224678912)]);
+        assert_eq!(
+            config.intents(),
+            GatewayIntents::GUILDS | GatewayIntents::GUILD_VOICE_STATES
+        );
+        assert_eq!(config.ytdl.program, "yt-dlp");
+        assert_eq!(config.ytdl.args, ["--cookies", "cookies.txt"]);
+        assert_eq!(config.cache_dir(), PathBuf::from("/var/lib/marine/cache"));
+    }
+
+    #[test]
+    fn dev_registration_needs_guilds() {
+        let mut config = valid();
+        config.discord.registration = Registration::Dev;
+        assert_eq!(config.validate().len(), 1);
+        config.discord.dev_guilds.push(GuildId::new(1));
+        assert!(config.validate().is_empty());
+    }
+
+    #[test]
+    fn example_config_is_up_to_date() {
+        let example = Config::from_toml(include_str!("../config.example.toml")).unwrap();
+        assert_eq!(
+            example.validate().len(),
+            1,
+            "only the token should be missing"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(Config::from_toml("[discord]\ntokn = \"abc\"").is_err());
+    }
+
+    #[test]
+    fn env_overrides_the_file() {
+        let mut config = Config::from_toml("[cache]\nmax_mb = 10").unwrap();
+        let problems = config.apply_env(env(&[
+            ("DISCORD_TOKEN", "from-env"),
+            ("CACHE_MAX_MB", "20"),
+            ("YTDL_ARGS", "--proxy socks5://localhost"),
+            ("DISCORD_INTENTS", "guilds,message_content"),
+            ("COMMAND_REGISTRATION", "dev"),
+            ("DEV_GUILDS", "123, 456"),
+            ("SERVER_LISTEN", "127.0.0.1:9000"),
+            ("LOG_FORMAT", "JSON"),
+        ]));
+
+        assert!(problems.is_empty());
+        assert_eq!(config.discord.token, "from-env");
+        assert_eq!(config.cache.max_mb, 20);
+        assert_eq!(config.ytdl.args, ["--proxy", "socks5://localhost"]);
+        assert_eq!(config.discord.registration, Registration::Dev);
+        assert_eq!(
+            config.discord.dev_guilds,
+            [GuildId::new(123), GuildId::new(456)]
+        );
+        assert_eq!(
+            config.intents(),
+            GatewayIntents::GUILDS | GatewayIntents::MESSAGE_CONTENT
+        );
+        assert_eq!(
+            config.server.listen,
+            Some("127.0.0.1:9000".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let mut config = Config::from_toml(
+            r#"
+            [discord]
+            intents = ["guilds", "telepathy"]
+
+            [apple_music]
+            storefront = "USA"
+            token_lifetime = 5
+            "#,
+        )
+        .unwrap();
+        let mut problems = config.apply_env(env(&[
+            ("CACHE_MAX_AGE_HOURS", "a week"),
+            ("COMMAND_REGISTRATION", "everywhere"),
+            ("DEV_GUILDS", "my-server"),
+        ]));
+        problems.extend(config.validate());
+
+        let expected = [
+            "CACHE_MAX_AGE_HOURS",
+            "COMMAND_REGISTRATION",
+            "DEV_GUILDS",
+            "token is missing",
+            "\"telepathy\" isn't a gateway intent",
+            "storefront",
+            "token_lifetime",
+        ];
+        assert_eq!(problems.len(), expected.len(), "{:?}", problems);
+        for (problem, expected) in problems.iter().zip(expected) {
+            assert!(problem.contains(expected), "{:?}", problem);
+        }
+    }
+
+    #[test]
+    fn dashboard_needs_a_secret_and_a_server() {
+        let mut config = valid();
+        config.dashboard.client_id = Some("123".to_owned());
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+
+        config.dashboard.client_secret = "hunter2".to_owned();
+        config.server.listen = Some("127.0.0.1:8080".parse().unwrap());
+        assert!(config.validate().is_empty());
+        assert!(!format!("{:?}", config).contains("hunter2"));
+    }
+
+    #[test]
+    fn debug_output_hides_the_token() {
+        let config = valid();
+        assert!(!format!("{:?}", config).contains("\"token\""));
+        assert!(format!("{:?}", config.discord).contains("<redacted>"));
+    }
+}
@@ -0,0 +1,523 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{AppendHeaders, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, UserId};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// How long a login lasts.
+const SESSION_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// How long someone has to finish logging in with Discord.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const COOKIE: &str = "marine_session";
+/// Holds the state of a login in progress, so only the browser that started it can finish it.
+const LOGIN_COOKIE: &str = "marine_login";
+
+/// Discord's OAuth2 endpoints, or a stand-in with the same paths.
+pub struct OAuth {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Where Discord sends people back to, `<public_url>/auth/callback`.
+    pub redirect_uri: String,
+    pub discord_url: String,
+    pub http: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct DiscordUser {
+    id: UserId,
+    username: String,
+    global_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PartialGuild {
+    id: GuildId,
+}
+
+impl OAuth {
+    fn authorize_url(&self, state: &str) -> String {
+        format!(
+            "{}/oauth2/authorize?response_type=code&scope=identify%20guilds&client_id={}&redirect_uri={}&state={}",
+            self.discord_url,
+            urlencoding::encode(&self.client_id),
+            urlencoding::encode(&self.redirect_uri),
+            state
+        )
+    }
+
+    /// Trades the code Discord handed back for who logged in and which servers they're in.
+    async fn login(&self, code: &str) -> Result<Session> {
+        let token: TokenResponse = self
+            .http
+            .post(format!("{}/api/oauth2/token", self.discord_url))
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.redirect_uri.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let user: DiscordUser = self.api("users/@me", &token.access_token).await?;
+        let guilds: Vec<PartialGuild> = self.api("users/@me/guilds", &token.access_token).await?;
+
+        Ok(Session {
+            user_id: user.id,
+            name: user.global_name.unwrap_or(user.username),
+            guilds: guilds.into_iter().map(|g| g.id).collect(),
+            expires: Instant::now() + SESSION_LIFETIME,
+        })
+    }
+
+    async fn api<T: serde::de::DeserializeOwned>(&self, path: &str, token: &str) -> Result<T> {
+        Ok(self
+            .http
+            .get(format!("{}/api/v10/{}", self.discord_url, path))
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+/// Someone logged in to the dashboard.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: UserId,
+    pub name: String,
+    /// Servers they were in when they logged in.
+    pub guilds: HashSet<GuildId>,
+    expires: Instant,
+}
+
+/// Logins in progress and the browsers that are logged in.
+pub struct Auth {
+    oauth: OAuth,
+    /// Whether cookies should only be sent over HTTPS.
+    secure: bool,
+    pending: Mutex<HashMap<String, Instant>>,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Auth {
+    pub fn new(oauth: OAuth, secure: bool) -> Self {
+        Self {
+            oauth,
+            secure,
+            pending: Default::default(),
+            sessions: Default::default(),
+        }
+    }
+
+    /// The session the request's cookie belongs to.
+    pub async fn session(&self, headers: &HeaderMap) -> Option<Session> {
+        let id = read_cookie(headers, COOKIE)?;
+        let sessions = self.sessions.lock().await;
+        sessions
+            .get(id)
+            .filter(|s| s.expires > Instant::now())
+            .cloned()
+    }
+
+    fn cookie(&self, name: &str, value: &str, max_age: Duration) -> String {
+        format!(
+            "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}",
+            name,
+            value,
+            max_age.as_secs(),
+            if self.secure { "; Secure" } else { "" }
+        )
+    }
+}
+
+fn read_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|cookie| cookie.trim().strip_prefix(name)?.strip_prefix('='))
+}
+
+fn random_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Logging in and out, and who's logged in.
+pub fn router(auth: Arc<Auth>) -> Router {
+    Router::new()
+        .route("/auth/login", get(login))
+        .route("/auth/callback", get(callback))
+        .route("/auth/logout", post(logout))
+        .route("/api/me", get(me))
+        .with_state(auth)
+}
+
+async fn login(State(auth): State<Arc<Auth>>) -> impl IntoResponse {
+    let state = random_id();
+    {
+        let mut pending = auth.pending.lock().await;
+        pending.retain(|_, started| started.elapsed() < LOGIN_TIMEOUT);
+        pending.insert(state.clone(), Instant::now());
+    }
+    (
+        [(
+            header::SET_COOKIE,
+            auth.cookie(LOGIN_COOKIE, &state, LOGIN_TIMEOUT),
+        )],
+        Redirect::to(&auth.oauth.authorize_url(&state)),
+    )
+}
+
+#[derive(Deserialize)]
+struct Callback {
+    code: Option<String>,
+    state: String,
+}
+
+async fn callback(
+    State(auth): State<Arc<Auth>>,
+    headers: HeaderMap,
+    Query(query): Query<Callback>,
+) -> Response {
+    // otherwise anyone could send a link that logs whoever opens it in as themselves
+    if read_cookie(&headers, LOGIN_COOKIE) != Some(query.state.as_str()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "That login was started somewhere else, try again",
+        )
+            .into_response();
+    }
+    let started = auth.pending.lock().await.remove(&query.state);
+    if started.is_none_or(|started| started.elapsed() >= LOGIN_TIMEOUT) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "That login link expired, try again",
+        )
+            .into_response();
+    }
+    // no code means they said no
+    let Some(code) = query.code else {
+        return Redirect::to("/").into_response();
+    };
+
+    let session = match auth.oauth.login(&code).await {
+        Ok(session) => session,
+        Err(e) => {
+            warn!("Dashboard login failed: {}", e);
+            return (StatusCode::BAD_GATEWAY, "Couldn't log in with Discord").into_response();
+        }
+    };
+
+    let id = random_id();
+    {
+        let mut sessions = auth.sessions.lock().await;
+        sessions.retain(|_, s| s.expires > Instant::now());
+        sessions.insert(id.clone(), session);
+    }
+    (
+        AppendHeaders([
+            (
+                header::SET_COOKIE,
+                auth.cookie(COOKIE, &id, SESSION_LIFETIME),
+            ),
+            (
+                header::SET_COOKIE,
+                auth.cookie(LOGIN_COOKIE, "", Duration::ZERO),
+            ),
+        ]),
+        Redirect::to("/"),
+    )
+        .into_response()
+}
+
+async fn logout(State(auth): State<Arc<Auth>>, headers: HeaderMap) -> impl IntoResponse {
+    if let Some(id) = read_cookie(&headers, COOKIE) {
+        auth.sessions.lock().await.remove(id);
+    }
+    (
+        [(header::SET_COOKIE, auth.cookie(COOKIE, "", Duration::ZERO))],
+        Redirect::to("/"),
+    )
+}
+
+#[derive(Serialize)]
+struct Me {
+    id: UserId,
+    name: String,
+}
+
+async fn me(State(auth): State<Arc<Auth>>, headers: HeaderMap) -> Result<Json<Me>, StatusCode> {
+    let session = auth
+        .session(&headers)
+        .await
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    Ok(Json(Me {
+        id: session.user_id,
+        name: session.name,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{extract::Form, http::HeaderValue};
+    use reqwest::redirect::Policy;
+
+    use super::*;
+
+    async fn spawn(router: Router) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        addr
+    }
+
+    /// Pretends to be Discord, handing out a token for one code and answering for one user.
+    async fn stand_in_discord() -> SocketAddr {
+        async fn token(Form(form): Form<HashMap<String, String>>) -> Response {
+            if form.get("code").map(String::as_str) == Some("good-code")
+                && form.get("client_secret").map(String::as_str) == Some("secret")
+            {
+                Json(serde_json::json!({ "access_token": "access", "token_type": "Bearer" }))
+                    .into_response()
+            } else {
+                StatusCode::BAD_REQUEST.into_response()
+            }
+        }
+        fn authorized(headers: &HeaderMap) -> bool {
+            headers.get(header::AUTHORIZATION) == Some(&HeaderValue::from_static("Bearer access"))
+        }
+        async fn user(headers: HeaderMap) -> Response {
+            if !authorized(&headers) {
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+            Json(serde_json::json!({ "id": "42", "username": "ferris", "global_name": "Ferris" }))
+                .into_response()
+        }
+        async fn guilds(headers: HeaderMap) -> Response {
+            if !authorized(&headers) {
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+            Json(serde_json::json!([{ "id": "7", "name": "Crab Rave" }])).into_response()
+        }
+
+        spawn(
+            Router::new()
+                .route("/api/oauth2/token", post(token))
+                .route("/api/v10/users/@me", get(user))
+                .route("/api/v10/users/@me/guilds", get(guilds)),
+        )
+        .await
+    }
+
+    async fn dashboard() -> (Arc<Auth>, String, reqwest::Client) {
+        let discord = stand_in_discord().await;
+        let oauth = OAuth {
+            client_id: "client".to_owned(),
+            client_secret: "secret".to_owned(),
+            redirect_uri: "http://dashboard/auth/callback".to_owned(),
+            discord_url: format!("http://{}", discord),
+            http: reqwest::Client::new(),
+        };
+        let auth = Arc::new(Auth::new(oauth, false));
+        let addr = spawn(router(auth.clone())).await;
+        let client = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .build()
+            .unwrap();
+        (auth, format!("http://{}", addr), client)
+    }
+
+    /// Follows the login redirect and returns the state it asked Discord to send back, and the
+    /// cookie that has to come back with it.
+    async fn start_login(base: &str, client: &reqwest::Client) -> (String, String) {
+        let response = client
+            .get(format!("{}/auth/login", base))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_redirection());
+        let location = response.headers()["location"].to_str().unwrap();
+        assert!(location.contains("/oauth2/authorize?"), "{}", location);
+        assert!(location.contains("scope=identify%20guilds"), "{}", location);
+        let url = reqwest::Url::parse(location).unwrap();
+        let state = url
+            .query_pairs()
+            .find(|(k, _)| k == "state")
+            .map(|(_, v)| v.into_owned())
+            .unwrap();
+
+        let cookie = response.headers()["set-cookie"].to_str().unwrap();
+        assert!(cookie.contains("HttpOnly"));
+        let cookie = cookie.split(';').next().unwrap().to_owned();
+        assert_eq!(cookie, format!("{}={}", LOGIN_COOKIE, state));
+        (state, cookie)
+    }
+
+    #[tokio::test]
+    async fn logs_in_through_discord() {
+        let (auth, base, client) = dashboard().await;
+        let (state, login_cookie) = start_login(&base, &client).await;
+
+        let response = client
+            .get(format!(
+                "{}/auth/callback?code=good-code&state={}",
+                base, state
+            ))
+            .header("cookie", &login_cookie)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_redirection());
+        let cookies: Vec<_> = response
+            .headers()
+            .get_all("set-cookie")
+            .iter()
+            .map(|c| c.to_str().unwrap())
+            .collect();
+        let cookie = cookies
+            .iter()
+            .find(|c| c.starts_with(COOKIE))
+            .unwrap()
+            .to_owned();
+        assert!(cookie.contains("HttpOnly"));
+        // the login's done with, so its cookie goes
+        assert!(cookies
+            .iter()
+            .any(|c| c.starts_with(&format!("{}=;", LOGIN_COOKIE)) && c.contains("Max-Age=0")));
+        let cookie = cookie.split(';').next().unwrap().to_owned();
+
+        let me: serde_json::Value = client
+            .get(format!("{}/api/me", base))
+            .header("cookie", &cookie)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(me["name"], "Ferris");
+        assert_eq!(me["id"], "42");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, cookie.parse().unwrap());
+        let session = auth.session(&headers).await.unwrap();
+        assert!(session.guilds.contains(&GuildId::new(7)));
+
+        client
+            .post(format!("{}/auth/logout", base))
+            .header("cookie", &cookie)
+            .send()
+            .await
+            .unwrap();
+        assert!(auth.session(&headers).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_state_and_bad_codes() {
+        let (_, base, client) = dashboard().await;
+
+        let response = client
+            .get(format!(
+                "{}/auth/callback?code=good-code&state=made-up",
+                base
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), StatusCode::BAD_REQUEST.as_u16());
+
+        let (state, cookie) = start_login(&base, &client).await;
+        let response = client
+            .get(format!(
+                "{}/auth/callback?code=bad-code&state={}",
+                base, state
+            ))
+            .header("cookie", &cookie)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), StatusCode::BAD_GATEWAY.as_u16());
+
+        // a state only works once
+        let response = client
+            .get(format!(
+                "{}/auth/callback?code=good-code&state={}",
+                base, state
+            ))
+            .header("cookie", &cookie)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), StatusCode::BAD_REQUEST.as_u16());
+    }
+
+    #[tokio::test]
+    async fn only_finishes_logins_in_the_browser_that_started_them() {
+        let (auth, base, client) = dashboard().await;
+        // someone starts a login and sends their callback link to somebody else
+        let (state, _) = start_login(&base, &client).await;
+
+        for cookie in [None, Some(format!("{}=other-state", LOGIN_COOKIE))] {
+            let mut request = client.get(format!(
+                "{}/auth/callback?code=good-code&state={}",
+                base, state
+            ));
+            if let Some(cookie) = cookie {
+                request = request.header("cookie", cookie);
+            }
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status().as_u16(), StatusCode::BAD_REQUEST.as_u16());
+            assert!(response.headers().get("set-cookie").is_none());
+        }
+        assert!(auth.sessions.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn needs_a_session() {
+        let (_, base, client) = dashboard().await;
+        let response = client.get(format!("{}/api/me", base)).send().await.unwrap();
+        assert_eq!(
+            response.status().as_u16(),
+            StatusCode::UNAUTHORIZED.as_u16()
+        );
+
+        let response = client
+            .get(format!("{}/api/me", base))
+            .header("cookie", "marine_session=forged")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status().as_u16(),
+            StatusCode::UNAUTHORIZED.as_u16()
+        );
+    }
+}
@@ -0,0 +1,693 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use once_cell::sync::OnceCell;
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::all::{Cache, GuildId, Http, HttpError, Member};
+use songbird::tracks::{PlayMode, TrackHandle};
+
+use crate::{
+    config::Config,
+    err::{AppError, ErrorKind},
+    helpers::get_http_client,
+    voice::{
+        controls::{self, check_control, check_dj, Control},
+        history::Played,
+        metadata::{title_of, Metadata, RequestedBy, Requester},
+        play, queue,
+        source::TrackSource,
+        volume::set_volume,
+    },
+    Data,
+};
+
+use self::auth::{Auth, OAuth, Session};
+
+pub mod auth;
+
+/// How often a connected browser is sent the guild's state, if it changed.
+const PUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The web dashboard: a page for watching and controlling each server's queue, and the JSON and
+/// WebSocket API behind it. Everything it changes goes through the same queue operations as the
+/// commands do.
+pub struct Dashboard {
+    auth: Arc<Auth>,
+    data: Arc<Data>,
+    /// Where the dashboard is served from, to check WebSocket origins against.
+    origin: String,
+    /// Handed over once the bot has connected.
+    bot: OnceCell<serenity::Context>,
+    /// The bot's cache and Discord's API, which is all deciding who gets in takes.
+    discord: OnceCell<(Arc<Cache>, Arc<Http>)>,
+}
+
+impl Dashboard {
+    /// The dashboard, if it's been configured.
+    pub fn new(config: &Config, data: Arc<Data>) -> Option<Arc<Self>> {
+        let dashboard = &config.dashboard;
+        let client_id = dashboard.client_id.clone()?;
+        let public_url = dashboard.public_url.trim_end_matches('/');
+
+        let oauth = OAuth {
+            client_id,
+            client_secret: dashboard.client_secret.clone(),
+            redirect_uri: format!("{}/auth/callback", public_url),
+            discord_url: dashboard.discord_url.trim_end_matches('/').to_owned(),
+            http: reqwest::Client::new(),
+        };
+        let origin = reqwest::Url::parse(public_url)
+            .map(|url| url.origin().ascii_serialization())
+            .unwrap_or_default();
+
+        Some(Arc::new(Self {
+            auth: Arc::new(Auth::new(oauth, public_url.starts_with("https://"))),
+            data,
+            origin,
+            bot: OnceCell::new(),
+            discord: OnceCell::new(),
+        }))
+    }
+
+    pub fn set_context(&self, ctx: serenity::Context) {
+        let _ = self.discord.set((ctx.cache.clone(), ctx.http.clone()));
+        let _ = self.bot.set(ctx);
+    }
+
+    pub fn router(self: &Arc<Self>) -> Router {
+        let api = Router::new()
+            .route("/", get(index))
+            .route("/api/guilds", get(guilds))
+            .route("/api/guilds/:guild", get(guild))
+            .route("/api/guilds/:guild/ws", get(live))
+            .route("/api/guilds/:guild/queue", post(add))
+            .route("/api/guilds/:guild/queue/move", post(move_track))
+            .route("/api/guilds/:guild/queue/:index", delete(remove))
+            .route("/api/guilds/:guild/control/:control", post(control))
+            .route("/api/guilds/:guild/volume", put(volume))
+            .with_state(self.clone());
+        auth::router(self.auth.clone()).merge(api)
+    }
+
+    /// The logged in user and the bot, as long as both of them are in the guild.
+    async fn member(
+        &self,
+        headers: &HeaderMap,
+        guild_id: GuildId,
+    ) -> Result<(Session, &serenity::Context), ApiError> {
+        let (session, _) = self.access(headers, guild_id).await?;
+        Ok((session, self.bot()?))
+    }
+
+    /// Like [`Self::member`], but only for people allowed to control playback.
+    async fn controller(
+        &self,
+        headers: &HeaderMap,
+        guild_id: GuildId,
+    ) -> Result<(Session, &serenity::Context), ApiError> {
+        let (session, member) = self.access(headers, guild_id).await?;
+        if let Some(role) = self.data.settings.get(guild_id).await.dj_role {
+            check_dj(&self.discord()?.0, guild_id, &member, role)?;
+            return Ok((session, self.bot()?));
+        }
+        let ctx = self.bot()?;
+        check_control(ctx, &self.data, guild_id, session.user_id).await?;
+        Ok((session, ctx))
+    }
+
+    /// The logged in user and their membership of the guild. The servers listed at login can be
+    /// days old, so Discord is asked again whether they're still in this one.
+    async fn access(
+        &self,
+        headers: &HeaderMap,
+        guild_id: GuildId,
+    ) -> Result<(Session, Member), ApiError> {
+        let session = self.auth.session(headers).await.ok_or(ApiError(
+            StatusCode::UNAUTHORIZED,
+            "Log in first".to_owned(),
+        ))?;
+        let (cache, http) = self.discord()?;
+        let not_found = || {
+            ApiError(
+                StatusCode::NOT_FOUND,
+                "I'm not in that server with you".to_owned(),
+            )
+        };
+        if !session.guilds.contains(&guild_id) || cache.guild(guild_id).is_none() {
+            return Err(not_found());
+        }
+        match http.get_member(guild_id, session.user_id).await {
+            Ok(member) => Ok((session, member)),
+            Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(response)))
+                if response.status_code.as_u16() == 404 =>
+            {
+                Err(not_found())
+            }
+            Err(e) => Err(AppError::from(e).into()),
+        }
+    }
+
+    fn discord(&self) -> Result<&(Arc<Cache>, Arc<Http>), ApiError> {
+        self.discord.get().ok_or(ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "I'm still connecting to Discord".to_owned(),
+        ))
+    }
+
+    fn bot(&self) -> Result<&serenity::Context, ApiError> {
+        self.bot.get().ok_or(ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "I'm still connecting to Discord".to_owned(),
+        ))
+    }
+
+    async fn view(&self, ctx: &serenity::Context, guild_id: GuildId) -> GuildView {
+        let manager = songbird::get(ctx)
+            .await
+            .expect("Songbird Voice client placed in at initialisation.")
+            .clone();
+        let settings = self.data.settings.get(guild_id).await;
+        let mut view = GuildView {
+            connected: false,
+            paused: false,
+            position: 0.0,
+            volume: settings.default_volume,
+            normalize: settings.normalize,
+            current: None,
+            queue: Vec::new(),
+            history: self.data.history.recent(guild_id).await,
+        };
+
+        let Some(call) = manager.get(guild_id) else {
+            return view;
+        };
+        view.connected = true;
+        let tracks = call.lock().await.queue().current_queue();
+        let mut tracks = tracks.iter();
+        if let Some(current) = tracks.next() {
+            if let Ok(info) = current.get_info().await {
+                view.paused = info.playing == PlayMode::Pause;
+                view.position = info.position.as_secs_f64();
+            }
+            view.current = Some(TrackView::of(current).await);
+        }
+        for track in tracks {
+            view.queue.push(TrackView::of(track).await);
+        }
+        view
+    }
+}
+
+/// Something the API couldn't do, sent back as `{"error": "..."}`.
+struct ApiError(StatusCode, String);
+
+impl From<AppError> for ApiError {
+    fn from(e: AppError) -> Self {
+        let status = match e.kind() {
+            ErrorKind::NotInVoice => StatusCode::CONFLICT,
+            ErrorKind::InvalidValue(_) | ErrorKind::SourceResolution(_) => StatusCode::BAD_REQUEST,
+            ErrorKind::PermissionDenied(_) => StatusCode::FORBIDDEN,
+            ErrorKind::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorKind::Catalog(_) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if e.is_internal() {
+            tracing::error!(
+                correlation_id = e.id(),
+                "Dashboard request failed: {}",
+                e.chain()
+            );
+        }
+        Self(status, e.message())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// Everything the dashboard shows about a guild.
+#[derive(Serialize)]
+struct GuildView {
+    /// Whether the bot is in a voice call there.
+    connected: bool,
+    paused: bool,
+    /// Seconds into the current track.
+    position: f64,
+    volume: u16,
+    normalize: bool,
+    current: Option<TrackView>,
+    /// Upcoming tracks, the first of which is at index 1 of the queue.
+    queue: Vec<TrackView>,
+    history: Vec<Played>,
+}
+
+#[derive(Serialize)]
+struct TrackView {
+    title: Option<String>,
+    url: Option<String>,
+    thumbnail: Option<String>,
+    /// In seconds.
+    duration: Option<f64>,
+    requester: Option<Requester>,
+}
+
+impl TrackView {
+    async fn of(track: &TrackHandle) -> Self {
+        let typemap = track.typemap().read().await;
+        let metadata = typemap.get::<Metadata>();
+        Self {
+            title: title_of(&typemap),
+            url: metadata.and_then(|m| m.source_url.clone()),
+            thumbnail: metadata.and_then(|m| m.thumbnail.clone()),
+            duration: metadata.and_then(|m| m.duration).map(|d| d.as_secs_f64()),
+            requester: typemap.get::<RequestedBy>().cloned(),
+        }
+    }
+}
+
+async fn index() -> Html<&'static str> {
+    Html(include_str!("index.html"))
+}
+
+#[derive(Serialize)]
+struct GuildSummary {
+    id: GuildId,
+    name: String,
+    icon: Option<String>,
+}
+
+async fn guilds(
+    State(dashboard): State<Arc<Dashboard>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<GuildSummary>>, ApiError> {
+    let session = dashboard.auth.session(&headers).await.ok_or(ApiError(
+        StatusCode::UNAUTHORIZED,
+        "Log in first".to_owned(),
+    ))?;
+    let ctx = dashboard.bot()?;
+
+    let mut guilds: Vec<_> = session
+        .guilds
+        .iter()
+        .filter_map(|id| {
+            let guild = ctx.cache.guild(*id)?;
+            Some(GuildSummary {
+                id: *id,
+                name: guild.name.clone(),
+                icon: guild.icon_url(),
+            })
+        })
+        .collect();
+    guilds.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Json(guilds))
+}
+
+async fn guild(
+    State(dashboard): State<Arc<Dashboard>>,
+    Path(guild_id): Path<GuildId>,
+    headers: HeaderMap,
+) -> Result<Json<GuildView>, ApiError> {
+    let (_, ctx) = dashboard.member(&headers, guild_id).await?;
+    Ok(Json(dashboard.view(ctx, guild_id).await))
+}
+
+/// Pushes the guild's state whenever it changes, until the browser goes away.
+async fn live(
+    State(dashboard): State<Arc<Dashboard>>,
+    Path(guild_id): Path<GuildId>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    // cookies go along with cross-site WebSocket requests, so check they're from our own page
+    let origin = headers.get(header::ORIGIN).and_then(|o| o.to_str().ok());
+    if origin.is_some_and(|origin| origin != dashboard.origin) {
+        return Err(ApiError(StatusCode::FORBIDDEN, "Wrong origin".to_owned()));
+    }
+    dashboard.member(&headers, guild_id).await?;
+
+    Ok(upgrade.on_upgrade(move |socket| push(dashboard, guild_id, socket)))
+}
+
+async fn push(dashboard: Arc<Dashboard>, guild_id: GuildId, mut socket: WebSocket) {
+    let Ok(ctx) = dashboard.bot() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(PUSH_INTERVAL);
+    let mut last = String::new();
+    loop {
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                _ => {}
+            },
+            _ = interval.tick() => {
+                let view = dashboard.view(ctx, guild_id).await;
+                let Ok(json) = serde_json::to_string(&view) else {
+                    return;
+                };
+                if json != last {
+                    if socket.send(Message::Text(json.clone())).await.is_err() {
+                        return;
+                    }
+                    last = json;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AddTrack {
+    url: String,
+}
+
+async fn add(
+    State(dashboard): State<Arc<Dashboard>>,
+    Path(guild_id): Path<GuildId>,
+    headers: HeaderMap,
+    Json(body): Json<AddTrack>,
+) -> Result<Json<GuildView>, ApiError> {
+    let (session, ctx) = dashboard.member(&headers, guild_id).await?;
+    let data = &dashboard.data;
+
+    let source = TrackSource::parse(body.url.trim(), data.config.media_dir.as_deref())
+        .map_err(ErrorKind::SourceResolution)
+        .map_err(AppError::from)?;
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    // joining needs someone to be in a voice channel on Discord, so leave that to /play
+    let call = manager
+        .get(guild_id)
+        .ok_or_else(|| AppError::from(ErrorKind::NotInVoice))?;
+
+    let requester = Requester {
+        id: session.user_id,
+        name: session.name,
+    };
+    let http = get_http_client(ctx).await;
+    play::add(data, http, guild_id, &call, source, requester).await?;
+    data.now_playing.refresh(guild_id).await;
+
+    Ok(Json(dashboard.view(ctx, guild_id).await))
+}
+
+async fn queue_of(
+    ctx: &serenity::Context,
+    guild_id: GuildId,
+) -> Result<songbird::tracks::TrackQueue, AppError> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let call = manager.get(guild_id).ok_or(ErrorKind::NotInVoice)?;
+    let queue = call.lock().await.queue().clone();
+    Ok(queue)
+}
+
+async fn remove(
+    State(dashboard): State<Arc<Dashboard>>,
+    Path((guild_id, index)): Path<(GuildId, usize)>,
+    headers: HeaderMap,
+) -> Result<Json<GuildView>, ApiError> {
+    let (_, ctx) = dashboard.controller(&headers, guild_id).await?;
+    queue::remove_at(&queue_of(ctx, guild_id).await?, index)?;
+    dashboard.data.now_playing.refresh(guild_id).await;
+    Ok(Json(dashboard.view(ctx, guild_id).await))
+}
+
+#[derive(Deserialize)]
+struct MoveTrack {
+    from: usize,
+    to: usize,
+}
+
+async fn move_track(
+    State(dashboard): State<Arc<Dashboard>>,
+    Path(guild_id): Path<GuildId>,
+    headers: HeaderMap,
+    Json(body): Json<MoveTrack>,
+) -> Result<Json<GuildView>, ApiError> {
+    let (_, ctx) = dashboard.controller(&headers, guild_id).await?;
+    queue::move_track(&queue_of(ctx, guild_id).await?, body.from, body.to)?;
+    dashboard.data.now_playing.refresh(guild_id).await;
+    Ok(Json(dashboard.view(ctx, guild_id).await))
+}
+
+async fn control(
+    State(dashboard): State<Arc<Dashboard>>,
+    Path((guild_id, name)): Path<(GuildId, String)>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let control = Control::from_custom_id(&format!("np:{}", name)).ok_or(ApiError(
+        StatusCode::NOT_FOUND,
+        format!("There's no {} control", name),
+    ))?;
+    let (_, ctx) = dashboard.controller(&headers, guild_id).await?;
+
+    let result = controls::apply(&queue_of(ctx, guild_id).await?, control).await;
+    dashboard.data.now_playing.refresh(guild_id).await;
+    Ok(Json(serde_json::json!({ "result": result })))
+}
+
+#[derive(Deserialize)]
+struct Volume {
+    level: Option<u16>,
+    normalize: Option<bool>,
+}
+
+async fn volume(
+    State(dashboard): State<Arc<Dashboard>>,
+    Path(guild_id): Path<GuildId>,
+    headers: HeaderMap,
+    Json(body): Json<Volume>,
+) -> Result<Json<GuildView>, ApiError> {
+    let (_, ctx) = dashboard.controller(&headers, guild_id).await?;
+    if body.level.is_some_and(|level| level > 200) {
+        return Err(AppError::from(ErrorKind::InvalidValue(
+            "volume has to be between 0 and 200".to_owned(),
+        ))
+        .into());
+    }
+
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let http = get_http_client(ctx).await;
+    set_volume(
+        &dashboard.data,
+        &manager,
+        &http,
+        guild_id,
+        body.level,
+        body.normalize,
+    )
+    .await?;
+    Ok(Json(dashboard.view(ctx, guild_id).await))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, net::SocketAddr};
+
+    use ::serenity::all::{GuildCreateEvent, HttpBuilder, RoleId};
+    use axum::extract::Form;
+    use reqwest::redirect::Policy;
+
+    use crate::config::DashboardConfig;
+
+    use super::*;
+
+    async fn spawn(router: Router) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        addr
+    }
+
+    /// Pretends to be Discord for user 42, who was in servers 7 and 8 when they logged in but
+    /// has since left 8.
+    async fn stand_in_discord() -> SocketAddr {
+        async fn token(Form(form): Form<HashMap<String, String>>) -> Response {
+            if form.get("code").map(String::as_str) == Some("good-code") {
+                Json(serde_json::json!({ "access_token": "access", "token_type": "Bearer" }))
+                    .into_response()
+            } else {
+                StatusCode::BAD_REQUEST.into_response()
+            }
+        }
+        async fn user() -> Json<serde_json::Value> {
+            Json(serde_json::json!({ "id": "42", "username": "ferris", "global_name": "Ferris" }))
+        }
+        async fn guilds() -> Json<serde_json::Value> {
+            Json(serde_json::json!([{ "id": "7" }, { "id": "8" }]))
+        }
+        async fn member(Path((guild, user)): Path<(u64, u64)>) -> Response {
+            if (guild, user) != (7, 42) {
+                let error = serde_json::json!({ "message": "Unknown Member", "code": 10007 });
+                return (StatusCode::NOT_FOUND, Json(error)).into_response();
+            }
+            Json(serde_json::json!({
+                "user": { "id": "42", "username": "ferris", "discriminator": "0", "avatar": null },
+                "roles": [],
+                "joined_at": "2024-01-01T00:00:00Z",
+                "deaf": false,
+                "mute": false,
+                "flags": 0,
+            }))
+            .into_response()
+        }
+
+        spawn(
+            Router::new()
+                .route("/api/oauth2/token", post(token))
+                .route("/api/v10/users/@me", get(user))
+                .route("/api/v10/users/@me/guilds", get(guilds))
+                .route("/api/v10/guilds/:guild/members/:user", get(member)),
+        )
+        .await
+    }
+
+    /// A server the bot is in, as it'd arrive from the gateway.
+    fn guild(id: u64) -> GuildCreateEvent {
+        serde_json::from_value(serde_json::json!({
+            "id": id.to_string(), "name": "Crab Rave", "owner_id": "1", "afk_timeout": 300,
+            "verification_level": 0, "default_message_notifications": 0,
+            "explicit_content_filter": 0, "mfa_level": 0, "premium_tier": 0, "nsfw_level": 0,
+            "system_channel_flags": 0, "premium_progress_bar_enabled": false,
+            "preferred_locale": "en-US", "joined_at": "2024-01-01T00:00:00Z", "large": false,
+            "member_count": 1, "roles": [], "emojis": [], "stickers": [], "features": [],
+            "members": [], "channels": [], "voice_states": [], "presences": [], "threads": [],
+            "stage_instances": [], "guild_scheduled_events": [],
+        }))
+        .unwrap()
+    }
+
+    /// The dashboard, talking to the stand-in and already in servers 7 and 8.
+    async fn dashboard(name: &str) -> (Arc<Dashboard>, String, reqwest::Client) {
+        let discord = format!("http://{}", stand_in_discord().await);
+        let config = Config {
+            data_dir: std::env::temp_dir().join(format!("marine-{}-{}", name, std::process::id())),
+            dashboard: DashboardConfig {
+                client_id: Some("client".to_owned()),
+                client_secret: "secret".to_owned(),
+                discord_url: discord.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let _ = std::fs::remove_dir_all(&config.data_dir);
+        let config: &'static Config = Box::leak(Box::new(config));
+
+        let dashboard = Dashboard::new(config, Arc::new(Data::open(config))).unwrap();
+        let cache = Arc::new(Cache::new());
+        cache.update(&mut guild(7));
+        cache.update(&mut guild(8));
+        let http = HttpBuilder::new("token")
+            .proxy(discord)
+            .ratelimiter_disabled(true)
+            .build();
+        let _ = dashboard.discord.set((cache, Arc::new(http)));
+
+        let addr = spawn(dashboard.router()).await;
+        let client = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .build()
+            .unwrap();
+        (dashboard, format!("http://{}", addr), client)
+    }
+
+    /// Goes through the login and returns the session cookie.
+    async fn log_in(base: &str, client: &reqwest::Client) -> String {
+        let response = client
+            .get(format!("{}/auth/login", base))
+            .send()
+            .await
+            .unwrap();
+        let login = response.headers()["set-cookie"].to_str().unwrap();
+        let login = login.split(';').next().unwrap().to_owned();
+        let state = login.split_once('=').unwrap().1;
+
+        let response = client
+            .get(format!(
+                "{}/auth/callback?code=good-code&state={}",
+                base, state
+            ))
+            .header("cookie", &login)
+            .send()
+            .await
+            .unwrap();
+        let session = response
+            .headers()
+            .get_all("set-cookie")
+            .iter()
+            .map(|c| c.to_str().unwrap())
+            .find(|c| c.starts_with("marine_session="))
+            .unwrap();
+        session.split(';').next().unwrap().to_owned()
+    }
+
+    #[tokio::test]
+    async fn asks_for_a_login_first() {
+        let (_, base, client) = dashboard("dashboard-login").await;
+        let response = client
+            .get(format!("{}/api/guilds/7", base))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn hides_servers_theyre_no_longer_in() {
+        let (_, base, client) = dashboard("dashboard-left").await;
+        let cookie = log_in(&base, &client).await;
+        // 8 was in the list at login, but Discord says they've left since
+        for guild in [8, 9] {
+            let response = client
+                .post(format!("{}/api/guilds/{}/queue", base, guild))
+                .header("cookie", &cookie)
+                .json(&serde_json::json!({ "url": "https://example.com/a.mp3" }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 404, "guild {}", guild);
+        }
+    }
+
+    #[tokio::test]
+    async fn only_djs_control_playback() {
+        let (dashboard, base, client) = dashboard("dashboard-dj").await;
+        let cookie = log_in(&base, &client).await;
+        dashboard
+            .data
+            .settings
+            .update(GuildId::new(7), |s| s.dj_role = Some(RoleId::new(99)))
+            .await
+            .unwrap();
+
+        let response = client
+            .post(format!("{}/api/guilds/7/control/skip", base))
+            .header("cookie", &cookie)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        let _ = std::fs::remove_dir_all(&dashboard.data.config.data_dir);
+    }
+}
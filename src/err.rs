@@ -1,17 +1,255 @@
+use std::error::Error as _;
+
+use poise::CreateReply;
 use thiserror::Error;
 
+/// What went wrong, as far as the user needs to know.
 #[derive(Error, Debug)]
-#[allow(dead_code)]
-pub enum AppError {
+pub enum ErrorKind {
+    #[error("not in a voice channel")]
+    NotInVoice,
+    #[error("couldn't resolve the requested source")]
+    SourceResolution(#[source] anyhow::Error),
+    #[error("invalid value: {0}")]
+    InvalidValue(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("upstream catalog error")]
+    Catalog(#[source] anyhow::Error),
+    #[error("rate limited")]
+    RateLimited,
     // Generic internal error
     #[error("internal error")]
-    InternalError(#[from] anyhow::Error),
-    #[error("failed to authenticate")]
-    RequestError(#[from] reqwest::Error),
-    #[error("Unable to read file or start subprocess")]
-    UnableToRead(#[from] std::io::Error),
-    #[error("failed to serve image")]
-    NotFound,
-    #[error("Serenity error: {0}")]
-    Serenity(#[from] serenity::Error),
-}
\ No newline at end of file
+    Internal(#[source] anyhow::Error),
+    #[error("HTTP request failed")]
+    Request(#[source] reqwest::Error),
+    #[error("unable to read file or start subprocess")]
+    Io(#[source] std::io::Error),
+    #[error("Discord request failed")]
+    Serenity(#[source] serenity::Error),
+}
+
+/// An error from a command, tagged with an ID the user can quote back to us.
+#[derive(Error, Debug)]
+#[error("{kind} [{id}]")]
+pub struct AppError {
+    id: String,
+    #[source]
+    kind: Box<ErrorKind>,
+}
+
+impl AppError {
+    pub fn new(kind: ErrorKind) -> Self {
+        let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_owned();
+        Self {
+            id,
+            kind: Box::new(kind),
+        }
+    }
+
+    /// The correlation ID shown to the user and logged alongside the error.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Whether this is our fault rather than something the user can fix.
+    pub fn is_internal(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::Internal(_)
+                | ErrorKind::Request(_)
+                | ErrorKind::Io(_)
+                | ErrorKind::Serenity(_)
+        )
+    }
+
+    /// Every error in the chain, outermost first, for the logs.
+    pub fn chain(&self) -> String {
+        let mut chain = self.kind.to_string();
+        let mut source = self.kind.source();
+        while let Some(error) = source {
+            chain.push_str(": ");
+            chain.push_str(&error.to_string());
+            source = error.source();
+        }
+        chain
+    }
+
+    /// What we tell the user, without any internals.
+    pub fn user_message(&self) -> String {
+        format!("{}\n-# Error ID: `{}`", self.message(), self.id)
+    }
+
+    /// What went wrong in a sentence, without the error ID.
+    pub fn message(&self) -> String {
+        match self.kind.as_ref() {
+            ErrorKind::NotInVoice => {
+                "I'm not in a voice channel. Join one and try again.".to_owned()
+            }
+            // what yt-dlp or the site said is only for the logs
+            ErrorKind::SourceResolution(_) => {
+                "I couldn't play that. Check the link, or search for it instead.".to_owned()
+            }
+            ErrorKind::InvalidValue(why) => format!("That won't work, {}.", why),
+            ErrorKind::PermissionDenied(what) => format!("You're not allowed to {}.", what),
+            ErrorKind::Catalog(_) => {
+                "Apple Music isn't responding right now, try again in a bit.".to_owned()
+            }
+            ErrorKind::RateLimited => "Slow down! Try again in a moment.".to_owned(),
+            _ => "Something went wrong on our end.".to_owned(),
+        }
+    }
+
+    /// An ephemeral reply for the user who ran the command.
+    pub fn reply(&self) -> CreateReply {
+        CreateReply::default()
+            .content(self.user_message())
+            .ephemeral(true)
+    }
+}
+
+impl From<ErrorKind> for AppError {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(ErrorKind::Internal(e))
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS) {
+            return Self::new(ErrorKind::RateLimited);
+        }
+        Self::new(ErrorKind::Request(e))
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(ErrorKind::Io(e))
+    }
+}
+
+impl From<serenity::Error> for AppError {
+    fn from(e: serenity::Error) -> Self {
+        use serenity::{model::ModelError, Error};
+
+        match e {
+            Error::Model(ModelError::InvalidPermissions { required, present }) => {
+                Self::new(ErrorKind::PermissionDenied(format!(
+                    "do that while I'm missing {}",
+                    (required - present).get_permission_names().join(", ")
+                )))
+            }
+            e => Self::new(ErrorKind::Serenity(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_unique_and_shown_to_the_user() {
+        let a = AppError::from(ErrorKind::NotInVoice);
+        let b = AppError::from(ErrorKind::NotInVoice);
+
+        assert_eq!(a.id().len(), 8);
+        assert_ne!(a.id(), b.id());
+        assert!(a.user_message().contains(a.id()));
+    }
+
+    #[test]
+    fn maps_kinds_to_friendly_messages() {
+        let cases = [
+            (ErrorKind::NotInVoice, "not in a voice channel"),
+            (
+                ErrorKind::SourceResolution(anyhow::anyhow!("Invalid URL")),
+                "I couldn't play that.",
+            ),
+            (
+                ErrorKind::InvalidValue("the volume has to be a number".to_owned()),
+                "That won't work, the volume has to be a number.",
+            ),
+            (
+                ErrorKind::PermissionDenied("skip other people's tracks".to_owned()),
+                "not allowed to skip other people's tracks",
+            ),
+            (
+                ErrorKind::Catalog(anyhow::anyhow!("500 Internal Server Error")),
+                "Apple Music isn't responding",
+            ),
+            (ErrorKind::RateLimited, "Try again in a moment"),
+            (
+                ErrorKind::Internal(anyhow::anyhow!("database exploded")),
+                "went wrong on our end",
+            ),
+            (
+                ErrorKind::Io(std::io::Error::other("disk full")),
+                "went wrong on our end",
+            ),
+        ];
+
+        for (kind, expected) in cases {
+            let message = AppError::from(kind).user_message();
+            assert!(
+                message.contains(expected),
+                "{:?} doesn't contain {:?}",
+                message,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn internals_stay_out_of_user_messages() {
+        let error = AppError::from(anyhow::anyhow!("database exploded"));
+        assert!(!error.user_message().contains("database"));
+
+        let error = AppError::from(ErrorKind::SourceResolution(
+            anyhow::anyhow!("ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm you're not a bot")
+                .context("yt-dlp exited with 1"),
+        ));
+        assert!(!error.user_message().contains("yt-dlp"));
+        assert!(error.chain().contains("Sign in to confirm"));
+    }
+
+    #[test]
+    fn anyhow_errors_are_internal() {
+        let error = AppError::from(anyhow::anyhow!("oops"));
+        assert!(matches!(error.kind(), ErrorKind::Internal(_)));
+        assert!(error.is_internal());
+        assert!(!AppError::from(ErrorKind::NotInVoice).is_internal());
+    }
+
+    #[test]
+    fn chain_includes_every_source() {
+        let inner = anyhow::anyhow!("connection reset").context("fetching token");
+        let error = AppError::from(ErrorKind::Catalog(inner));
+        assert_eq!(
+            error.chain(),
+            "upstream catalog error: fetching token: connection reset"
+        );
+    }
+
+    #[test]
+    fn missing_permissions_are_permission_denied() {
+        use serenity::{all::Permissions, model::ModelError};
+
+        let error = AppError::from(serenity::Error::Model(ModelError::InvalidPermissions {
+            required: Permissions::SEND_MESSAGES,
+            present: Permissions::empty(),
+        }));
+        assert!(matches!(error.kind(), ErrorKind::PermissionDenied(_)));
+    }
+}
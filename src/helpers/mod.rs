@@ -3,7 +3,8 @@ use std::time::Duration;
 use reqwest::Client as HttpClient;
 use serenity::{all::Context, prelude::TypeMapKey};
 
-pub mod track_end;
+pub mod normalize;
+pub mod track_error;
 
 pub struct HttpKey;
 
@@ -27,18 +28,3 @@ pub fn s2hms(seconds: u64) -> String {
 pub fn d2hms(duration: Duration) -> String {
     s2hms(duration.as_secs())
 }
-
-/// Trims the artist from the title, if it's present.
-/// If the title is "Artist - Title", it will return "Title".
-/// If the title is "Title - Artist", it will return "Title".
-pub fn trim_artist_from_title(title: &str, artist: &str) -> String {
-    // find 'artist - '
-    if let Some(pos) = title.find((artist.to_owned() + " - ").as_str()) {
-        return title[pos + artist.len() + 3..].to_owned();
-    }
-    if let Some(pos) = title.find((" - ".to_string() + artist).as_str()) {
-        return title[pos + 3..].to_owned();
-    }
-
-    title.to_owned()
-}
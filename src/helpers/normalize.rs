@@ -0,0 +1,710 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use songbird::input::AuxMetadata;
+
+/// Words that describe an upload rather than the recording in it.
+const NOISE: &[&str] = &[
+    "official",
+    "officiel",
+    "oficial",
+    "music",
+    "video",
+    "audio",
+    "lyric",
+    "lyrics",
+    "visualizer",
+    "visualiser",
+    "hd",
+    "hq",
+    "4k",
+    "1080p",
+    "720p",
+    "mv",
+    "m/v",
+    "clip",
+    "explicit",
+    "clean",
+    "remaster",
+    "remastered",
+];
+
+static BRACKETED: Lazy<Regex> = Lazy::new(|| Regex::new(r"[(\[【]([^)\]】]*)[)\]】]").unwrap());
+/// Featured artists written into the title without brackets, up to the end of it.
+static FEATURING: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\s+(?:feat\.?|ft\.?|featuring)\s+(.+)$").unwrap());
+/// Featured artists inside brackets, where "with" is common too.
+static BRACKETED_FEATURING: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^(?:feat\.?|ft\.?|featuring|with)\s+(.+)$").unwrap());
+/// Between the artist and the title, and whatever else gets added.
+static DASH: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+[-–—~]\s+").unwrap());
+/// After the title, usually followed by the channel, a label or noise.
+static PIPE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s*(?:\||//)\s*").unwrap());
+/// A title in quotes after the artist, like `BTS 'Dynamite' Official MV`.
+static QUOTED: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"^(.+?)\s+['"‘“]([^'"’”]+)['"’”](.*)$"#).unwrap());
+static CHANNEL_SUFFIX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\s*(?:-\s*topic|official(?:\s+(?:youtube|artist|music))?(?:\s+channel)?|(?:youtube\s+)?channel)$")
+        .unwrap()
+});
+static VEVO: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s*(?:VEVO|Vevo)$").unwrap());
+static SPACES: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").unwrap());
+
+/// A track's title and artists, cleaned up from however it was uploaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalized {
+    pub title: String,
+    /// Who the track's by, without anyone featured on it.
+    pub artist: Option<String>,
+    pub featured: Vec<String>,
+}
+
+impl Normalized {
+    /// What to search a music catalog for.
+    pub fn query(&self) -> String {
+        match &self.artist {
+            Some(artist) => format!("{} {}", self.title, artist),
+            None => self.title.clone(),
+        }
+    }
+
+    /// Normalizes a queued track's title and artist. Sites that know the recording's own title
+    /// and artist, like YouTube Music, are trusted over the upload's title.
+    pub fn from_metadata(metadata: &AuxMetadata) -> Option<Self> {
+        if let (Some(track), Some(artist)) = (&metadata.track, &metadata.artist) {
+            let mut normalized = normalize(track, None);
+            let mut artists = split_artists(artist);
+            if !artists.is_empty() {
+                normalized.artist = Some(artists.remove(0));
+            }
+            for artist in artists {
+                if !normalized.featured.contains(&artist) {
+                    normalized.featured.push(artist);
+                }
+            }
+            return Some(normalized);
+        }
+
+        let title = metadata.title.as_deref()?;
+        let channel = metadata.artist.as_deref().or(metadata.channel.as_deref());
+        Some(normalize(title, channel))
+    }
+}
+
+/// Pulls the title, artist and featured artists out of a video's title and the channel that
+/// uploaded it.
+pub fn normalize(title: &str, channel: Option<&str>) -> Normalized {
+    let channel = channel.map(clean_channel).filter(|c| !c.is_empty());
+    let mut featured = Vec::new();
+
+    // anything after a pipe is almost never the title
+    let original = title;
+    let title = PIPE
+        .split(title)
+        .find(|segment| !is_noise(segment))
+        .unwrap_or(title);
+    let mut parts: Vec<String> = DASH
+        .split(title)
+        .map(|part| clean_part(part, &mut featured))
+        .filter(|part| !part.is_empty() && !is_noise(part))
+        .collect();
+
+    let (title, artist) = if parts.len() >= 2 {
+        // the channel says which side the artist's on, otherwise it's "Artist - Title"
+        let artist_at = channel
+            .as_deref()
+            .and_then(|channel| parts.iter().position(|p| same_artist(p, channel)))
+            .unwrap_or(0);
+        let artist = parts.remove(artist_at);
+        (parts.join(" - "), Some(artist))
+    } else {
+        let part = parts.pop().unwrap_or_default();
+        match QUOTED.captures(&part) {
+            Some(quoted) if is_noise(&quoted[3]) || quoted[3].trim().is_empty() => {
+                (quoted[2].to_owned(), Some(quoted[1].to_owned()))
+            }
+            _ => (part, channel.clone()),
+        }
+    };
+
+    let artist = artist.map(|artist| {
+        let mut artist = BRACKETED.replace_all(&artist, "").into_owned();
+        if let Some(found) = FEATURING.captures(&artist) {
+            featured.extend(split_artists(&found[1]));
+            artist = FEATURING.replace(&artist, "").into_owned();
+        }
+        tidy(&artist)
+    });
+    let title = tidy(&title);
+    featured.dedup();
+
+    Normalized {
+        // it was all noise, which is better than nothing
+        title: if title.is_empty() {
+            tidy(original)
+        } else {
+            title
+        },
+        artist: artist.filter(|a| !a.is_empty()),
+        featured,
+    }
+}
+
+/// Takes the featured artists and noise out of one part of a title, keeping anything else in
+/// brackets, like "(Live)" or "(Remix)".
+fn clean_part(part: &str, featured: &mut Vec<String>) -> String {
+    let part = BRACKETED.replace_all(part, |caps: &regex::Captures| {
+        let inside = caps[1].trim();
+        if let Some(found) = BRACKETED_FEATURING.captures(inside) {
+            if !is_noise(&found[1]) {
+                featured.extend(split_artists(&found[1]));
+            }
+            return String::new();
+        }
+        let kept: Vec<_> = inside
+            .split_whitespace()
+            .filter(|word| !NOISE.contains(&word.to_lowercase().as_str()))
+            .collect();
+        // a year left on its own is when it was uploaded or remastered
+        if kept.iter().all(|word| is_number(word)) {
+            String::new()
+        } else {
+            format!(" ({})", kept.join(" "))
+        }
+    });
+
+    let mut part = part.into_owned();
+    if let Some(found) = FEATURING.captures(&part) {
+        featured.extend(split_artists(&found[1]));
+        part = FEATURING.replace(&part, "").into_owned();
+    }
+    strip_trailing_noise(&tidy(&part))
+}
+
+/// Drops noise left at the end without brackets, like "Song Official Video".
+fn strip_trailing_noise(part: &str) -> String {
+    let words: Vec<_> = part.split(' ').collect();
+    let keep = words
+        .iter()
+        .rposition(|word| !NOISE.contains(&word.to_lowercase().as_str()))
+        .map_or(0, |last| last + 1);
+    // a lone "Music" or "Audio" at the end might be part of the title
+    let dropped = &words[keep..];
+    let strong = dropped.iter().any(|word| {
+        matches!(
+            word.to_lowercase().as_str(),
+            "video" | "lyrics" | "visualizer" | "visualiser" | "mv" | "m/v" | "official"
+        )
+    });
+    if keep == 0 || !strong {
+        part.to_owned()
+    } else {
+        words[..keep].join(" ")
+    }
+}
+
+/// Whether some text is nothing but noise, like "Official Video" or "Lyrics".
+fn is_noise(text: &str) -> bool {
+    let text = text.trim_matches(|c: char| c.is_whitespace() || "()[]【】-|".contains(c));
+    let is_noise_word = |word: &str| NOISE.contains(&word.to_lowercase().as_str());
+    text.split_whitespace().any(is_noise_word)
+        && text
+            .split_whitespace()
+            .all(|word| is_noise_word(word) || is_number(word))
+}
+
+fn is_number(word: &str) -> bool {
+    word.chars().all(|c| c.is_ascii_digit())
+}
+
+/// The artist's name from the channel that uploaded their video.
+pub fn clean_channel(channel: &str) -> String {
+    let channel = CHANNEL_SUFFIX.replace(channel.trim(), "");
+    if !VEVO.is_match(&channel) {
+        return tidy(&channel);
+    }
+    // VEVO channels are the artist's name with the spaces taken out
+    let name = VEVO.replace(&channel, "");
+    if name.contains(' ') {
+        tidy(&name)
+    } else {
+        tidy(&split_camel_case(&name))
+    }
+}
+
+/// Splits `RickAstley` into `Rick Astley`, leaving `ABBA` and `deadmau5` alone.
+fn split_camel_case(name: &str) -> String {
+    let mut split = String::with_capacity(name.len() + 4);
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_uppercase() && previous.is_some_and(|p| p.is_lowercase()) {
+            split.push(' ');
+        }
+        split.push(c);
+        previous = Some(c);
+    }
+    split
+}
+
+/// Splits "A, B & C" into each artist.
+pub fn split_artists(artists: &str) -> Vec<String> {
+    static SEPARATORS: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"(?i)\s*,\s*|\s+&\s+|\s+and\s+").unwrap());
+    SEPARATORS
+        .split(artists)
+        .map(tidy)
+        .filter(|a| !a.is_empty())
+        .collect()
+}
+
+/// Whether a part of a title names the same artist as the channel.
+fn same_artist(part: &str, channel: &str) -> bool {
+    let key = |s: &str| -> String {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let (part, channel) = (key(part), key(channel));
+    !part.is_empty() && (part == channel || (part.len() >= 3 && channel.starts_with(&part)))
+}
+
+/// Collapses whitespace and trims stray separators and quotes.
+fn tidy(text: &str) -> String {
+    let text = SPACES.replace_all(text.trim(), " ");
+    let text = text.trim_matches(|c: char| c.is_whitespace() || "-–—|:~,".contains(c));
+    let quotes = ['\'', '"', '‘', '’', '“', '”'];
+    let unquoted = text
+        .strip_prefix(quotes)
+        .and_then(|t| t.strip_suffix(quotes));
+    unquoted.unwrap_or(text).trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Title, channel, then the title, artist and featured artists that should come out.
+    type Case = (
+        &'static str,
+        Option<&'static str>,
+        &'static str,
+        Option<&'static str>,
+        &'static [&'static str],
+    );
+
+    const CASES: &[Case] = &[
+        // the usual "Artist - Title (noise)"
+        (
+            "Rick Astley - Never Gonna Give You Up (Official Music Video)",
+            Some("Rick Astley"),
+            "Never Gonna Give You Up",
+            Some("Rick Astley"),
+            &[],
+        ),
+        (
+            "Queen – Bohemian Rhapsody (Official Video Remastered)",
+            Some("Queen Official"),
+            "Bohemian Rhapsody",
+            Some("Queen"),
+            &[],
+        ),
+        (
+            "a-ha - Take On Me (Official Video) [4K]",
+            Some("a-ha"),
+            "Take On Me",
+            Some("a-ha"),
+            &[],
+        ),
+        (
+            "Daft Punk - Get Lucky (Official Audio) ft. Pharrell Williams, Nile Rodgers",
+            Some("Daft Punk"),
+            "Get Lucky",
+            Some("Daft Punk"),
+            &["Pharrell Williams", "Nile Rodgers"],
+        ),
+        (
+            "Coldplay - Yellow [Lyrics]",
+            Some("Lyrics Hub"),
+            "Yellow",
+            Some("Coldplay"),
+            &[],
+        ),
+        (
+            "Eminem - Lose Yourself [HD]",
+            Some("msvogue23"),
+            "Lose Yourself",
+            Some("Eminem"),
+            &[],
+        ),
+        (
+            "Fleetwood Mac - Dreams (2004 Remaster)",
+            Some("Fleetwood Mac"),
+            "Dreams",
+            Some("Fleetwood Mac"),
+            &[],
+        ),
+        (
+            "The Beatles - Here Comes The Sun (Remastered 2009)",
+            Some("The Beatles"),
+            "Here Comes The Sun",
+            Some("The Beatles"),
+            &[],
+        ),
+        (
+            "Billie Eilish - bad guy (Official Lyric Video)",
+            Some("Billie Eilish"),
+            "bad guy",
+            Some("Billie Eilish"),
+            &[],
+        ),
+        (
+            "Tame Impala - The Less I Know The Better (Visualizer)",
+            Some("Tame Impala"),
+            "The Less I Know The Better",
+            Some("Tame Impala"),
+            &[],
+        ),
+        (
+            "Kendrick Lamar - HUMBLE. (Explicit)",
+            Some("Kendrick Lamar"),
+            "HUMBLE.",
+            Some("Kendrick Lamar"),
+            &[],
+        ),
+        // featured artists, however they're written
+        (
+            "Mark Ronson - Uptown Funk (Official Video) ft. Bruno Mars",
+            Some("Mark Ronson"),
+            "Uptown Funk",
+            Some("Mark Ronson"),
+            &["Bruno Mars"],
+        ),
+        (
+            "Calvin Harris feat. Rihanna - This Is What You Came For",
+            Some("Calvin Harris"),
+            "This Is What You Came For",
+            Some("Calvin Harris"),
+            &["Rihanna"],
+        ),
+        (
+            "Gotye - Somebody That I Used To Know (feat. Kimbra)",
+            Some("gotyemusic"),
+            "Somebody That I Used To Know",
+            Some("Gotye"),
+            &["Kimbra"],
+        ),
+        (
+            "Post Malone - Sunflower [ft. Swae Lee] (Official Video)",
+            Some("Post Malone"),
+            "Sunflower",
+            Some("Post Malone"),
+            &["Swae Lee"],
+        ),
+        (
+            "Ed Sheeran - Perfect Symphony (with Andrea Bocelli)",
+            Some("Ed Sheeran"),
+            "Perfect Symphony",
+            Some("Ed Sheeran"),
+            &["Andrea Bocelli"],
+        ),
+        (
+            "DJ Khaled ft. Drake, Rick Ross & Lil Wayne - I'm On One",
+            Some("DJKhaledVEVO"),
+            "I'm On One",
+            Some("DJ Khaled"),
+            &["Drake", "Rick Ross", "Lil Wayne"],
+        ),
+        (
+            "Lizzo - Good as Hell (featuring Ariana Grande) [Remix]",
+            Some("Lizzo"),
+            "Good as Hell (Remix)",
+            Some("Lizzo"),
+            &["Ariana Grande"],
+        ),
+        (
+            "Shakira - Hips Don't Lie (Official 4K Video) FT. Wyclef Jean",
+            Some("shakiraVEVO"),
+            "Hips Don't Lie",
+            Some("Shakira"),
+            &["Wyclef Jean"],
+        ),
+        // Topic channels only have the title, the channel's the artist
+        (
+            "Bohemian Rhapsody",
+            Some("Queen - Topic"),
+            "Bohemian Rhapsody",
+            Some("Queen"),
+            &[],
+        ),
+        (
+            "Midnight City",
+            Some("M83 - Topic"),
+            "Midnight City",
+            Some("M83"),
+            &[],
+        ),
+        (
+            "Strobe (Radio Edit)",
+            Some("deadmau5 - Topic"),
+            "Strobe (Radio Edit)",
+            Some("deadmau5"),
+            &[],
+        ),
+        // VEVO uploads
+        (
+            "Rick Astley - Never Gonna Give You Up",
+            Some("RickAstleyVEVO"),
+            "Never Gonna Give You Up",
+            Some("Rick Astley"),
+            &[],
+        ),
+        (
+            "Shake It Off",
+            Some("TaylorSwiftVEVO"),
+            "Shake It Off",
+            Some("Taylor Swift"),
+            &[],
+        ),
+        (
+            "Dancing Queen",
+            Some("ABBAVEVO"),
+            "Dancing Queen",
+            Some("ABBA"),
+            &[],
+        ),
+        (
+            "Hello (Official Music Video)",
+            Some("AdeleVEVO"),
+            "Hello",
+            Some("Adele"),
+            &[],
+        ),
+        (
+            "Umbrella (Orange Version) (Official Music Video) ft. JAY-Z",
+            Some("RihannaVEVO"),
+            "Umbrella (Orange Version)",
+            Some("Rihanna"),
+            &["JAY-Z"],
+        ),
+        // the channel says which side the artist's on
+        (
+            "Blinding Lights - The Weeknd",
+            Some("The Weeknd"),
+            "Blinding Lights",
+            Some("The Weeknd"),
+            &[],
+        ),
+        (
+            "Levitating - Dua Lipa (Lyrics)",
+            Some("Dua Lipa Official"),
+            "Levitating",
+            Some("Dua Lipa"),
+            &[],
+        ),
+        // channels that aren't the artist
+        (
+            "Avicii - Levels",
+            Some("Ultra Records"),
+            "Levels",
+            Some("Avicii"),
+            &[],
+        ),
+        ("Darude - Sandstorm", None, "Sandstorm", Some("Darude"), &[]),
+        (
+            "Untitled Track",
+            Some("Some Label Official YouTube Channel"),
+            "Untitled Track",
+            Some("Some Label"),
+            &[],
+        ),
+        // noise without brackets, after separators and at the end
+        (
+            "Oasis - Wonderwall | Official Video",
+            Some("Oasis"),
+            "Wonderwall",
+            Some("Oasis"),
+            &[],
+        ),
+        (
+            "Linkin Park - Numb - Official Music Video",
+            Some("Linkin Park"),
+            "Numb",
+            Some("Linkin Park"),
+            &[],
+        ),
+        (
+            "Nirvana - Smells Like Teen Spirit Official Video",
+            Some("Nirvana"),
+            "Smells Like Teen Spirit",
+            Some("Nirvana"),
+            &[],
+        ),
+        (
+            "Radiohead - Creep // Lyrics",
+            Some("Radiohead"),
+            "Creep",
+            Some("Radiohead"),
+            &[],
+        ),
+        (
+            "Daft Punk - Around the World | Daft Punk",
+            Some("Daft Punk"),
+            "Around the World",
+            Some("Daft Punk"),
+            &[],
+        ),
+        // what's left in brackets that isn't noise stays
+        (
+            "Nirvana - Lithium (Live at Reading 1992)",
+            Some("Nirvana"),
+            "Lithium (Live at Reading 1992)",
+            Some("Nirvana"),
+            &[],
+        ),
+        (
+            "Adele - Someone Like You (Official Live Video)",
+            Some("Adele"),
+            "Someone Like You (Live)",
+            Some("Adele"),
+            &[],
+        ),
+        (
+            "Justice - D.A.N.C.E. (Part 2)",
+            Some("Justice"),
+            "D.A.N.C.E. (Part 2)",
+            Some("Justice"),
+            &[],
+        ),
+        (
+            "Prince - 1999 (Official Video)",
+            Some("Prince"),
+            "1999",
+            Some("Prince"),
+            &[],
+        ),
+        (
+            "Kraftwerk - The Model (2009 Remaster) [HQ]",
+            Some("Kraftwerk"),
+            "The Model",
+            Some("Kraftwerk"),
+            &[],
+        ),
+        (
+            "Pink Floyd - Money (2011)",
+            Some("Pink Floyd"),
+            "Money",
+            Some("Pink Floyd"),
+            &[],
+        ),
+        // titles in quotes
+        (
+            "BTS (방탄소년단) 'Dynamite' Official MV",
+            Some("HYBE LABELS"),
+            "Dynamite",
+            Some("BTS"),
+            &[],
+        ),
+        (
+            "BLACKPINK - 'How You Like That' M/V",
+            Some("BLACKPINK"),
+            "How You Like That",
+            Some("BLACKPINK"),
+            &[],
+        ),
+        (
+            "IU \"Blueming\" MV",
+            Some("1theK (원더케이)"),
+            "Blueming",
+            Some("IU"),
+            &[],
+        ),
+        // names that look like noise or separators
+        (
+            "Oasis - Don't Look Back In Anger",
+            Some("Oasis"),
+            "Don't Look Back In Anger",
+            Some("Oasis"),
+            &[],
+        ),
+        (
+            "Jay-Z - 99 Problems",
+            Some("JayZVEVO"),
+            "99 Problems",
+            Some("Jay-Z"),
+            &[],
+        ),
+        (
+            "Simon & Garfunkel - The Sound of Silence (Audio)",
+            Some("Simon & Garfunkel"),
+            "The Sound of Silence",
+            Some("Simon & Garfunkel"),
+            &[],
+        ),
+        (
+            "Massive Attack - Teardrop - Live",
+            Some("Massive Attack"),
+            "Teardrop - Live",
+            Some("Massive Attack"),
+            &[],
+        ),
+        // nothing to go on but noise
+        ("Official Video", None, "Official Video", None, &[]),
+        (
+            "  Song   with   spaces  ",
+            None,
+            "Song with spaces",
+            None,
+            &[],
+        ),
+    ];
+
+    #[test]
+    fn normalizes_titles() {
+        for (title, channel, want_title, want_artist, want_featured) in CASES {
+            let normalized = normalize(title, *channel);
+            assert_eq!(normalized.title, *want_title, "title of {:?}", title);
+            assert_eq!(
+                normalized.artist.as_deref(),
+                *want_artist,
+                "artist of {:?}",
+                title
+            );
+            assert_eq!(
+                normalized.featured, *want_featured,
+                "featured in {:?}",
+                title
+            );
+        }
+    }
+
+    #[test]
+    fn cleans_channel_names() {
+        for (channel, want) in [
+            ("Rick Astley - Topic", "Rick Astley"),
+            ("RickAstleyVEVO", "Rick Astley"),
+            ("ABBAVEVO", "ABBA"),
+            ("Arctic Monkeys Vevo", "Arctic Monkeys"),
+            ("Queen Official", "Queen"),
+            ("Dua Lipa Official Artist Channel", "Dua Lipa"),
+            ("deadmau5", "deadmau5"),
+            ("The Beatles", "The Beatles"),
+        ] {
+            assert_eq!(clean_channel(channel), want, "{:?}", channel);
+        }
+    }
+
+    #[test]
+    fn trusts_music_metadata() {
+        let metadata = AuxMetadata {
+            title: Some("Daft Punk - Get Lucky (Official Audio)".to_owned()),
+            track: Some("Get Lucky (feat. Pharrell Williams)".to_owned()),
+            artist: Some("Daft Punk, Nile Rodgers".to_owned()),
+            ..Default::default()
+        };
+        let normalized = Normalized::from_metadata(&metadata).unwrap();
+        assert_eq!(normalized.title, "Get Lucky");
+        assert_eq!(normalized.artist.as_deref(), Some("Daft Punk"));
+        assert_eq!(normalized.featured, ["Pharrell Williams", "Nile Rodgers"]);
+        assert_eq!(normalized.query(), "Get Lucky Daft Punk");
+    }
+}
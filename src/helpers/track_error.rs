@@ -0,0 +1,299 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::Client as HttpClient;
+use serenity::{
+    all::{ChannelId, CreateMessage, GuildId, Http, MessageBuilder},
+    async_trait,
+};
+use songbird::{
+    input::AudioStreamError,
+    tracks::{PlayError, PlayMode, Track, TrackHandle},
+    typemap::{TypeMap, TypeMapKey},
+    Event, EventContext, EventHandler as VoiceEventHandler, Songbird,
+};
+use tracing::warn;
+
+use crate::{
+    metrics::metrics,
+    voice::{
+        filters::source::FilteredInput,
+        loudness::TrackGain,
+        metadata::{title_of, AlbumId, Catalog, CoverArt, Metadata, RequestedBy},
+        prefetch::PrefetchUrl,
+        source::{resolve, OriginalSource, TrackSource},
+        volume::track_volume,
+    },
+    Data,
+};
+
+/// Failed tracks in a row before we give up on the queue entirely.
+const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+
+/// Marks a track that's already a retry of one that failed.
+struct Retried;
+
+impl TypeMapKey for Retried {
+    type Value = ();
+}
+
+/// Tells the channel when a track fails to play, retries it once and stops the queue
+/// if nothing will play.
+pub struct TrackErrorNotifier {
+    pub chan_id: ChannelId,
+    pub http: Arc<Http>,
+    pub client: HttpClient,
+    pub manager: Arc<Songbird>,
+    pub guild_id: GuildId,
+    pub data: Arc<Data>,
+    pub failures: Arc<AtomicU32>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for TrackErrorNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(&[(state, handle)]) = ctx else {
+            return None;
+        };
+        let PlayMode::Errored(error) = &state.playing else {
+            return None;
+        };
+
+        warn!(
+            "Track {} failed in {}: {}",
+            handle.uuid(),
+            self.guild_id,
+            error
+        );
+
+        let (title, source, retried) = {
+            let typemap = handle.typemap().read().await;
+            (
+                title_of(&typemap).unwrap_or("This track".to_owned()),
+                typemap.get::<OriginalSource>().cloned(),
+                typemap.contains_key::<Retried>(),
+            )
+        };
+        // yt-dlp runs when the track is created, so that's where it fails
+        if matches!(error, PlayError::Create(_))
+            && matches!(source, Some(TrackSource::YoutubeDl(_)))
+        {
+            metrics().ytdl_failed();
+        }
+
+        let mut msg = MessageBuilder::new();
+        msg.push_bold_safe(&title)
+            .push(" couldn't be played (")
+            .push_safe(describe(error))
+            .push(")");
+
+        // a retry failing is the same track failing, which was counted the first time
+        let failures = if retried {
+            self.failures.load(Ordering::SeqCst)
+        } else {
+            self.failures.fetch_add(1, Ordering::SeqCst) + 1
+        };
+        if failures >= MAX_CONSECUTIVE_FAILURES {
+            self.failures.store(0, Ordering::SeqCst);
+            if let Some(call) = self.manager.get(self.guild_id) {
+                call.lock().await.queue().stop();
+            }
+            msg.push(format!(
+                ". {} tracks in a row have failed, so I've stopped playing.",
+                failures
+            ));
+        } else {
+            match source {
+                Some(source) if !retried => match self.retry(handle, source).await {
+                    Ok(()) => {
+                        msg.push(", trying it again.");
+                    }
+                    Err(e) => {
+                        warn!("Couldn't retry track: {}", e);
+                        msg.push(", skipping it.");
+                    }
+                },
+                _ => {
+                    msg.push(", skipping it.");
+                }
+            }
+        }
+
+        let chan_id = self
+            .data
+            .settings
+            .get(self.guild_id)
+            .await
+            .announce_channel
+            .unwrap_or(self.chan_id);
+        if let Err(e) = chan_id
+            .send_message(&self.http, CreateMessage::new().content(msg.build()))
+            .await
+        {
+            warn!("Failed to send message: {}", e);
+        }
+
+        None
+    }
+}
+
+impl TrackErrorNotifier {
+    /// Loads the track again from scratch and slots it in where the failed one was.
+    async fn retry(&self, failed: &TrackHandle, source: TrackSource) -> Result<()> {
+        let call = self
+            .manager
+            .get(self.guild_id)
+            .ok_or_else(|| anyhow!("No longer in a call"))?;
+
+        // a bad download would only fail again, and yt-dlp will give us a fresh stream URL
+        if let TrackSource::YoutubeDl(url) = &source {
+            self.data.cache.remove(url).await;
+        }
+
+        let settings = self.data.settings.get(self.guild_id).await;
+        let filters = self.data.playback.filters(self.guild_id).await;
+        // looked up before taking the call's lock, so the queue's own metadata lookup is cached;
+        // the failed track's gain is reused, so there's no need to look it up again
+        let resolved = resolve(
+            self.client.clone(),
+            source,
+            false,
+            &self.data.cache,
+            &self.data.config.ytdl,
+        )
+        .await;
+        let input = FilteredInput::wrap(resolved.input, filters);
+        let volume = track_volume(failed, &settings).await;
+
+        let mut handler = call.lock().await;
+        let retry = handler.enqueue(Track::from(input).volume(volume)).await;
+        {
+            let old = failed.typemap().read().await;
+            let mut new = retry.typemap().write().await;
+            copy::<Metadata>(&old, &mut new);
+            copy::<CoverArt>(&old, &mut new);
+            copy::<TrackGain>(&old, &mut new);
+            copy::<PrefetchUrl>(&old, &mut new);
+            copy::<AlbumId>(&old, &mut new);
+            copy::<Catalog>(&old, &mut new);
+            copy::<OriginalSource>(&old, &mut new);
+            copy::<RequestedBy>(&old, &mut new);
+            new.insert::<Retried>(());
+        }
+
+        // enqueue put it at the back, move it up to where the failed track was
+        handler.queue().modify_queue(|queue| {
+            let Some(retry) = queue.pop_back() else {
+                return;
+            };
+            match queue.iter().position(|t| t.uuid() == failed.uuid()) {
+                // the queue hasn't moved on yet, so it'll pick the retry up next
+                Some(i) => queue.insert(i + 1, retry),
+                None => {
+                    if let Some(next) = queue.front() {
+                        let _ = next.pause();
+                    }
+                    let _ = retry.play();
+                    queue.push_front(retry);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Resets the failure count whenever a track finishes without an error.
+pub struct TrackFailureReset {
+    pub failures: Arc<AtomicU32>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for TrackFailureReset {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(&[(state, _)]) = ctx else {
+            return None;
+        };
+        if !matches!(state.playing, PlayMode::Errored(_)) {
+            self.failures.store(0, Ordering::SeqCst);
+        }
+        None
+    }
+}
+
+fn copy<K: TypeMapKey>(from: &TypeMap, to: &mut TypeMap)
+where
+    K::Value: Clone,
+{
+    if let Some(value) = from.get::<K>() {
+        to.insert::<K>(value.clone());
+    }
+}
+
+/// A short reason for the channel; the full error goes to the logs.
+fn describe(error: &PlayError) -> String {
+    match error {
+        PlayError::Create(e) => match e.as_ref() {
+            AudioStreamError::Fail(why) => {
+                let why = why.to_string();
+                // yt-dlp errors come with a lot of noise in front
+                match why.rsplit_once("ERROR: ") {
+                    Some((_, reason)) => reason.lines().next().unwrap_or(reason).to_owned(),
+                    None => "the source couldn't be loaded".to_owned(),
+                }
+            }
+            _ => "the source couldn't be loaded".to_owned(),
+        },
+        PlayError::Parse(_) => "the audio format wasn't recognised".to_owned(),
+        PlayError::Decode(_) => "the audio stream broke off".to_owned(),
+        PlayError::Seek(_) => "seeking failed".to_owned(),
+        _ => "something went wrong".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use symphonia::core::errors::Error as SymphoniaError;
+
+    use super::*;
+
+    fn create(error: AudioStreamError) -> PlayError {
+        PlayError::Create(Arc::new(error))
+    }
+
+    #[test]
+    fn describes_failures_briefly() {
+        let ytdl = "Tried to run yt-dlp\nWARNING: [youtube] something\nERROR: [youtube] dQw4w9WgXcQ: Video unavailable\nThis video has been removed";
+        assert_eq!(
+            describe(&create(AudioStreamError::Fail(ytdl.into()))),
+            "[youtube] dQw4w9WgXcQ: Video unavailable"
+        );
+        assert_eq!(
+            describe(&create(AudioStreamError::Fail("connection reset".into()))),
+            "the source couldn't be loaded"
+        );
+        assert_eq!(
+            describe(&create(AudioStreamError::Unsupported)),
+            "the source couldn't be loaded"
+        );
+        assert_eq!(
+            describe(&PlayError::Parse(Arc::new(SymphoniaError::Unsupported(
+                "codec"
+            )))),
+            "the audio format wasn't recognised"
+        );
+        assert_eq!(
+            describe(&PlayError::Decode(Arc::new(SymphoniaError::DecodeError(
+                "bad packet"
+            )))),
+            "the audio stream broke off"
+        );
+        assert_eq!(
+            describe(&PlayError::Seek(Arc::new(SymphoniaError::ResetRequired))),
+            "seeking failed"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use poise::CreateReply;
+use rand::seq::SliceRandom;
+use serenity::all::{CreateEmbed, CreateEmbedFooter};
+
+use crate::{
+    err::{AppError, ErrorKind},
+    teal,
+    voice::{bulk::queue_list, get_or_join_call},
+    Context,
+};
+
+use super::like_playing;
+
+/// Likes listed per page of `/likes list`.
+const LIST_LIKES: usize = 20;
+
+/// Adds what's playing to your likes
+#[poise::command(category = "Music", slash_command, prefix_command, guild_only)]
+pub async fn like(ctx: Context<'_>) -> Result<(), AppError> {
+    let guild_id = ctx
+        .guild_id()
+        .ok_or_else(|| anyhow::anyhow!("Not in a guild"))?;
+    ctx.defer_ephemeral().await?;
+
+    let (like, added) = like_playing(
+        ctx.serenity_context(),
+        ctx.data(),
+        guild_id,
+        ctx.author().id,
+    )
+    .await?;
+    let content = if added {
+        format!("Added {} to your likes", like.label())
+    } else {
+        format!("{} is already in your likes", like.label())
+    };
+    ctx.send(CreateReply::default().content(content).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// The tracks you've liked with /like or the ❤️ button
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    subcommands("list", "play", "remove", "link_teal", "unlink_teal"),
+    subcommand_required
+)]
+pub async fn likes(_ctx: Context<'_>) -> Result<(), AppError> {
+    Ok(())
+}
+
+/// Lists your likes, newest first
+#[poise::command(slash_command, prefix_command)]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Page of likes to show"]
+    #[min = 1]
+    page: Option<usize>,
+) -> Result<(), AppError> {
+    let likes = ctx
+        .data()
+        .likes
+        .read()
+        .await
+        .get(&ctx.author().id)
+        .cloned()
+        .unwrap_or_default();
+    if likes.is_empty() {
+        ctx.say("You haven't liked anything yet. Press ❤️ on what's playing or use /like")
+            .await?;
+        return Ok(());
+    }
+
+    let pages = likes.len().div_ceil(LIST_LIKES);
+    let page = page.unwrap_or(1).clamp(1, pages);
+    let lines: Vec<_> = likes
+        .iter()
+        .enumerate()
+        .skip((page - 1) * LIST_LIKES)
+        .take(LIST_LIKES)
+        .map(|(i, like)| match &like.source_url {
+            Some(url) => format!("{}. [{}]({})", i + 1, like.label(), url),
+            None => format!("{}. {}", i + 1, like.label()),
+        })
+        .collect();
+
+    let embed = CreateEmbed::default()
+        .title(format!("{}'s likes", ctx.author().display_name()))
+        .description(lines.join("\n"))
+        .footer(CreateEmbedFooter::new(format!(
+            "{} tracks · page {} of {}",
+            likes.len(),
+            page,
+            pages
+        )));
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Shuffles your likes into the queue
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn play(ctx: Context<'_>) -> Result<(), AppError> {
+    let mut likes = ctx
+        .data()
+        .likes
+        .read()
+        .await
+        .get(&ctx.author().id)
+        .cloned()
+        .unwrap_or_default();
+    if likes.is_empty() {
+        ctx.say("You haven't liked anything yet").await?;
+        return Ok(());
+    }
+    likes.shuffle(&mut rand::thread_rng());
+
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let (guild_id, channel_id) = crate::voice::guild_info(ctx).await?;
+    let Ok(call) = get_or_join_call(&manager, ctx, guild_id, channel_id).await else {
+        return Err(ErrorKind::NotInVoice.into());
+    };
+    ctx.defer().await?;
+
+    let name = format!("{}'s likes", ctx.author().display_name());
+    let tracks = likes.into_iter().map(|like| like.source).collect();
+    let content = queue_list(ctx, guild_id, call, name, tracks).await;
+    ctx.say(content).await?;
+    Ok(())
+}
+
+/// Forgets one of your likes
+#[poise::command(slash_command, prefix_command)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Position of the like, as shown by /likes list"]
+    #[min = 1]
+    position: usize,
+) -> Result<(), AppError> {
+    let user = ctx.author().id;
+    let removed = ctx
+        .data()
+        .likes
+        .update(|likes| {
+            let list = likes.get_mut(&user)?;
+            let removed =
+                (position >= 1 && position <= list.len()).then(|| list.remove(position - 1));
+            if list.is_empty() {
+                likes.remove(&user);
+            }
+            removed
+        })
+        .await?;
+
+    let Some(like) = removed else {
+        return Err(ErrorKind::InvalidValue(format!("you don't have a like {}", position)).into());
+    };
+    ctx.say(format!("Removed {} from your likes", like.label()))
+        .await?;
+    Ok(())
+}
+
+/// Saves your likes to teal.fm too, using an app password from your ATProto account
+#[poise::command(slash_command, rename = "link-teal", ephemeral)]
+pub async fn link_teal(
+    ctx: Context<'_>,
+    #[description = "Your handle, like you.bsky.social"] handle: String,
+    #[description = "An app password, made in your account's settings"] app_password: String,
+    #[description = "Where your account is hosted, if not on Bluesky"] service: Option<String>,
+) -> Result<(), AppError> {
+    let service = service.as_deref().unwrap_or(teal::DEFAULT_SERVICE);
+    let account = teal::login(
+        service,
+        handle.trim().trim_start_matches('@'),
+        app_password.trim(),
+    )
+    .await
+    .map_err(|e| ErrorKind::InvalidValue(format!("couldn't log in: {}", e)))?;
+
+    let handle = account.handle.clone();
+    let user = ctx.author().id;
+    ctx.data()
+        .teal
+        .update(|accounts| accounts.insert(user, account))
+        .await?;
+    ctx.say(format!(
+        "New likes will be saved to teal.fm as @{}. /likes unlink-teal stops that",
+        handle
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Stops saving your likes to teal.fm
+#[poise::command(slash_command, prefix_command, rename = "unlink-teal", ephemeral)]
+pub async fn unlink_teal(ctx: Context<'_>) -> Result<(), AppError> {
+    let user = ctx.author().id;
+    let removed = ctx
+        .data()
+        .teal
+        .update(|accounts| accounts.remove(&user))
+        .await?;
+    let content = match removed {
+        Some(account) => format!("Stopped saving likes to @{}", account.handle),
+        None => "You haven't linked teal.fm".to_owned(),
+    };
+    ctx.say(content).await?;
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::all::{
+    ComponentInteraction, CreateInteractionResponse, CreateInteractionResponseMessage, GuildId,
+    UserId,
+};
+use songbird::tracks::TrackHandle;
+
+use crate::{
+    err::{AppError, ErrorKind},
+    store::JsonStore,
+    teal,
+    voice::{
+        metadata::{title_of, Catalog, Metadata},
+        source::{OriginalSource, TrackSource},
+    },
+    Data,
+};
+
+pub mod command;
+
+/// The most tracks a user can like, the oldest are forgotten after that.
+const MAX_LIKES: usize = 1000;
+/// Custom ID of the ❤️ button on the now playing message.
+pub const LIKE_BUTTON: &str = "np:like";
+
+/// A track someone liked, with enough to find it again anywhere.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Like {
+    pub source: TrackSource,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub duration: Option<Duration>,
+    /// The page the track was played from.
+    pub source_url: Option<String>,
+    /// The Apple Music catalog song it was matched to.
+    pub apple_music_id: Option<String>,
+    pub isrc: Option<String>,
+    /// Unix timestamp, in seconds.
+    pub liked_at: u64,
+}
+
+impl Like {
+    pub fn label(&self) -> String {
+        let title = self
+            .title
+            .clone()
+            .or_else(|| self.source_url.clone())
+            .unwrap_or_else(|| "Unknown track".to_owned());
+        match &self.artist {
+            Some(artist) => format!("{} — {}", title, artist),
+            None => title,
+        }
+    }
+}
+
+/// Everyone's liked tracks, newest first, saved to `likes.json`.
+pub type LikeStore = JsonStore<HashMap<UserId, Vec<Like>>>;
+
+/// Adds a like to the front of `likes`, unless the track's already in there.
+pub fn add(likes: &mut Vec<Like>, like: Like) -> bool {
+    if likes.iter().any(|l| l.source == like.source) {
+        return false;
+    }
+    likes.insert(0, like);
+    likes.truncate(MAX_LIKES);
+    true
+}
+
+/// What's known about a queued track, with the catalog ID and ISRC of the Apple Music song it
+/// was matched to.
+async fn from_track(handle: &TrackHandle) -> Option<Like> {
+    let typemap = handle.typemap().read().await;
+    let metadata = typemap.get::<Metadata>().cloned().unwrap_or_default();
+    let catalog = typemap.get::<Catalog>();
+    Some(Like {
+        source: typemap.get::<OriginalSource>()?.clone(),
+        title: title_of(&typemap),
+        artist: catalog
+            .map(|c| c.artist.clone())
+            .or_else(|| metadata.artist.clone()),
+        duration: metadata.duration,
+        source_url: metadata.source_url.clone(),
+        apple_music_id: catalog.map(|c| c.id.clone()),
+        isrc: catalog.and_then(|c| c.isrc.clone()),
+        liked_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+    })
+}
+
+/// Adds what's playing in the guild to the user's likes, returning it and whether it's new.
+pub async fn like_playing(
+    ctx: &serenity::Context,
+    data: &Arc<Data>,
+    guild_id: GuildId,
+    user: UserId,
+) -> Result<(Like, bool), AppError> {
+    let current = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .get(guild_id)
+        .ok_or(ErrorKind::NotInVoice)?
+        .lock()
+        .await
+        .queue()
+        .current();
+    let Some(current) = current else {
+        return Err(ErrorKind::InvalidValue("nothing's playing".to_owned()).into());
+    };
+
+    let like = from_track(&current)
+        .await
+        .ok_or_else(|| ErrorKind::InvalidValue("this track can't be played again".to_owned()))?;
+
+    let added = data
+        .likes
+        .update(|likes| add(likes.entry(user).or_default(), like.clone()))
+        .await?;
+    if added {
+        let data = data.clone();
+        let like = like.clone();
+        tokio::spawn(async move { teal::sync_like(&data, user, &like).await });
+    }
+    Ok((like, added))
+}
+
+/// Handles a press of the ❤️ button on the now playing message.
+pub async fn handle_button(
+    ctx: &serenity::Context,
+    component: &ComponentInteraction,
+    data: &Arc<Data>,
+) -> Result<(), AppError> {
+    if component.data.custom_id != LIKE_BUTTON {
+        return Ok(());
+    }
+    let Some(guild_id) = component.guild_id else {
+        return Ok(());
+    };
+
+    let content = match like_playing(ctx, data, guild_id, component.user.id).await {
+        Ok((like, true)) => format!("Added {} to your likes", like.label()),
+        Ok((like, false)) => format!("{} is already in your likes", like.label()),
+        Err(e) => e.user_message(),
+    };
+    let reply = CreateInteractionResponseMessage::new()
+        .content(content)
+        .ephemeral(true);
+    component
+        .create_response(&ctx.http, CreateInteractionResponse::Message(reply))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn like(url: &str) -> Like {
+        Like {
+            source: TrackSource::YoutubeDl(url.to_owned()),
+            title: None,
+            artist: None,
+            duration: None,
+            source_url: Some(url.to_owned()),
+            apple_music_id: None,
+            isrc: None,
+            liked_at: 0,
+        }
+    }
+
+    #[test]
+    fn likes_each_track_once_newest_first() {
+        let mut likes = Vec::new();
+        assert!(add(&mut likes, like("https://youtu.be/a")));
+        assert!(add(&mut likes, like("https://youtu.be/b")));
+        assert!(!add(&mut likes, like("https://youtu.be/a")));
+        assert_eq!(
+            likes
+                .iter()
+                .map(|l| l.source_url.as_deref().unwrap())
+                .collect::<Vec<_>>(),
+            ["https://youtu.be/b", "https://youtu.be/a"]
+        );
+    }
+
+    #[test]
+    fn forgets_the_oldest_likes() {
+        let mut likes = Vec::new();
+        for i in 0..=MAX_LIKES {
+            add(&mut likes, like(&format!("https://youtu.be/{}", i)));
+        }
+        assert_eq!(likes.len(), MAX_LIKES);
+        assert_eq!(
+            likes.last().and_then(|l| l.source_url.as_deref()),
+            Some("https://youtu.be/1")
+        );
+    }
+}
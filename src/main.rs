@@ -1,21 +1,78 @@
-use ::serenity::all::GatewayIntents;
 use ::serenity::prelude::TypeMapKey;
+use config::{Config, Registration};
 use dotenvy::dotenv;
 use err::AppError;
 use helpers::HttpKey;
 use poise::serenity_prelude as serenity;
-use songbird::SerenityInit;
+use songbird::{SerenityInit, Songbird};
 use std::env;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tracing::{error, info, warn};
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
 
+mod admin;
 mod apol;
+mod config;
+mod dashboard;
 mod err;
 mod helpers;
+mod likes;
+mod metrics;
 mod odesli;
+mod playlist;
+mod server;
+mod settings;
+mod shutdown;
+mod stats;
+mod store;
+mod teal;
+mod telemetry;
 mod voice;
 
-struct Data {}
+struct Data {
+    config: &'static Config,
+    settings: settings::Settings,
+    playback: voice::state::PlaybackState,
+    radio: voice::radio::RadioPresets,
+    cache: Arc<voice::prefetch::TrackCache>,
+    now_playing: voice::nowplaying::LiveMessages,
+    registered: admin::Registered,
+    saved: voice::saved::SavedQueues,
+    history: voice::history::History,
+    playlists: playlist::PlaylistStore,
+    likes: likes::LikeStore,
+    teal: teal::Accounts,
+    teal_writes: teal::Writes,
+    plays: stats::store::PlayStore,
+}
+
+impl Data {
+    /// Loads everything saved under the config's data directory.
+    fn open(config: &'static Config) -> Self {
+        Self {
+            config,
+            settings: settings::Settings::open(&config.data_dir),
+            playback: Default::default(),
+            radio: store::JsonStore::open(&config.data_dir, "radio"),
+            cache: Arc::new(voice::prefetch::TrackCache::open(
+                config.cache_dir(),
+                config.cache.max_bytes(),
+                config.cache.max_age(),
+                &config.ytdl,
+            )),
+            now_playing: Default::default(),
+            registered: Default::default(),
+            saved: store::JsonStore::open(&config.data_dir, "queues"),
+            history: Default::default(),
+            playlists: store::JsonStore::open(&config.data_dir, "playlists"),
+            likes: store::JsonStore::open(&config.data_dir, "likes"),
+            teal: store::JsonStore::open(&config.data_dir, "teal"),
+            teal_writes: Default::default(),
+            plays: stats::store::PlayStore::open(&config.data_dir),
+        }
+    }
+}
 
 impl TypeMapKey for Data {
     type Value = Arc<Data>;
@@ -34,11 +91,72 @@ async fn age(
     Ok(())
 }
 
+/// What was asked for on the command line.
+struct Args {
+    config: Option<PathBuf>,
+    check_config: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        config: None,
+        check_config: false,
+    };
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--check-config" => args.check_config = true,
+            "--config" => {
+                let path = argv.next().ok_or("--config needs a path")?;
+                args.config = Some(PathBuf::from(path));
+            }
+            _ => match arg.strip_prefix("--config=") {
+                Some(path) => args.config = Some(PathBuf::from(path)),
+                None => return Err(format!("unknown argument {}", arg)),
+            },
+        }
+    }
+    Ok(args)
+}
+
 async fn on_error(error: poise::FrameworkError<'_, Arc<Data>, AppError>) {
     match error {
         poise::FrameworkError::Setup { error, .. } => panic!("Failed to start bot {:?}", error),
         poise::FrameworkError::Command { error, ctx, .. } => {
-            warn!("Error in command `{}`: {:?}", ctx.command().name, error);
+            metrics::metrics().command_failed(&ctx.command().qualified_name);
+            if error.is_internal() {
+                error!(
+                    correlation_id = error.id(),
+                    "Error in command `{}`: {}",
+                    ctx.command().qualified_name,
+                    error.chain()
+                );
+            } else {
+                warn!(
+                    correlation_id = error.id(),
+                    "Command `{}` failed: {}",
+                    ctx.command().qualified_name,
+                    error.chain()
+                );
+            }
+            if let Err(e) = ctx.send(error.reply()).await {
+                error!("Failed to send error reply: {}", e)
+            }
+        }
+        poise::FrameworkError::CommandCheckFailed {
+            error: Some(error),
+            ctx,
+            ..
+        } => {
+            warn!(
+                correlation_id = error.id(),
+                "Check failed for `{}`: {}",
+                ctx.command().qualified_name,
+                error.chain()
+            );
+            if let Err(e) = ctx.send(error.reply()).await {
+                error!("Failed to send error reply: {}", e)
+            }
         }
         error => {
             if let Err(e) = poise::builtins::on_error(error).await {
@@ -51,14 +169,42 @@ async fn on_error(error: poise::FrameworkError<'_, Arc<Data>, AppError>) {
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    tracing_subscriber::fmt::init();
 
-    let discord_token = env::var("DISCORD_TOKEN").expect("Expected DISCORD_TOKEN in environment");
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\nusage: marine [--config <path>] [--check-config]", e);
+            std::process::exit(2);
+        }
+    };
+    let config = match Config::load(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if args.check_config {
+        println!("Configuration is valid");
+        return;
+    }
+    let telemetry = telemetry::init(&config.log);
+    // lives as long as the bot does, so everything can borrow it
+    let config: &'static Config = Box::leak(Box::new(config));
+
+    apol::set_user_agent(config.apple_music.user_agent.clone());
+    apol::set_token_lifetime(Duration::from_secs(config.apple_music.token_lifetime))
+        .await
+        .expect("setting the token lifetime can't fail");
+    voice::loudness::set_client_version(config.youtube.client_version.clone());
+
+    let user_data = Arc::new(Data::open(config));
 
-    let user_data = Arc::new(Data {});
+    let dashboard = dashboard::Dashboard::new(config, user_data.clone());
 
-    let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
+    let intents = config.intents();
     let ud_clone = user_data.clone();
+    let dashboard_clone = dashboard.clone();
     let framework: poise::Framework<Arc<Data>, AppError> = poise::Framework::builder()
         .options(poise::FrameworkOptions {
             commands: vec![
@@ -68,10 +214,33 @@ async fn main() {
                 voice::queue::skip(),
                 voice::queue::now_playing(),
                 voice::queue::queue(),
+                voice::queue::remove(),
+                voice::queue::move_cmd(),
+                voice::controls::loop_track(),
+                voice::controls::shuffle(),
+                voice::controls::stop(),
+                voice::volume::volume(),
+                voice::crossfade::crossfade(),
+                voice::announce::announce(),
+                voice::fairness::queue_rules(),
+                voice::filters::filter(),
+                voice::radio::radio(),
+                voice::saved::restore(),
+                playlist::command::playlist(),
+                likes::command::like(),
+                likes::command::likes(),
+                stats::command::stats(),
+                voice::menus::play_this(),
+                voice::menus::listening_to(),
+                settings::command::config(),
+                admin::admin(),
             ],
+            owners: config.discord.owners.iter().copied().collect(),
             pre_command: |ctx| {
                 Box::pin(async move {
+                    tracing::Span::current().record("command", &ctx.command().qualified_name);
                     info!("Executing command {}...", ctx.command().qualified_name);
+                    metrics::metrics().command_started(&ctx.command().qualified_name);
                 })
             },
             on_error: |error| Box::pin(on_error(error)),
@@ -79,49 +248,105 @@ async fn main() {
             post_command: |ctx| {
                 Box::pin(async move {
                     info!("Executed command {}!", ctx.command().qualified_name);
+                    metrics::metrics().command_completed(&ctx.command().qualified_name);
                 })
             },
-            event_handler: |_ctx, event, _framework, _data| {
+            event_handler: |ctx, event, framework, data| {
                 Box::pin(async move {
-                    info!(
-                        "Got an event in event handler: {:?}",
-                        event.snake_case_name()
-                    );
+                    debug!("Got event {}", event.snake_case_name());
+                    match event {
+                        serenity::FullEvent::InteractionCreate {
+                            interaction: serenity::Interaction::Component(component),
+                        } => {
+                            voice::nowplaying::handle_button(ctx, component, data).await?;
+                            likes::handle_button(ctx, component, data).await?;
+                        }
+                        serenity::FullEvent::Message { new_message } => {
+                            voice::listen::on_message(ctx, new_message, data).await?;
+                        }
+                        serenity::FullEvent::GuildCreate { guild, .. } => {
+                            admin::on_guild_create(
+                                ctx,
+                                &framework.options.commands,
+                                data,
+                                guild.id,
+                            )
+                            .await?;
+                        }
+                        _ => {}
+                    }
                     Ok(())
                 })
             },
 
             ..Default::default()
         })
-        .setup(|ctx, ready, framework| {
+        .setup(move |ctx, ready, framework| {
             Box::pin(async move {
-                //poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                // for all guilds we are in
-                for guild in ctx.cache.guilds() {
-                    poise::builtins::register_in_guild(ctx, &framework.options().commands, guild)
-                        .await?;
+                // guild commands are registered as each guild comes in
+                if config.discord.registration == Registration::Global {
+                    poise::builtins::register_globally(ctx, &framework.options().commands).await?;
                 }
                 info!(
                     "{} [{}] connected successfully!",
                     ready.user.name, ready.user.id
                 );
+                metrics::metrics().set_discord_ready(true);
+                if let Some(dashboard) = dashboard_clone {
+                    dashboard.set_context(ctx.clone());
+                }
+                stats::spawn_recaps(ctx.http.clone(), ud_clone.clone());
+                // not ready until there's an Apple Music token to look tracks up with
+                tokio::spawn(async {
+                    while let Err(e) = apol::get_apple_music_token().await {
+                        warn!("Failed to get an Apple Music token, retrying: {}", e);
+                        tokio::time::sleep(Duration::from_secs(30)).await;
+                    }
+                });
                 Ok(ud_clone)
             })
         })
         .build();
 
-    let mut client = serenity::ClientBuilder::new(discord_token, intents)
-        .framework(framework)
+    let http_client = reqwest::Client::builder()
+        .user_agent(&config.http.user_agent)
+        .build()
+        .expect("user agent was validated with the config");
+
+    let songbird = Songbird::serenity();
+    let mut client = serenity::ClientBuilder::new(&config.discord.token, intents)
+        .framework(telemetry::Traced(framework))
         //.event_handler(Handler)
-        .register_songbird()
-        .type_map_insert::<HttpKey>(reqwest::Client::new())
+        .register_songbird_with(songbird.clone())
+        .type_map_insert::<HttpKey>(http_client)
         .await
         .expect("create client failed");
 
     {
         let mut data = client.data.write().await;
-        data.insert::<Data>(user_data);
+        data.insert::<Data>(user_data.clone());
         //
     }
-    client.start().await.unwrap();
+
+    if let Some(addr) = config.server.listen {
+        let manager = songbird.clone();
+        let dashboard = dashboard.map(|dashboard| dashboard.router());
+        tokio::spawn(async move {
+            if let Err(e) = server::serve(addr, manager, dashboard).await {
+                error!("HTTP server stopped: {}", e);
+            }
+        });
+    }
+
+    let http = client.http.clone();
+    let shards = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown::signal().await;
+        shutdown::shutdown(http, songbird, user_data, shards).await;
+    });
+
+    if let Err(e) = client.start().await {
+        error!("Client stopped: {}", e);
+    }
+    telemetry.shutdown();
 }
@@ -0,0 +1,379 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use serenity::{all::GuildId, async_trait};
+use songbird::{typemap::TypeMapKey, Event, EventContext, EventHandler as VoiceEventHandler};
+
+/// Upper bounds of the source resolution latency buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+/// The bot's counters, shared by everything that feeds them.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// What the bot has been up to since it started, for `/metrics` and `/readyz`.
+#[derive(Default)]
+pub struct Metrics {
+    discord_ready: AtomicBool,
+    apple_music_ready: AtomicBool,
+    tracks_played: AtomicU64,
+    ytdl_failures: AtomicU64,
+    /// Apple Music responses by status code, or `error` when there wasn't one.
+    apple_music: Mutex<BTreeMap<String, u64>>,
+    commands: Mutex<BTreeMap<String, CommandCounts>>,
+    /// Source resolution latency by source kind.
+    resolve: Mutex<BTreeMap<&'static str, Histogram>>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct CommandCounts {
+    started: u64,
+    completed: u64,
+    failed: u64,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Histogram {
+    /// Cumulative, one per bucket in `LATENCY_BUCKETS`.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, le) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= le {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+impl Metrics {
+    pub fn set_discord_ready(&self, ready: bool) {
+        self.discord_ready.store(ready, Ordering::Relaxed);
+    }
+
+    pub fn set_apple_music_ready(&self) {
+        self.apple_music_ready.store(true, Ordering::Relaxed);
+    }
+
+    /// What still has to happen before the bot is ready, empty once it is.
+    pub fn not_ready(&self) -> Vec<&'static str> {
+        let mut waiting = Vec::new();
+        if !self.discord_ready.load(Ordering::Relaxed) {
+            waiting.push("discord");
+        }
+        if !self.apple_music_ready.load(Ordering::Relaxed) {
+            waiting.push("apple_music");
+        }
+        waiting
+    }
+
+    pub fn track_played(&self) {
+        self.tracks_played.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn ytdl_failed(&self) {
+        self.ytdl_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts an Apple Music response and hands it back.
+    pub fn apple_music(
+        &self,
+        response: reqwest::Result<reqwest::Response>,
+    ) -> reqwest::Result<reqwest::Response> {
+        let status = match &response {
+            Ok(response) => response.status().as_u16().to_string(),
+            Err(_) => "error".to_owned(),
+        };
+        *self.apple_music.lock().unwrap().entry(status).or_default() += 1;
+        response
+    }
+
+    pub fn resolved(&self, kind: &'static str, took: Duration) {
+        self.resolve
+            .lock()
+            .unwrap()
+            .entry(kind)
+            .or_default()
+            .observe(took.as_secs_f64());
+    }
+
+    pub fn command_started(&self, name: &str) {
+        self.command(name, |c| c.started += 1);
+    }
+
+    pub fn command_completed(&self, name: &str) {
+        self.command(name, |c| c.completed += 1);
+    }
+
+    pub fn command_failed(&self, name: &str) {
+        self.command(name, |c| c.failed += 1);
+    }
+
+    fn command(&self, name: &str, f: impl FnOnce(&mut CommandCounts)) {
+        let mut commands = self.commands.lock().unwrap();
+        match commands.get_mut(name) {
+            Some(counts) => f(counts),
+            None => f(commands.entry(name.to_owned()).or_default()),
+        }
+    }
+
+    /// Everything in the Prometheus text format, along with the queue length of each call the
+    /// bot is in.
+    pub fn render(&self, calls: &[(GuildId, usize)]) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "marine_voice_connections",
+            "gauge",
+            "Voice calls the bot is in.",
+        );
+        let _ = writeln!(out, "marine_voice_connections {}", calls.len());
+
+        header(
+            &mut out,
+            "marine_queue_length",
+            "gauge",
+            "Tracks queued in each call.",
+        );
+        for (guild_id, len) in calls {
+            let _ = writeln!(out, "marine_queue_length{{guild=\"{}\"}} {}", guild_id, len);
+        }
+
+        header(
+            &mut out,
+            "marine_tracks_played_total",
+            "counter",
+            "Tracks that started playing.",
+        );
+        let _ = writeln!(
+            out,
+            "marine_tracks_played_total {}",
+            self.tracks_played.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "marine_ytdl_failures_total",
+            "counter",
+            "yt-dlp runs that failed.",
+        );
+        let _ = writeln!(
+            out,
+            "marine_ytdl_failures_total {}",
+            self.ytdl_failures.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "marine_apple_music_requests_total",
+            "counter",
+            "Requests to Apple Music by response status.",
+        );
+        for (status, count) in self.apple_music.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "marine_apple_music_requests_total{{status=\"{}\"}} {}",
+                status, count
+            );
+        }
+
+        header(
+            &mut out,
+            "marine_resolve_seconds",
+            "histogram",
+            "How long looking up a track took, by source.",
+        );
+        for (kind, histogram) in self.resolve.lock().unwrap().iter() {
+            for (count, le) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "marine_resolve_seconds_bucket{{source=\"{}\",le=\"{}\"}} {}",
+                    kind, le, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "marine_resolve_seconds_bucket{{source=\"{}\",le=\"+Inf\"}} {}",
+                kind, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "marine_resolve_seconds_sum{{source=\"{}\"}} {}",
+                kind, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "marine_resolve_seconds_count{{source=\"{}\"}} {}",
+                kind, histogram.count
+            );
+        }
+
+        let commands = self.commands.lock().unwrap();
+        command_counts(
+            &mut out,
+            &commands,
+            "marine_commands_started_total",
+            "Commands invoked, by name.",
+            |c| c.started,
+        );
+        command_counts(
+            &mut out,
+            &commands,
+            "marine_commands_completed_total",
+            "Commands that finished successfully, by name.",
+            |c| c.completed,
+        );
+        command_counts(
+            &mut out,
+            &commands,
+            "marine_commands_failed_total",
+            "Commands that returned an error, by name.",
+            |c| c.failed,
+        );
+
+        out
+    }
+}
+
+fn header(out: &mut String, metric: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", metric, help);
+    let _ = writeln!(out, "# TYPE {} {}", metric, kind);
+}
+
+fn command_counts(
+    out: &mut String,
+    commands: &BTreeMap<String, CommandCounts>,
+    metric: &str,
+    help: &str,
+    count: fn(&CommandCounts) -> u64,
+) {
+    header(out, metric, "counter", help);
+    for (name, counts) in commands {
+        let _ = writeln!(
+            out,
+            "{}{{command=\"{}\"}} {}",
+            metric,
+            escape(name),
+            count(counts)
+        );
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Marks a track that's already been counted, since resuming it fires the play event again.
+struct Counted;
+
+impl TypeMapKey for Counted {
+    type Value = ();
+}
+
+/// Counts every track that starts playing in a call.
+pub struct CountPlays;
+
+#[async_trait]
+impl VoiceEventHandler for CountPlays {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(tracks) = ctx else {
+            return None;
+        };
+        for (_, handle) in tracks.iter() {
+            let mut typemap = handle.typemap().write().await;
+            if !typemap.contains_key::<Counted>() {
+                typemap.insert::<Counted>(());
+                metrics().track_played();
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_gauges() {
+        let metrics = Metrics::default();
+        metrics.track_played();
+        metrics.track_played();
+        metrics.ytdl_failed();
+        metrics.command_started("play");
+        metrics.command_completed("play");
+        metrics.command_started("queue rules");
+        metrics.command_failed("queue rules");
+
+        let text = metrics.render(&[(GuildId::new(1), 3), (GuildId::new(2), 0)]);
+        for line in [
+            "marine_voice_connections 2",
+            "marine_queue_length{guild=\"1\"} 3",
+            "marine_queue_length{guild=\"2\"} 0",
+            "marine_tracks_played_total 2",
+            "marine_ytdl_failures_total 1",
+            "marine_commands_started_total{command=\"play\"} 1",
+            "marine_commands_completed_total{command=\"play\"} 1",
+            "marine_commands_failed_total{command=\"play\"} 0",
+            "marine_commands_failed_total{command=\"queue rules\"} 1",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {line:?} in\n{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn latency_buckets_are_cumulative() {
+        let metrics = Metrics::default();
+        metrics.resolved("youtube", Duration::from_millis(300));
+        metrics.resolved("youtube", Duration::from_secs(3));
+        metrics.resolved("youtube", Duration::from_secs(60));
+
+        let text = metrics.render(&[]);
+        for line in [
+            "marine_resolve_seconds_bucket{source=\"youtube\",le=\"0.25\"} 0",
+            "marine_resolve_seconds_bucket{source=\"youtube\",le=\"0.5\"} 1",
+            "marine_resolve_seconds_bucket{source=\"youtube\",le=\"5\"} 2",
+            "marine_resolve_seconds_bucket{source=\"youtube\",le=\"30\"} 2",
+            "marine_resolve_seconds_bucket{source=\"youtube\",le=\"+Inf\"} 3",
+            "marine_resolve_seconds_count{source=\"youtube\"} 3",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {line:?} in\n{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn ready_once_discord_and_apple_music_are() {
+        let metrics = Metrics::default();
+        assert_eq!(metrics.not_ready(), ["discord", "apple_music"]);
+        metrics.set_discord_ready(true);
+        metrics.set_apple_music_ready();
+        assert!(metrics.not_ready().is_empty());
+    }
+}
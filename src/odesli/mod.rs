@@ -1 +1,188 @@
+use std::collections::{BTreeMap, HashMap};
 
+use anyhow::{anyhow, Result};
+use reqwest::Client as HttpClient;
+use serde::Deserialize;
+use tracing::{debug, instrument};
+
+const API_URL: &str = "https://api.song.link/v1-alpha.1/links";
+
+/// The same song on every platform Odesli knows about.
+#[derive(Debug, Clone)]
+pub struct Links {
+    /// The song.link page listing all of them.
+    pub page_url: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub thumbnail: Option<String>,
+    /// Links by Odesli's platform name, e.g. `spotify` or `appleMusic`.
+    pub platforms: BTreeMap<String, String>,
+}
+
+impl Links {
+    pub fn get(&self, platform: &str) -> Option<&str> {
+        self.platforms.get(platform).map(String::as_str)
+    }
+
+    /// Somewhere yt-dlp can play the song from.
+    pub fn youtube(&self) -> Option<&str> {
+        self.get("youtube").or_else(|| self.get("youtubeMusic"))
+    }
+
+    /// Every platform's name and link, the big ones first.
+    pub fn by_platform(&self) -> Vec<(&str, &str)> {
+        let mut platforms: Vec<_> = self
+            .platforms
+            .iter()
+            .map(|(platform, url)| (platform.as_str(), url.as_str()))
+            .collect();
+        platforms.sort_by_key(|(platform, _)| {
+            PLATFORMS
+                .iter()
+                .position(|(p, _)| p == platform)
+                .unwrap_or(PLATFORMS.len())
+        });
+        platforms
+            .into_iter()
+            .map(|(platform, url)| (platform_name(platform), url))
+            .collect()
+    }
+}
+
+/// Odesli's platform names and what to call them, in the order they're listed.
+const PLATFORMS: &[(&str, &str)] = &[
+    ("spotify", "Spotify"),
+    ("appleMusic", "Apple Music"),
+    ("youtube", "YouTube"),
+    ("youtubeMusic", "YouTube Music"),
+    ("soundcloud", "SoundCloud"),
+    ("tidal", "Tidal"),
+    ("deezer", "Deezer"),
+    ("amazonMusic", "Amazon Music"),
+    ("pandora", "Pandora"),
+    ("itunes", "iTunes"),
+    ("amazonStore", "Amazon"),
+    ("napster", "Napster"),
+    ("yandex", "Yandex Music"),
+    ("anghami", "Anghami"),
+    ("boomplay", "Boomplay"),
+    ("audiomack", "Audiomack"),
+    ("audius", "Audius"),
+];
+
+fn platform_name(platform: &str) -> &str {
+    PLATFORMS
+        .iter()
+        .find(|(p, _)| *p == platform)
+        .map_or(platform, |(_, name)| name)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    entity_unique_id: String,
+    page_url: String,
+    links_by_platform: HashMap<String, PlatformLink>,
+    entities_by_unique_id: HashMap<String, Entity>,
+}
+
+#[derive(Deserialize)]
+struct PlatformLink {
+    url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entity {
+    title: Option<String>,
+    artist_name: Option<String>,
+    thumbnail_url: Option<String>,
+}
+
+impl From<Response> for Links {
+    fn from(mut response: Response) -> Self {
+        let entity = response
+            .entities_by_unique_id
+            .remove(&response.entity_unique_id);
+        Self {
+            page_url: response.page_url,
+            title: entity.as_ref().and_then(|e| e.title.clone()),
+            artist: entity.as_ref().and_then(|e| e.artist_name.clone()),
+            thumbnail: entity.and_then(|e| e.thumbnail_url),
+            platforms: response
+                .links_by_platform
+                .into_iter()
+                .map(|(platform, link)| (platform, link.url))
+                .collect(),
+        }
+    }
+}
+
+/// Finds a song or album link on every other platform, as seen from `country`.
+#[instrument(name = "odesli_lookup", skip(http))]
+pub async fn lookup(http: &HttpClient, url: &str, country: &str) -> Result<Links> {
+    debug!("Looking up links on Odesli");
+
+    let response = http
+        .get(API_URL)
+        .query(&[("url", url), ("userCountry", &country.to_uppercase())])
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        Ok(response.json::<Response>().await?.into())
+    } else {
+        let status = response.status();
+        let text = response.text().await?;
+        Err(anyhow!("{}: {}", status, text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_song_and_its_links() {
+        let response: Response = serde_json::from_str(
+            r#"{
+                "entityUniqueId": "SPOTIFY_SONG::1",
+                "userCountry": "US",
+                "pageUrl": "https://song.link/s/1",
+                "linksByPlatform": {
+                    "spotify": { "url": "https://open.spotify.com/track/1", "entityUniqueId": "SPOTIFY_SONG::1" },
+                    "youtubeMusic": { "url": "https://music.youtube.com/watch?v=abc", "entityUniqueId": "YOUTUBE_VIDEO::abc" }
+                },
+                "entitiesByUniqueId": {
+                    "SPOTIFY_SONG::1": {
+                        "id": "1",
+                        "type": "song",
+                        "title": "Never Gonna Give You Up",
+                        "artistName": "Rick Astley",
+                        "thumbnailUrl": "https://i.scdn.co/image/1",
+                        "apiProvider": "spotify",
+                        "platforms": ["spotify"]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let links = Links::from(response);
+        assert_eq!(links.page_url, "https://song.link/s/1");
+        assert_eq!(links.title.as_deref(), Some("Never Gonna Give You Up"));
+        assert_eq!(links.artist.as_deref(), Some("Rick Astley"));
+        assert_eq!(
+            links.youtube(),
+            Some("https://music.youtube.com/watch?v=abc")
+        );
+        assert_eq!(links.get("appleMusic"), None);
+        assert_eq!(
+            links.by_platform(),
+            [
+                ("Spotify", "https://open.spotify.com/track/1"),
+                ("YouTube Music", "https://music.youtube.com/watch?v=abc"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,645 @@
+use std::time::Duration;
+
+use poise::{serenity_prelude as serenity, CreateReply};
+use rand::seq::SliceRandom;
+use serenity::all::{Attachment, CreateAttachment, CreateEmbed, Mentionable, UserId};
+use songbird::tracks::TrackHandle;
+
+use crate::{
+    apol::playlist::{parse_playlist_url, playlist_tracks},
+    err::{AppError, ErrorKind},
+    helpers::get_http_client,
+    voice::{
+        bulk::queue_list,
+        get_or_join_call,
+        links::{find_links, source_for},
+        metadata::{title_of, Metadata},
+        source::{OriginalSource, TrackSource},
+    },
+    Context,
+};
+
+use super::{import as import_file, to_json, to_m3u, Playlist, PlaylistTrack, Scope};
+
+/// The biggest playlist file that can be imported.
+const MAX_IMPORT_BYTES: u32 = 1024 * 1024;
+/// Tracks listed per `/playlist show`.
+const SHOW_TRACKS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Format {
+    #[name = "JSON"]
+    Json,
+    #[name = "M3U"]
+    M3u,
+}
+
+/// Saves tracks to play again later, for yourself or the whole server
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    subcommands(
+        "create",
+        "add",
+        "remove",
+        "show",
+        "play",
+        "delete",
+        "import",
+        "export",
+        "collaborator"
+    ),
+    subcommand_required,
+    guild_only
+)]
+pub async fn playlist(_ctx: Context<'_>) -> Result<(), AppError> {
+    Ok(())
+}
+
+fn scope(ctx: Context<'_>, server: Option<bool>) -> Result<Scope, AppError> {
+    if server.unwrap_or(false) {
+        let guild_id = ctx
+            .guild_id()
+            .ok_or_else(|| anyhow::anyhow!("Not in a guild"))?;
+        Ok(Scope::Guild(guild_id))
+    } else {
+        Ok(Scope::User(ctx.author().id))
+    }
+}
+
+/// Whether the author can manage the server, and so every playlist shared with it.
+async fn is_manager(ctx: Context<'_>) -> bool {
+    let Some(member) = ctx.author_member().await else {
+        return false;
+    };
+    ctx.guild().is_some_and(|guild| {
+        #[allow(deprecated)]
+        guild.member_permissions(&member).manage_guild()
+    })
+}
+
+fn not_found(name: &str) -> AppError {
+    ErrorKind::InvalidValue(format!("there's no playlist called {}", name)).into()
+}
+
+fn check_edit(playlist: &Playlist, user: UserId, manager: bool) -> Result<(), AppError> {
+    if playlist.can_edit(user, manager) {
+        Ok(())
+    } else {
+        Err(ErrorKind::PermissionDenied(format!(
+            "change {} without being a collaborator",
+            playlist.name
+        ))
+        .into())
+    }
+}
+
+/// Applies `f` to a playlist the author is allowed to change.
+async fn edit<R>(
+    ctx: Context<'_>,
+    scope: Scope,
+    name: &str,
+    f: impl FnOnce(&mut Playlist) -> R,
+) -> Result<R, AppError> {
+    let manager = is_manager(ctx).await;
+    let user = ctx.author().id;
+    ctx.data()
+        .playlists
+        .update(|playlists| {
+            let playlist = playlists
+                .get_mut(scope, name)
+                .ok_or_else(|| not_found(name))?;
+            check_edit(playlist, user, manager)?;
+            Ok(f(playlist))
+        })
+        .await?
+}
+
+async fn autocomplete_playlist(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let partial = partial.to_lowercase();
+    let playlists = ctx.data().playlists.read().await;
+    let mut names: Vec<_> = playlists.list(Scope::User(ctx.author().id));
+    if let Some(guild_id) = ctx.guild_id() {
+        names.extend(playlists.list(Scope::Guild(guild_id)));
+    }
+    let mut names: Vec<_> = names
+        .into_iter()
+        .map(|p| p.name.clone())
+        .filter(|n| n.to_lowercase().contains(&partial))
+        .collect();
+    names.dedup();
+    names.truncate(25);
+    names
+}
+
+/// Makes a new, empty playlist
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn create(
+    ctx: Context<'_>,
+    #[description = "Name for the playlist"] name: String,
+    #[description = "Share it with the server instead of keeping it to yourself"] server: Option<
+        bool,
+    >,
+) -> Result<(), AppError> {
+    let scope = scope(ctx, server)?;
+    let owner = ctx.author().id;
+    ctx.data()
+        .playlists
+        .update(|playlists| {
+            playlists
+                .create(scope, Playlist::new(name.trim(), owner))
+                .map(|_| ())
+        })
+        .await?
+        .map_err(ErrorKind::InvalidValue)?;
+
+    ctx.say(format!("Made the playlist {}", name.trim()))
+        .await?;
+    Ok(())
+}
+
+/// A queued track as it's saved in a playlist, unless it can't be played again.
+async fn track_from(handle: &TrackHandle) -> Option<PlaylistTrack> {
+    let typemap = handle.typemap().read().await;
+    Some(PlaylistTrack {
+        source: typemap.get::<OriginalSource>()?.clone(),
+        title: title_of(&typemap),
+        duration: typemap.get::<Metadata>().and_then(|m| m.duration),
+    })
+}
+
+/// Adds a song, what's playing or the whole queue to a playlist
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "The playlist to add to"]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "A link to add, or leave empty for what's playing"] song: Option<String>,
+    #[description = "Add everything in the queue instead of just what's playing"]
+    #[rename = "queue"]
+    whole_queue: Option<bool>,
+    #[description = "Add to the server's playlist instead of your own"] server: Option<bool>,
+) -> Result<(), AppError> {
+    let scope = scope(ctx, server)?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or_else(|| anyhow::anyhow!("Not in a guild"))?;
+
+    let tracks = match song {
+        Some(song) => {
+            let source = match find_links(&song).into_iter().next() {
+                Some(link) => {
+                    let http = get_http_client(ctx.serenity_context()).await;
+                    let storefront = ctx
+                        .data()
+                        .settings
+                        .get(guild_id)
+                        .await
+                        .storefront_or(&ctx.data().config.apple_music.storefront);
+                    source_for(&http, &link, &storefront).await?
+                }
+                None => TrackSource::parse(&song, ctx.data().config.media_dir.as_deref())
+                    .map_err(ErrorKind::SourceResolution)?,
+            };
+            vec![PlaylistTrack {
+                source,
+                title: None,
+                duration: None,
+            }]
+        }
+        None => {
+            let manager = songbird::get(ctx.serenity_context())
+                .await
+                .expect("Songbird Voice client placed in at initialisation.")
+                .clone();
+            let call = manager.get(guild_id).ok_or(ErrorKind::NotInVoice)?;
+            let mut handles = call.lock().await.queue().current_queue();
+            if !whole_queue.unwrap_or(false) {
+                handles.truncate(1);
+            }
+            let mut tracks = Vec::new();
+            for handle in &handles {
+                tracks.extend(track_from(handle).await);
+            }
+            tracks
+        }
+    };
+    if tracks.is_empty() {
+        ctx.say("There's nothing playing that I could save").await?;
+        return Ok(());
+    }
+
+    let wanted = tracks.len();
+    let (added, name) = edit(ctx, scope, &name, |playlist| {
+        (playlist.extend(tracks), playlist.name.clone())
+    })
+    .await?;
+
+    let content = match (added, wanted) {
+        (0, _) => format!("{} is full", name),
+        (1, 1) => format!("Added 1 track to {}", name),
+        (added, wanted) if added == wanted => format!("Added {} tracks to {}", added, name),
+        (added, _) => format!("Added {} tracks to {} before it filled up", added, name),
+    };
+    ctx.say(content).await?;
+    Ok(())
+}
+
+/// Takes a track out of a playlist
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "The playlist to remove from"]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "Position of the track, as shown by /playlist show"]
+    #[min = 1]
+    position: usize,
+    #[description = "Use the server's playlist instead of your own"] server: Option<bool>,
+) -> Result<(), AppError> {
+    let scope = scope(ctx, server)?;
+    let removed = edit(ctx, scope, &name, |playlist| {
+        (position >= 1 && position <= playlist.tracks.len())
+            .then(|| (playlist.tracks.remove(position - 1), playlist.name.clone()))
+    })
+    .await?;
+
+    let Some((track, name)) = removed else {
+        return Err(
+            ErrorKind::InvalidValue(format!("there's no track {} in {}", position, name)).into(),
+        );
+    };
+    ctx.say(format!("Removed {} from {}", track.label(), name))
+        .await?;
+    Ok(())
+}
+
+/// Lists your and the server's playlists, or the tracks in one of them
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn show(
+    ctx: Context<'_>,
+    #[description = "The playlist to show, or leave empty for all of them"]
+    #[autocomplete = "autocomplete_playlist"]
+    name: Option<String>,
+    #[description = "Use the server's playlist instead of your own"] server: Option<bool>,
+    #[description = "Page of tracks to show"]
+    #[min = 1]
+    page: Option<usize>,
+) -> Result<(), AppError> {
+    let guild_id = ctx
+        .guild_id()
+        .ok_or_else(|| anyhow::anyhow!("Not in a guild"))?;
+    let playlists = ctx.data().playlists.read().await;
+
+    let Some(name) = name else {
+        let list = |scope| {
+            let names: Vec<_> = playlists
+                .list(scope)
+                .into_iter()
+                .map(|p| format!("{} ({} tracks)", p.name, p.tracks.len()))
+                .collect();
+            match names.is_empty() {
+                true => "none yet".to_owned(),
+                false => names.join("\n"),
+            }
+        };
+        let embed = CreateEmbed::default()
+            .title("Playlists")
+            .field("Yours", list(Scope::User(ctx.author().id)), false)
+            .field("This server's", list(Scope::Guild(guild_id)), false);
+        drop(playlists);
+        ctx.send(CreateReply::default().embed(embed)).await?;
+        return Ok(());
+    };
+
+    let scope = scope(ctx, server)?;
+    let playlist = playlists
+        .get(scope, &name)
+        .cloned()
+        .ok_or_else(|| not_found(&name))?;
+    drop(playlists);
+
+    let pages = playlist.tracks.len().div_ceil(SHOW_TRACKS).max(1);
+    let page = page.unwrap_or(1).clamp(1, pages);
+    let tracks: Vec<_> = playlist
+        .tracks
+        .iter()
+        .enumerate()
+        .skip((page - 1) * SHOW_TRACKS)
+        .take(SHOW_TRACKS)
+        .map(|(i, track)| format!("{}. {}", i + 1, track.label()))
+        .collect();
+    let total: Duration = playlist.tracks.iter().filter_map(|t| t.duration).sum();
+
+    let mut embed = CreateEmbed::default()
+        .title(&playlist.name)
+        .description(if tracks.is_empty() {
+            "No tracks yet".to_owned()
+        } else {
+            tracks.join("\n")
+        })
+        .field("Owner", playlist.owner.mention().to_string(), true)
+        .field(
+            "Tracks",
+            format!(
+                "{} ({})",
+                playlist.tracks.len(),
+                crate::helpers::d2hms(total)
+            ),
+            true,
+        );
+    if !playlist.collaborators.is_empty() {
+        let collaborators: Vec<_> = playlist
+            .collaborators
+            .iter()
+            .map(|c| c.mention().to_string())
+            .collect();
+        embed = embed.field("Collaborators", collaborators.join(", "), true);
+    }
+    if pages > 1 {
+        embed = embed.footer(serenity::CreateEmbedFooter::new(format!(
+            "Page {} of {}",
+            page, pages
+        )));
+    }
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Queues everything in a playlist
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn play(
+    ctx: Context<'_>,
+    #[description = "The playlist to play"]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "Use the server's playlist instead of your own"] server: Option<bool>,
+    #[description = "Play the tracks in a random order"] shuffle: Option<bool>,
+) -> Result<(), AppError> {
+    let scope = scope(ctx, server)?;
+    let mut playlist = ctx
+        .data()
+        .playlists
+        .read()
+        .await
+        .get(scope, &name)
+        .cloned()
+        .ok_or_else(|| not_found(&name))?;
+    if playlist.tracks.is_empty() {
+        ctx.say(format!("{} is empty", playlist.name)).await?;
+        return Ok(());
+    }
+    if shuffle.unwrap_or(false) {
+        playlist.tracks.shuffle(&mut rand::thread_rng());
+    }
+
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let (guild_id, channel_id) = crate::voice::guild_info(ctx).await?;
+    let Ok(call) = get_or_join_call(&manager, ctx, guild_id, channel_id).await else {
+        return Err(ErrorKind::NotInVoice.into());
+    };
+    ctx.defer().await?;
+
+    let tracks = playlist.tracks.into_iter().map(|t| t.source).collect();
+    let content = queue_list(ctx, guild_id, call, playlist.name, tracks).await;
+    ctx.say(content).await?;
+    Ok(())
+}
+
+/// Deletes one of your playlists, or one of the server's you made
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn delete(
+    ctx: Context<'_>,
+    #[description = "The playlist to delete"]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "Use the server's playlist instead of your own"] server: Option<bool>,
+) -> Result<(), AppError> {
+    let scope = scope(ctx, server)?;
+    let manager = is_manager(ctx).await;
+    let user = ctx.author().id;
+    let deleted = ctx
+        .data()
+        .playlists
+        .update(|playlists| {
+            let playlist = playlists
+                .get(scope, &name)
+                .ok_or_else(|| not_found(&name))?;
+            if playlist.owner != user && !manager {
+                return Err(ErrorKind::PermissionDenied(format!(
+                    "delete {}, which isn't yours",
+                    playlist.name
+                ))
+                .into());
+            }
+            Ok::<_, AppError>(playlists.remove(scope, &name))
+        })
+        .await??;
+
+    if let Some(deleted) = deleted {
+        ctx.say(format!("Deleted {}", deleted.name)).await?;
+    }
+    Ok(())
+}
+
+/// Imports an Apple Music playlist, or a JSON or M3U file
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn import(
+    ctx: Context<'_>,
+    #[description = "An Apple Music playlist link"] url: Option<String>,
+    #[description = "A JSON or M3U playlist file"] file: Option<Attachment>,
+    #[description = "Name for the playlist, or an existing one to add to"]
+    #[autocomplete = "autocomplete_playlist"]
+    name: Option<String>,
+    #[description = "Import into the server's playlists instead of your own"] server: Option<bool>,
+) -> Result<(), AppError> {
+    let scope = scope(ctx, server)?;
+    ctx.defer().await?;
+
+    let (found_name, tracks, skipped) = if let Some(url) = url {
+        let link = parse_playlist_url(url.trim()).ok_or_else(|| {
+            ErrorKind::InvalidValue("that isn't an Apple Music playlist link".to_owned())
+        })?;
+        let songs = playlist_tracks(&link.storefront, &link.id)
+            .await
+            .map_err(ErrorKind::Catalog)?;
+        let tracks: Vec<_> = songs
+            .into_iter()
+            .filter_map(|song| {
+                let attributes = song.attributes?;
+                let title = attributes.name?;
+                let query = match &attributes.artist_name {
+                    Some(artist) => format!("{} - {}", artist, title),
+                    None => title.clone(),
+                };
+                Some(PlaylistTrack {
+                    source: TrackSource::search(&query),
+                    title: Some(title),
+                    duration: attributes
+                        .duration_in_millis
+                        .and_then(|ms| u64::try_from(ms).ok())
+                        .map(Duration::from_millis),
+                })
+            })
+            .collect();
+        (Some(link.name), tracks, 0)
+    } else if let Some(file) = file {
+        if file.size > MAX_IMPORT_BYTES {
+            return Err(ErrorKind::InvalidValue(
+                "that file is too big to be a playlist".to_owned(),
+            )
+            .into());
+        }
+        let bytes = file.download().await?;
+        let text = String::from_utf8(bytes)
+            .map_err(|_| ErrorKind::InvalidValue("that file isn't text".to_owned()))?;
+        let imported = import_file(&text, ctx.data().config.media_dir.as_deref())
+            .map_err(|e| ErrorKind::InvalidValue(e.to_string()))?;
+        let name = imported.name.or_else(|| {
+            file.filename
+                .rsplit_once('.')
+                .map(|(stem, _)| stem.to_owned())
+        });
+        (name, imported.tracks, imported.skipped)
+    } else {
+        ctx.say("Give me an Apple Music playlist link or a playlist file")
+            .await?;
+        return Ok(());
+    };
+
+    let name = name.or(found_name).unwrap_or_else(|| "Imported".to_owned());
+    let manager = is_manager(ctx).await;
+    let user = ctx.author().id;
+    let wanted = tracks.len();
+    // in one go, so a playlist made meanwhile by the same name isn't mistaken for ours
+    let (added, name) = ctx
+        .data()
+        .playlists
+        .update(|playlists| {
+            if playlists.get(scope, &name).is_none() {
+                playlists
+                    .create(scope, Playlist::new(name.trim(), user))
+                    .map_err(ErrorKind::InvalidValue)?;
+            }
+            let playlist = playlists
+                .get_mut(scope, &name)
+                .ok_or_else(|| not_found(&name))?;
+            check_edit(playlist, user, manager)?;
+            Ok::<_, AppError>((playlist.extend(tracks), playlist.name.clone()))
+        })
+        .await??;
+
+    let mut content = format!("Imported {} tracks into {}", added, name);
+    if added < wanted {
+        content.push_str(&format!(", {} didn't fit", wanted - added));
+    }
+    if skipped > 0 {
+        content.push_str(&format!(", {} couldn't be read", skipped));
+    }
+    ctx.say(content).await?;
+    Ok(())
+}
+
+/// Sends a playlist as a JSON or M3U file
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn export(
+    ctx: Context<'_>,
+    #[description = "The playlist to export"]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "File format, JSON by default"] format: Option<Format>,
+    #[description = "Use the server's playlist instead of your own"] server: Option<bool>,
+) -> Result<(), AppError> {
+    let scope = scope(ctx, server)?;
+    let playlist = ctx
+        .data()
+        .playlists
+        .read()
+        .await
+        .get(scope, &name)
+        .cloned()
+        .ok_or_else(|| not_found(&name))?;
+
+    let media_dir = ctx.data().config.media_dir.as_deref();
+    let (text, extension) = match format.unwrap_or(Format::Json) {
+        Format::Json => (to_json(&playlist, media_dir), "json"),
+        Format::M3u => (to_m3u(&playlist, media_dir), "m3u"),
+    };
+    let file_name: String = playlist
+        .name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let attachment =
+        CreateAttachment::bytes(text.into_bytes(), format!("{}.{}", file_name, extension));
+
+    ctx.send(
+        CreateReply::default()
+            .content(format!("Here's {}", playlist.name))
+            .attachment(attachment),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Lets someone else change one of the server's playlists, or stops them
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn collaborator(
+    ctx: Context<'_>,
+    #[description = "The server playlist to share"]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "Who to share it with"] user: serenity::User,
+    #[description = "Stop them changing it instead"] remove: Option<bool>,
+) -> Result<(), AppError> {
+    let scope = scope(ctx, Some(true))?;
+    let manager = is_manager(ctx).await;
+    let author = ctx.author().id;
+    let remove = remove.unwrap_or(false);
+
+    let name = ctx
+        .data()
+        .playlists
+        .update(|playlists| {
+            let playlist = playlists
+                .get_mut(scope, &name)
+                .ok_or_else(|| not_found(&name))?;
+            if playlist.owner != author && !manager {
+                return Err(ErrorKind::PermissionDenied(format!(
+                    "share {}, which isn't yours",
+                    playlist.name
+                ))
+                .into());
+            }
+            playlist.collaborators.retain(|c| *c != user.id);
+            if !remove {
+                playlist.collaborators.push(user.id);
+            }
+            Ok::<_, AppError>(playlist.name.clone())
+        })
+        .await??;
+
+    let content = if remove {
+        format!("{} can't change {} any more", user.mention(), name)
+    } else {
+        format!("{} can now change {}", user.mention(), name)
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(content)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
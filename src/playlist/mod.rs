@@ -0,0 +1,393 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, UserId};
+
+use crate::{store::JsonStore, voice::source::TrackSource};
+
+pub mod command;
+
+/// The most tracks a playlist can hold.
+pub const MAX_TRACKS: usize = 1000;
+/// The longest a playlist name can be.
+const MAX_NAME: usize = 100;
+
+/// Whose playlists to look in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    User(UserId),
+    /// Shared with everyone in a server.
+    Guild(GuildId),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Playlist {
+    pub name: String,
+    /// Who made it, and the only one who can share or delete a server playlist.
+    pub owner: UserId,
+    /// People besides the owner who can change a server playlist.
+    #[serde(default)]
+    pub collaborators: Vec<UserId>,
+    pub tracks: Vec<PlaylistTrack>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistTrack {
+    pub source: TrackSource,
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl PlaylistTrack {
+    /// The title, or where the track comes from when it doesn't have one.
+    pub fn label(&self) -> String {
+        self.title
+            .clone()
+            .unwrap_or_else(|| location(&self.source, None))
+    }
+}
+
+impl Playlist {
+    pub fn new(name: &str, owner: UserId) -> Self {
+        Self {
+            name: name.to_owned(),
+            owner,
+            collaborators: Vec::new(),
+            tracks: Vec::new(),
+        }
+    }
+
+    /// Whether `user` can add and remove tracks. Anyone can change their own playlists, and server
+    /// managers can change any of the server's.
+    pub fn can_edit(&self, user: UserId, manager: bool) -> bool {
+        manager || self.owner == user || self.collaborators.contains(&user)
+    }
+
+    /// Adds as many of `tracks` as there's room for, returning how many that was.
+    pub fn extend(&mut self, tracks: impl IntoIterator<Item = PlaylistTrack>) -> usize {
+        let room = MAX_TRACKS.saturating_sub(self.tracks.len());
+        let before = self.tracks.len();
+        self.tracks.extend(tracks.into_iter().take(room));
+        self.tracks.len() - before
+    }
+}
+
+pub type PlaylistStore = JsonStore<Playlists>;
+
+/// Everyone's playlists, saved to `playlists.json`. Names are case insensitive.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Playlists {
+    users: HashMap<UserId, BTreeMap<String, Playlist>>,
+    guilds: HashMap<GuildId, BTreeMap<String, Playlist>>,
+}
+
+impl Playlists {
+    pub fn list(&self, scope: Scope) -> Vec<&Playlist> {
+        self.scope(scope)
+            .map(|playlists| playlists.values().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, scope: Scope, name: &str) -> Option<&Playlist> {
+        self.scope(scope)?.get(&key(name))
+    }
+
+    pub fn get_mut(&mut self, scope: Scope, name: &str) -> Option<&mut Playlist> {
+        self.scope_mut(scope).get_mut(&key(name))
+    }
+
+    /// Adds a new playlist, unless there's already one with that name.
+    pub fn create(&mut self, scope: Scope, playlist: Playlist) -> Result<&mut Playlist, String> {
+        let name = playlist.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME {
+            return Err(format!(
+                "playlist names have to be 1 to {} characters",
+                MAX_NAME
+            ));
+        }
+        let playlists = self.scope_mut(scope);
+        let key = key(name);
+        if playlists.contains_key(&key) {
+            return Err(format!("there's already a playlist called {}", name));
+        }
+        Ok(playlists.entry(key).or_insert(playlist))
+    }
+
+    pub fn remove(&mut self, scope: Scope, name: &str) -> Option<Playlist> {
+        let playlists = self.scope_mut(scope);
+        let removed = playlists.remove(&key(name));
+        if playlists.is_empty() {
+            match scope {
+                Scope::User(user) => self.users.remove(&user),
+                Scope::Guild(guild) => self.guilds.remove(&guild),
+            };
+        }
+        removed
+    }
+
+    fn scope(&self, scope: Scope) -> Option<&BTreeMap<String, Playlist>> {
+        match scope {
+            Scope::User(user) => self.users.get(&user),
+            Scope::Guild(guild) => self.guilds.get(&guild),
+        }
+    }
+
+    fn scope_mut(&mut self, scope: Scope) -> &mut BTreeMap<String, Playlist> {
+        match scope {
+            Scope::User(user) => self.users.entry(user).or_default(),
+            Scope::Guild(guild) => self.guilds.entry(guild).or_default(),
+        }
+    }
+}
+
+fn key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Where a track comes from, as written in an exported playlist. Local files are written relative
+/// to the media directory, so the playlist still works if it moves.
+pub fn location(source: &TrackSource, media_dir: Option<&Path>) -> String {
+    match source {
+        TrackSource::YoutubeDl(url) | TrackSource::Http { url, .. } => url.clone(),
+        TrackSource::Local(path) => media_dir
+            .and_then(|dir| path.strip_prefix(dir.canonicalize().ok()?).ok())
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+/// Reads a location back, the other way around from [`location`].
+pub fn parse_location(location: &str, media_dir: Option<&Path>) -> Result<TrackSource> {
+    match location.strip_prefix("ytsearch1:") {
+        Some(query) => Ok(TrackSource::search(query)),
+        None => TrackSource::parse(location, media_dir),
+    }
+}
+
+/// A playlist as it's exported, without anything that only makes sense to this bot.
+#[derive(Debug, Serialize, Deserialize)]
+struct Exported {
+    name: String,
+    tracks: Vec<ExportedTrack>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedTrack {
+    location: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    /// In seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration: Option<u64>,
+}
+
+pub fn to_json(playlist: &Playlist, media_dir: Option<&Path>) -> String {
+    let exported = Exported {
+        name: playlist.name.clone(),
+        tracks: playlist
+            .tracks
+            .iter()
+            .map(|track| ExportedTrack {
+                location: location(&track.source, media_dir),
+                title: track.title.clone(),
+                duration: track.duration.map(|d| d.as_secs()),
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&exported).expect("playlists always serialize")
+}
+
+pub fn to_m3u(playlist: &Playlist, media_dir: Option<&Path>) -> String {
+    let mut out = format!("#EXTM3U\n#PLAYLIST:{}\n", playlist.name);
+    for track in &playlist.tracks {
+        if let Some(title) = &track.title {
+            let duration = track.duration.map_or(-1, |d| d.as_secs() as i64);
+            out.push_str(&format!(
+                "#EXTINF:{},{}\n",
+                duration,
+                title.replace('\n', " ")
+            ));
+        }
+        out.push_str(&location(&track.source, media_dir));
+        out.push('\n');
+    }
+    out
+}
+
+/// What came out of an imported file: its name, if it had one, and every track that could be
+/// read, along with how many couldn't.
+pub struct Imported {
+    pub name: Option<String>,
+    pub tracks: Vec<PlaylistTrack>,
+    pub skipped: usize,
+}
+
+/// Reads a JSON or M3U playlist, whichever `text` looks like.
+pub fn import(text: &str, media_dir: Option<&Path>) -> Result<Imported> {
+    if text.trim_start().starts_with('{') {
+        from_json(text, media_dir)
+    } else {
+        Ok(from_m3u(text, media_dir))
+    }
+}
+
+fn from_json(text: &str, media_dir: Option<&Path>) -> Result<Imported> {
+    let exported: Exported =
+        serde_json::from_str(text).map_err(|e| anyhow!("that isn't a playlist: {}", e))?;
+    let mut imported = Imported {
+        name: Some(exported.name),
+        tracks: Vec::new(),
+        skipped: 0,
+    };
+    for track in exported.tracks {
+        match parse_location(&track.location, media_dir) {
+            Ok(source) => imported.tracks.push(PlaylistTrack {
+                source,
+                title: track.title,
+                duration: track.duration.map(Duration::from_secs),
+            }),
+            Err(_) => imported.skipped += 1,
+        }
+    }
+    Ok(imported)
+}
+
+fn from_m3u(text: &str, media_dir: Option<&Path>) -> Imported {
+    let mut imported = Imported {
+        name: None,
+        tracks: Vec::new(),
+        skipped: 0,
+    };
+    let mut info: Option<(Option<Duration>, String)> = None;
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            imported.name = Some(name.trim().to_owned());
+        } else if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            info = extinf.split_once(',').map(|(duration, title)| {
+                let duration = duration.trim().parse::<u64>().ok().map(Duration::from_secs);
+                (duration, title.trim().to_owned())
+            });
+        } else if line.starts_with('#') {
+            continue;
+        } else {
+            let (duration, title) = info.take().unzip();
+            match parse_location(line, media_dir) {
+                Ok(source) => imported.tracks.push(PlaylistTrack {
+                    source,
+                    title,
+                    duration: duration.flatten(),
+                }),
+                Err(_) => imported.skipped += 1,
+            }
+        }
+    }
+    imported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist() -> Playlist {
+        let mut playlist = Playlist::new("Road Trip", UserId::new(1));
+        playlist.tracks = vec![
+            PlaylistTrack {
+                source: TrackSource::YoutubeDl("https://youtu.be/a".to_owned()),
+                title: Some("Song A".to_owned()),
+                duration: Some(Duration::from_secs(200)),
+            },
+            PlaylistTrack {
+                source: TrackSource::search("Artist - Song B"),
+                title: None,
+                duration: None,
+            },
+            PlaylistTrack {
+                source: TrackSource::Http {
+                    url: "https://example.com/c.mp3".to_owned(),
+                    extension: "mp3".to_owned(),
+                },
+                title: Some("Song C".to_owned()),
+                duration: None,
+            },
+        ];
+        playlist
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let playlist = playlist();
+        let imported = import(&to_json(&playlist, None), None).unwrap();
+        assert_eq!(imported.name.as_deref(), Some("Road Trip"));
+        assert_eq!(imported.tracks, playlist.tracks);
+        assert_eq!(imported.skipped, 0);
+    }
+
+    #[test]
+    fn m3u_round_trips() {
+        let playlist = playlist();
+        let m3u = to_m3u(&playlist, None);
+        assert!(m3u.starts_with("#EXTM3U\n#PLAYLIST:Road Trip\n#EXTINF:200,Song A\n"));
+        assert!(m3u.contains("#EXTINF:-1,Song C\n"));
+
+        let imported = import(&m3u, None).unwrap();
+        assert_eq!(imported.name.as_deref(), Some("Road Trip"));
+        assert_eq!(imported.tracks, playlist.tracks);
+    }
+
+    #[test]
+    fn skips_what_it_cannot_play() {
+        let imported = import(
+            "#EXTM3U\n#EXTINF:10,Local\n/music/song.mp3\nhttps://youtu.be/b\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(imported.skipped, 1);
+        assert_eq!(imported.tracks.len(), 1);
+        assert_eq!(imported.tracks[0].title, None);
+    }
+
+    #[test]
+    fn names_are_case_insensitive_and_unique() {
+        let mut playlists = Playlists::default();
+        let scope = Scope::User(UserId::new(1));
+        playlists.create(scope, playlist()).unwrap();
+        assert!(playlists.get(scope, "road trip").is_some());
+        assert!(playlists
+            .create(scope, Playlist::new("ROAD TRIP", UserId::new(1)))
+            .is_err());
+        assert!(playlists
+            .create(scope, Playlist::new(" ", UserId::new(1)))
+            .is_err());
+
+        assert!(playlists
+            .get(Scope::Guild(GuildId::new(1)), "road trip")
+            .is_none());
+        assert!(playlists.remove(scope, "Road Trip").is_some());
+        assert!(playlists.list(scope).is_empty());
+    }
+
+    #[test]
+    fn collaborators_can_edit() {
+        let mut playlist = playlist();
+        playlist.collaborators.push(UserId::new(2));
+        assert!(playlist.can_edit(UserId::new(1), false));
+        assert!(playlist.can_edit(UserId::new(2), false));
+        assert!(!playlist.can_edit(UserId::new(3), false));
+        assert!(playlist.can_edit(UserId::new(3), true));
+    }
+
+    #[test]
+    fn stops_at_the_track_limit() {
+        let mut playlist = playlist();
+        let track = playlist.tracks[0].clone();
+        let added = playlist.extend(std::iter::repeat_n(track, MAX_TRACKS));
+        assert_eq!(added, MAX_TRACKS - 3);
+        assert_eq!(playlist.tracks.len(), MAX_TRACKS);
+    }
+}
@@ -0,0 +1,65 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use serenity::all::GuildId;
+use songbird::Songbird;
+use tokio::net::TcpListener;
+use tracing::info;
+
+use crate::metrics::metrics;
+
+/// Serves health checks and metrics, and the dashboard if there is one, until the process exits.
+pub async fn serve(
+    addr: SocketAddr,
+    manager: Arc<Songbird>,
+    dashboard: Option<Router>,
+) -> std::io::Result<()> {
+    let mut app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(prometheus))
+        .with_state(manager);
+    if let Some(dashboard) = dashboard {
+        app = app.merge(dashboard);
+    }
+
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving health checks and metrics on {}", addr);
+    axum::serve(listener, app).await
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn readyz() -> (StatusCode, String) {
+    let waiting = metrics().not_ready();
+    if waiting.is_empty() {
+        (StatusCode::OK, "ready".to_owned())
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("waiting for {}", waiting.join(", ")),
+        )
+    }
+}
+
+async fn prometheus(State(manager): State<Arc<Songbird>>) -> impl IntoResponse {
+    let handlers: Vec<_> = manager.iter().collect();
+    let mut calls = Vec::with_capacity(handlers.len());
+    for (guild_id, call) in handlers {
+        let len = call.lock().await.queue().len();
+        calls.push((GuildId::new(guild_id.0.get()), len));
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics().render(&calls),
+    )
+}
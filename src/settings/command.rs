@@ -0,0 +1,233 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{AutocompleteChoice, ChannelType, CreateEmbed, ResolvedValue};
+
+use crate::{
+    err::{AppError, ErrorKind},
+    voice::source::SourceKind,
+    Context,
+};
+
+use super::Setting;
+
+const STOREFRONTS: &[&str] = &[
+    "us", "gb", "ca", "au", "nz", "ie", "de", "fr", "nl", "se", "jp", "br", "mx", "in",
+];
+
+/// Shows or changes how the bot behaves in this server
+#[poise::command(
+    category = "Settings",
+    slash_command,
+    prefix_command,
+    subcommands("get", "set", "reset"),
+    subcommand_required,
+    guild_only
+)]
+pub async fn config(_ctx: Context<'_>) -> Result<(), AppError> {
+    Ok(())
+}
+
+fn parse_setting(name: &str) -> Result<Setting, AppError> {
+    Setting::from_name(name).ok_or_else(|| {
+        ErrorKind::InvalidValue(format!(
+            "{} isn't a setting, try any of {}",
+            name,
+            Setting::ALL.map(|s| s.name()).join(", ")
+        ))
+        .into()
+    })
+}
+
+async fn autocomplete_setting(_ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let partial = partial.to_lowercase();
+    Setting::ALL
+        .into_iter()
+        .filter(|s| s.name().contains(&partial))
+        .map(|s| AutocompleteChoice::new(format!("{} - {}", s.name(), s.description()), s.name()))
+        .collect()
+}
+
+/// Suggests values for whichever setting was picked in the same command.
+async fn autocomplete_value(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let poise::Context::Application(app) = ctx else {
+        return Vec::new();
+    };
+    let Some(setting) = app
+        .args
+        .iter()
+        .find_map(|arg| match (arg.name, &arg.value) {
+            ("setting", ResolvedValue::String(name)) => Setting::from_name(name),
+            _ => None,
+        })
+    else {
+        return Vec::new();
+    };
+    let partial = partial.to_lowercase();
+    let matching = |options: &[&str]| -> Vec<AutocompleteChoice> {
+        options
+            .iter()
+            .filter(|o| o.starts_with(&partial))
+            .map(|o| AutocompleteChoice::from(*o))
+            .collect()
+    };
+
+    match setting {
+        Setting::AnnounceChannel | Setting::ListenChannel | Setting::RecapChannel => {
+            let Some(guild) = ctx.guild() else {
+                return Vec::new();
+            };
+            let mut channels: Vec<_> = guild
+                .channels
+                .values()
+                .filter(|c| c.kind == ChannelType::Text && c.name.contains(&partial))
+                .map(|c| AutocompleteChoice::new(format!("#{}", c.name), format!("<#{}>", c.id)))
+                .collect();
+            channels.insert(0, AutocompleteChoice::from("none"));
+            channels
+        }
+        Setting::DjRole => {
+            let Some(guild) = ctx.guild() else {
+                return Vec::new();
+            };
+            let mut roles: Vec<_> = guild
+                .roles
+                .values()
+                .filter(|r| !r.managed && r.id.get() != guild.id.get())
+                .filter(|r| r.name.to_lowercase().contains(&partial))
+                .map(|r| AutocompleteChoice::new(format!("@{}", r.name), format!("<@&{}>", r.id)))
+                .collect();
+            roles.insert(0, AutocompleteChoice::from("none"));
+            roles
+        }
+        Setting::DefaultVolume => matching(&["50", "75", "100", "125", "150"]),
+        Setting::Storefront => matching(STOREFRONTS),
+        Setting::IdleTimeout => matching(&["0", "1", "5", "15", "30", "60"]),
+        Setting::MaxQueue => matching(&["0", "25", "50", "100", "250"]),
+        Setting::Announce => matching(&["off", "compact", "full"]),
+        Setting::Crossfade => matching(&["0", "3", "6", "9", "12"]),
+        Setting::ListenQueue | Setting::Gapless => matching(&["on", "off"]),
+        Setting::AllowedSources => {
+            // complete the last name in the list
+            let (done, last) = match partial.rfind([',', ' ']) {
+                Some(i) => (partial[..=i].to_owned(), &partial[i + 1..]),
+                None => (String::new(), partial.as_str()),
+            };
+            let mut choices: Vec<_> = SourceKind::ALL
+                .iter()
+                .map(|s| s.name())
+                .filter(|name| name.starts_with(last) && !done.contains(name))
+                .map(|name| AutocompleteChoice::from(format!("{}{}", done, name)))
+                .collect();
+            if done.is_empty() && "all".starts_with(last) {
+                choices.insert(0, AutocompleteChoice::from("all"));
+            }
+            choices
+        }
+    }
+}
+
+/// Shows one or every setting
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn get(
+    ctx: Context<'_>,
+    #[description = "The setting to show, or leave empty for all of them"]
+    #[autocomplete = "autocomplete_setting"]
+    setting: Option<String>,
+) -> Result<(), AppError> {
+    let guild_id = ctx
+        .guild_id()
+        .ok_or_else(|| anyhow::anyhow!("Not in a guild"))?;
+    let settings = ctx.data().settings.get(guild_id).await;
+
+    let shown = match setting {
+        Some(name) => vec![parse_setting(&name)?],
+        None => Setting::ALL.to_vec(),
+    };
+    let mut embed = CreateEmbed::default().title("Settings");
+    for setting in shown {
+        embed = embed.field(setting.name(), setting.show(&settings), true);
+    }
+
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Changes a setting
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set(
+    ctx: Context<'_>,
+    #[description = "The setting to change"]
+    #[autocomplete = "autocomplete_setting"]
+    setting: String,
+    #[description = "Its new value, or none to unset it"]
+    #[autocomplete = "autocomplete_value"]
+    #[rest]
+    value: String,
+) -> Result<(), AppError> {
+    let guild_id = ctx
+        .guild_id()
+        .ok_or_else(|| anyhow::anyhow!("Not in a guild"))?;
+    let setting = parse_setting(&setting)?;
+
+    let (settings, result) = ctx
+        .data()
+        .settings
+        .update(guild_id, |settings| setting.set(settings, &value))
+        .await?;
+    result.map_err(ErrorKind::InvalidValue)?;
+
+    ctx.say(format!(
+        "Set {} to {}",
+        setting.name(),
+        setting.show(&settings)
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Puts one or every setting back to its default
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn reset(
+    ctx: Context<'_>,
+    #[description = "The setting to reset, or leave empty for all of them"]
+    #[autocomplete = "autocomplete_setting"]
+    setting: Option<String>,
+) -> Result<(), AppError> {
+    let guild_id = ctx
+        .guild_id()
+        .ok_or_else(|| anyhow::anyhow!("Not in a guild"))?;
+
+    match setting {
+        Some(name) => {
+            let setting = parse_setting(&name)?;
+            let (settings, _) = ctx
+                .data()
+                .settings
+                .update(guild_id, |settings| setting.reset(settings))
+                .await?;
+            ctx.say(format!(
+                "Reset {} to {}",
+                setting.name(),
+                setting.show(&settings)
+            ))
+            .await?;
+        }
+        None => {
+            ctx.data()
+                .settings
+                .update(guild_id, |settings| *settings = Default::default())
+                .await?;
+            ctx.say("Reset every setting to its default").await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,550 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, RoleId};
+
+use crate::{
+    helpers::s2hms,
+    store::JsonStore,
+    voice::{announce::AnnounceMode, crossfade::MAX_CROSSFADE, source::SourceKind},
+};
+
+pub mod command;
+
+/// The longest idle timeout, in minutes.
+const MAX_IDLE_TIMEOUT: u32 = 24 * 60;
+const MAX_QUEUE: usize = 1000;
+
+/// Everything a server can configure about the bot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    /// Where track announcements go, instead of the channel music was started from.
+    pub announce_channel: Option<ChannelId>,
+    /// How new tracks are announced.
+    pub announce: AnnounceMode,
+    /// When set, only members with this role can control playback.
+    pub dj_role: Option<RoleId>,
+    /// Volume in percent the bot plays at, picked with `/volume` or `/config`.
+    pub default_volume: u16,
+    /// Whether to level tracks using their loudness metadata.
+    pub normalize: bool,
+    /// Seconds the end of a track overlaps the start of the next, 0 to turn off.
+    pub crossfade: u8,
+    /// Whether to skip the crossfade between consecutive tracks of the same album.
+    pub gapless: bool,
+    /// Apple Music storefront used for catalog lookups, the bot's default when unset.
+    pub storefront: Option<String>,
+    /// Minutes with nothing playing before the bot leaves the call, 0 to stay forever.
+    pub idle_timeout: u32,
+    /// How many tracks the queue can hold.
+    pub max_queue: Option<usize>,
+    /// Whether upcoming tracks take turns between requesters.
+    pub fair_queue: bool,
+    /// How many tracks each person can have queued at once.
+    pub max_per_user: Option<usize>,
+    /// The longest track anyone can queue, in minutes.
+    pub max_track_minutes: Option<u32>,
+    /// Where tracks can be played from.
+    pub allowed_sources: Vec<SourceKind>,
+    /// Where song links people post get a card with the song on every platform.
+    pub listen_channel: Option<ChannelId>,
+    /// Whether links posted in the listen channel are also queued while the bot is in a call.
+    pub listen_queue: bool,
+    /// Where a recap of the week's listening is posted every week.
+    pub recap_channel: Option<ChannelId>,
+}
+
+impl Default for GuildSettings {
+    fn default() -> Self {
+        Self {
+            announce_channel: None,
+            announce: AnnounceMode::default(),
+            dj_role: None,
+            default_volume: 100,
+            normalize: false,
+            crossfade: 0,
+            gapless: false,
+            storefront: None,
+            idle_timeout: 5,
+            max_queue: None,
+            fair_queue: false,
+            max_per_user: None,
+            max_track_minutes: None,
+            allowed_sources: SourceKind::ALL.to_vec(),
+            listen_channel: None,
+            listen_queue: false,
+            recap_channel: None,
+        }
+    }
+}
+
+/// Every server's settings, saved to `settings.json`.
+pub struct Settings {
+    store: JsonStore<HashMap<GuildId, GuildSettings>>,
+}
+
+impl Settings {
+    pub fn open(data_dir: &Path) -> Self {
+        Self {
+            store: JsonStore::open(data_dir, "settings"),
+        }
+    }
+
+    pub async fn get(&self, guild_id: GuildId) -> GuildSettings {
+        self.store
+            .read()
+            .await
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Applies `f` to the guild's settings, saves them and returns the updated copy.
+    pub async fn update<R>(
+        &self,
+        guild_id: GuildId,
+        f: impl FnOnce(&mut GuildSettings) -> R,
+    ) -> Result<(GuildSettings, R)> {
+        self.store
+            .update(|guilds| {
+                let settings = guilds.entry(guild_id).or_default();
+                let out = f(settings);
+                let settings = settings.clone();
+                if settings == GuildSettings::default() {
+                    guilds.remove(&guild_id);
+                }
+                (settings, out)
+            })
+            .await
+    }
+}
+
+/// A single setting, as named in `/config`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Setting {
+    AnnounceChannel,
+    Announce,
+    DjRole,
+    DefaultVolume,
+    Crossfade,
+    Gapless,
+    Storefront,
+    IdleTimeout,
+    MaxQueue,
+    AllowedSources,
+    ListenChannel,
+    ListenQueue,
+    RecapChannel,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 13] = [
+        Setting::AnnounceChannel,
+        Setting::Announce,
+        Setting::DjRole,
+        Setting::DefaultVolume,
+        Setting::Crossfade,
+        Setting::Gapless,
+        Setting::Storefront,
+        Setting::IdleTimeout,
+        Setting::MaxQueue,
+        Setting::AllowedSources,
+        Setting::ListenChannel,
+        Setting::ListenQueue,
+        Setting::RecapChannel,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Setting::AnnounceChannel => "announce-channel",
+            Setting::Announce => "announce",
+            Setting::DjRole => "dj-role",
+            Setting::DefaultVolume => "default-volume",
+            Setting::Crossfade => "crossfade",
+            Setting::Gapless => "gapless",
+            Setting::Storefront => "storefront",
+            Setting::IdleTimeout => "idle-timeout",
+            Setting::MaxQueue => "max-queue",
+            Setting::AllowedSources => "allowed-sources",
+            Setting::ListenChannel => "listen-channel",
+            Setting::ListenQueue => "listen-queue",
+            Setting::RecapChannel => "recap-channel",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase().replace(['_', ' '], "-");
+        Self::ALL.into_iter().find(|s| s.name() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Setting::AnnounceChannel => "Channel new tracks are announced in",
+            Setting::Announce => "How new tracks are announced: off, compact or full",
+            Setting::DjRole => "Role needed to control playback",
+            Setting::DefaultVolume => "Volume in percent, 0-200",
+            Setting::Crossfade => "Seconds to fade between tracks, 0 to turn off",
+            Setting::Gapless => "Whether tracks from the same album play back to back unfaded",
+            Setting::Storefront => "Two letter Apple Music storefront, like us or gb",
+            Setting::IdleTimeout => "Minutes to wait with nothing playing before leaving",
+            Setting::MaxQueue => "Most tracks the queue can hold",
+            Setting::AllowedSources => "Where tracks can be played from",
+            Setting::ListenChannel => "Channel where posted song links get every platform's link",
+            Setting::ListenQueue => "Whether links posted in the listen channel are queued too",
+            Setting::RecapChannel => "Channel the weekly listening recap is posted in",
+        }
+    }
+
+    /// The setting's current value, for people to read.
+    pub fn show(&self, settings: &GuildSettings) -> String {
+        match self {
+            Setting::AnnounceChannel => match settings.announce_channel {
+                Some(channel) => format!("<#{}>", channel),
+                None => "wherever music was started".to_owned(),
+            },
+            Setting::Announce => settings.announce.describe().to_owned(),
+            Setting::DjRole => match settings.dj_role {
+                Some(role) => format!("<@&{}>", role),
+                None => "none, anyone listening can control playback".to_owned(),
+            },
+            Setting::DefaultVolume => format!("{}%", settings.default_volume),
+            Setting::Crossfade => match settings.crossfade {
+                0 => "off".to_owned(),
+                n => format!("{} seconds", n),
+            },
+            Setting::Gapless => if settings.gapless { "on" } else { "off" }.to_owned(),
+            Setting::Storefront => match &settings.storefront {
+                Some(storefront) => storefront.clone(),
+                None => "the bot's default".to_owned(),
+            },
+            Setting::IdleTimeout => match settings.idle_timeout {
+                0 => "never leave".to_owned(),
+                n => s2hms(n as u64 * 60),
+            },
+            Setting::MaxQueue => match settings.max_queue {
+                Some(n) => format!("{} tracks", n),
+                None => "unlimited".to_owned(),
+            },
+            Setting::AllowedSources => settings
+                .allowed_sources
+                .iter()
+                .map(|s| s.name())
+                .collect::<Vec<_>>()
+                .join(", "),
+            Setting::ListenChannel => match settings.listen_channel {
+                Some(channel) => format!("<#{}>", channel),
+                None => "none".to_owned(),
+            },
+            Setting::ListenQueue => {
+                if settings.listen_queue {
+                    "on, while I'm in a call".to_owned()
+                } else {
+                    "off".to_owned()
+                }
+            }
+            Setting::RecapChannel => match settings.recap_channel {
+                Some(channel) => format!("<#{}>", channel),
+                None => "none, no weekly recap".to_owned(),
+            },
+        }
+    }
+
+    /// Parses `value` and stores it, or explains what's wrong with it.
+    pub fn set(&self, settings: &mut GuildSettings, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        let unset = matches!(value.to_lowercase().as_str(), "none" | "off" | "");
+
+        match self {
+            Setting::AnnounceChannel => {
+                settings.announce_channel = if unset {
+                    None
+                } else {
+                    Some(ChannelId::new(parse_id(value, "<#")?))
+                };
+            }
+            Setting::Announce => {
+                settings.announce = match value.to_lowercase().as_str() {
+                    _ if unset => AnnounceMode::Off,
+                    "compact" | "line" => AnnounceMode::Compact,
+                    "full" | "card" => AnnounceMode::Full,
+                    _ => return Err("announce is either off, compact or full".to_owned()),
+                };
+            }
+            Setting::DjRole => {
+                settings.dj_role = if unset {
+                    None
+                } else {
+                    Some(RoleId::new(parse_id(value, "<@&")?))
+                };
+            }
+            Setting::DefaultVolume => {
+                let volume = value
+                    .trim_end_matches('%')
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|v| *v <= 200)
+                    .ok_or("the volume has to be a number from 0 to 200")?;
+                settings.default_volume = volume;
+            }
+            Setting::Crossfade => {
+                settings.crossfade = if unset {
+                    0
+                } else {
+                    value
+                        .trim_end_matches('s')
+                        .parse()
+                        .ok()
+                        .filter(|s| *s <= MAX_CROSSFADE)
+                        .ok_or(format!(
+                            "the crossfade has to be a number of seconds up to {}",
+                            MAX_CROSSFADE
+                        ))?
+                };
+            }
+            Setting::Gapless => settings.gapless = parse_switch(value, "gapless")?,
+            Setting::Storefront => {
+                if unset {
+                    settings.storefront = None;
+                    return Ok(());
+                }
+                if value.len() != 2 || !value.chars().all(|c| c.is_ascii_alphabetic()) {
+                    return Err("storefronts are two letter country codes, like us".to_owned());
+                }
+                settings.storefront = Some(value.to_lowercase());
+            }
+            Setting::IdleTimeout => {
+                settings.idle_timeout = if unset || value == "never" {
+                    0
+                } else {
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|m| *m <= MAX_IDLE_TIMEOUT)
+                        .ok_or(format!(
+                            "the idle timeout has to be a number of minutes up to {}",
+                            MAX_IDLE_TIMEOUT
+                        ))?
+                };
+            }
+            Setting::MaxQueue => {
+                let max = if unset {
+                    0
+                } else {
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|n| *n <= MAX_QUEUE)
+                        .ok_or(format!(
+                            "the queue size has to be a number up to {}",
+                            MAX_QUEUE
+                        ))?
+                };
+                settings.max_queue = Some(max).filter(|n| *n > 0);
+            }
+            Setting::AllowedSources => {
+                let mut sources = Vec::new();
+                for name in value
+                    .split([',', ' '])
+                    .map(str::trim)
+                    .filter(|n| !n.is_empty())
+                {
+                    if name == "all" {
+                        sources = SourceKind::ALL.to_vec();
+                        break;
+                    }
+                    let source = SourceKind::from_name(name).ok_or(format!(
+                        "{} isn't a source, try any of {}",
+                        name,
+                        SourceKind::ALL.map(|s| s.name()).join(", ")
+                    ))?;
+                    if !sources.contains(&source) {
+                        sources.push(source);
+                    }
+                }
+                if sources.is_empty() {
+                    return Err("at least one source has to be allowed".to_owned());
+                }
+                settings.allowed_sources = sources;
+            }
+            Setting::ListenChannel => {
+                settings.listen_channel = if unset {
+                    None
+                } else {
+                    Some(ChannelId::new(parse_id(value, "<#")?))
+                };
+            }
+            Setting::ListenQueue => {
+                settings.listen_queue = parse_switch(value, "listen-queue")?;
+            }
+            Setting::RecapChannel => {
+                settings.recap_channel = if unset {
+                    None
+                } else {
+                    Some(ChannelId::new(parse_id(value, "<#")?))
+                };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Puts the setting back to its default.
+    pub fn reset(&self, settings: &mut GuildSettings) {
+        let default = GuildSettings::default();
+        match self {
+            Setting::AnnounceChannel => settings.announce_channel = default.announce_channel,
+            Setting::Announce => settings.announce = default.announce,
+            Setting::DjRole => settings.dj_role = default.dj_role,
+            Setting::DefaultVolume => settings.default_volume = default.default_volume,
+            Setting::Crossfade => settings.crossfade = default.crossfade,
+            Setting::Gapless => settings.gapless = default.gapless,
+            Setting::Storefront => settings.storefront = default.storefront,
+            Setting::IdleTimeout => settings.idle_timeout = default.idle_timeout,
+            Setting::MaxQueue => settings.max_queue = default.max_queue,
+            Setting::AllowedSources => settings.allowed_sources = default.allowed_sources,
+            Setting::ListenChannel => settings.listen_channel = default.listen_channel,
+            Setting::ListenQueue => settings.listen_queue = default.listen_queue,
+            Setting::RecapChannel => settings.recap_channel = default.recap_channel,
+        }
+    }
+}
+
+impl GuildSettings {
+    /// The volume as a multiplier songbird understands, where 1.0 is unchanged.
+    pub fn volume_scale(&self) -> f32 {
+        self.default_volume as f32 / 100.0
+    }
+
+    /// The longest track anyone can queue.
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.max_track_minutes
+            .map(|minutes| Duration::from_secs(minutes as u64 * 60))
+    }
+
+    /// The storefront to search, falling back to the bot's default.
+    pub fn storefront_or(&self, default: &str) -> String {
+        self.storefront
+            .clone()
+            .unwrap_or_else(|| default.to_owned())
+    }
+}
+
+/// Reads an on or off value for the setting called `name`.
+fn parse_switch(value: &str, name: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "on" | "yes" | "true" => Ok(true),
+        "off" | "no" | "false" | "none" | "" => Ok(false),
+        _ => Err(format!("{} is either on or off", name)),
+    }
+}
+
+/// Reads an ID from a mention starting with `prefix`, or on its own.
+fn parse_id(value: &str, prefix: &str) -> Result<u64, String> {
+    let id = value
+        .strip_prefix(prefix)
+        .and_then(|v| v.strip_suffix('>'))
+        .unwrap_or(value);
+    id.parse()
+        .ok()
+        .filter(|id| *id != 0)
+        .ok_or(format!("{} isn't something I can find", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(setting: Setting, value: &str) -> Result<GuildSettings, String> {
+        let mut settings = GuildSettings::default();
+        setting.set(&mut settings, value).map(|_| settings)
+    }
+
+    #[test]
+    fn parses_mentions_and_ids() {
+        let channel = set(Setting::AnnounceChannel, "<#1234>").unwrap();
+        assert_eq!(channel.announce_channel, Some(ChannelId::new(1234)));
+        let role = set(Setting::DjRole, "5678").unwrap();
+        assert_eq!(role.dj_role, Some(RoleId::new(5678)));
+        assert!(set(Setting::DjRole, "<#1234>").is_err());
+        assert_eq!(set(Setting::DjRole, "none").unwrap().dj_role, None);
+    }
+
+    #[test]
+    fn validates_numbers() {
+        assert_eq!(
+            set(Setting::DefaultVolume, "150%").unwrap().default_volume,
+            150
+        );
+        assert!(set(Setting::DefaultVolume, "201").is_err());
+        assert_eq!(set(Setting::IdleTimeout, "never").unwrap().idle_timeout, 0);
+        assert!(set(Setting::IdleTimeout, "forever").is_err());
+        assert_eq!(set(Setting::MaxQueue, "0").unwrap().max_queue, None);
+        assert_eq!(set(Setting::MaxQueue, "50").unwrap().max_queue, Some(50));
+        assert_eq!(set(Setting::Crossfade, "6s").unwrap().crossfade, 6);
+        assert_eq!(set(Setting::Crossfade, "off").unwrap().crossfade, 0);
+        assert!(set(Setting::Crossfade, "13").is_err());
+    }
+
+    #[test]
+    fn validates_storefronts() {
+        assert_eq!(
+            set(Setting::Storefront, "GB")
+                .unwrap()
+                .storefront
+                .as_deref(),
+            Some("gb")
+        );
+        assert_eq!(set(Setting::Storefront, "none").unwrap().storefront, None);
+        assert!(set(Setting::Storefront, "usa").is_err());
+    }
+
+    #[test]
+    fn parses_source_lists() {
+        let settings = set(Setting::AllowedSources, "youtube, local youtube").unwrap();
+        assert_eq!(
+            settings.allowed_sources,
+            vec![SourceKind::YoutubeDl, SourceKind::Local]
+        );
+        assert!(set(Setting::AllowedSources, "spotify").is_err());
+        assert!(set(Setting::AllowedSources, ",").is_err());
+        assert_eq!(
+            set(Setting::AllowedSources, "all").unwrap().allowed_sources,
+            SourceKind::ALL.to_vec()
+        );
+    }
+
+    #[test]
+    fn parses_switches() {
+        assert!(set(Setting::ListenQueue, "on").unwrap().listen_queue);
+        assert!(!set(Setting::ListenQueue, "off").unwrap().listen_queue);
+        assert!(set(Setting::ListenQueue, "sometimes").is_err());
+        assert!(set(Setting::Gapless, "yes").unwrap().gapless);
+        assert!(set(Setting::Gapless, "sometimes").is_err());
+        assert_eq!(
+            set(Setting::Announce, "Compact").unwrap().announce,
+            AnnounceMode::Compact
+        );
+        assert_eq!(
+            set(Setting::Announce, "off").unwrap().announce,
+            AnnounceMode::Off
+        );
+        assert!(set(Setting::Announce, "loud").is_err());
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for setting in Setting::ALL {
+            assert_eq!(Setting::from_name(setting.name()), Some(setting));
+        }
+        assert_eq!(Setting::from_name("DJ role"), Some(Setting::DjRole));
+    }
+
+    #[test]
+    fn resets_to_defaults() {
+        let mut settings = set(Setting::MaxQueue, "10").unwrap();
+        Setting::MaxQueue.reset(&mut settings);
+        assert_eq!(settings, GuildSettings::default());
+    }
+}
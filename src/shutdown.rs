@@ -0,0 +1,157 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::future::join_all;
+use poise::serenity_prelude as serenity;
+use serenity::all::{CreateMessage, Http, ShardManager};
+use songbird::Songbird;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use crate::{metrics::metrics, voice::saved::SavedQueue, Data};
+
+/// How long saying goodbye and leaving the calls, and then closing the shards, can each take
+/// before we give up on doing it nicely.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Resolves on Ctrl-C, or SIGTERM where there is one.
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut term = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Saves every guild's queue, says goodbye, leaves the calls and stops the shards. Only the first
+/// call does anything.
+pub async fn shutdown(
+    http: Arc<Http>,
+    manager: Arc<Songbird>,
+    data: Arc<Data>,
+    shards: Arc<ShardManager>,
+) {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    info!("Shutting down");
+    metrics().set_discord_ready(false);
+
+    let queues = capture_queues(&manager, &data).await;
+    let saved = save_queues(&data, &queues).await;
+
+    if timeout(TIMEOUT, leave_calls(&http, &manager, queues, saved))
+        .await
+        .is_err()
+    {
+        warn!("Gave up leaving calls after {:?}", TIMEOUT);
+    }
+    if timeout(TIMEOUT, shards.shutdown_all()).await.is_err() {
+        warn!("Gave up closing shards after {:?}", TIMEOUT);
+    }
+}
+
+/// A call being left, with the queue it had and where to say goodbye.
+struct Leaving {
+    guild_id: serenity::GuildId,
+    channel: Option<serenity::ChannelId>,
+    queue: SavedQueue,
+}
+
+/// Stops playback in every call, and captures each queue along with where to say goodbye.
+async fn capture_queues(manager: &Songbird, data: &Data) -> Vec<Leaving> {
+    let mut queues = Vec::new();
+    let calls: Vec<_> = manager.iter().collect();
+    for (guild_id, call) in calls {
+        let guild_id = serenity::GuildId::new(guild_id.0.get());
+        let channel = data.playback.get(guild_id).await.channel;
+        let channel = data
+            .settings
+            .get(guild_id)
+            .await
+            .announce_channel
+            .or(channel);
+
+        let call = call.lock().await;
+        let queue = SavedQueue::capture(&call, channel).await;
+        call.queue().stop();
+        queues.push(Leaving {
+            guild_id,
+            channel,
+            queue,
+        });
+    }
+    queues
+}
+
+/// Saves every non-empty queue in one go, returning whether that worked.
+async fn save_queues(data: &Data, queues: &[Leaving]) -> bool {
+    if queues.iter().all(|l| l.queue.tracks.is_empty()) {
+        return true;
+    }
+    let result = data
+        .saved
+        .update(|saved| {
+            for leaving in queues.iter().filter(|l| !l.queue.tracks.is_empty()) {
+                saved.insert(leaving.guild_id, leaving.queue.clone());
+            }
+        })
+        .await;
+    if let Err(e) = &result {
+        warn!("Failed to save the queues: {}", e);
+    }
+    result.is_ok()
+}
+
+/// Says goodbye and leaves every call at once.
+async fn leave_calls(http: &Http, manager: &Songbird, queues: Vec<Leaving>, saved: bool) {
+    join_all(queues.into_iter().map(|leaving| async move {
+        let Leaving {
+            guild_id,
+            channel,
+            queue,
+        } = leaving;
+        let notice = if queue.tracks.is_empty() {
+            "I'm restarting, see you in a bit!".to_owned()
+        } else if saved {
+            format!(
+                "I'm restarting, see you in a bit! I saved the {} tracks in the queue, use /restore to pick up where you left off.",
+                queue.tracks.len()
+            )
+        } else {
+            "I'm restarting, see you in a bit! I couldn't save the queue, sorry.".to_owned()
+        };
+
+        if let Some(channel) = channel {
+            if let Err(e) = channel
+                .send_message(http, CreateMessage::new().content(notice))
+                .await
+            {
+                warn!(
+                    "Failed to post shutdown notice in guild {}: {}",
+                    guild_id, e
+                );
+            }
+        }
+        if let Err(e) = manager.remove(guild_id).await {
+            warn!("Failed to leave call in guild {}: {}", guild_id, e);
+        }
+    }))
+    .await;
+}
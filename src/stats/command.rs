@@ -0,0 +1,32 @@
+use poise::{serenity_prelude as serenity, ChoiceParameter, CreateReply};
+
+use crate::{err::AppError, Context};
+
+use super::{now, summarize, Period};
+
+/// Shows what the server's been listening to
+#[poise::command(category = "Music", slash_command, prefix_command, guild_only)]
+pub async fn stats(
+    ctx: Context<'_>,
+    #[description = "How far back to look, this week by default"] period: Option<Period>,
+    #[description = "Only count what this person asked for"] user: Option<serenity::User>,
+) -> Result<(), AppError> {
+    let guild_id = ctx
+        .guild_id()
+        .ok_or_else(|| anyhow::anyhow!("Not in a guild"))?;
+    let period = period.unwrap_or(Period::Week);
+
+    let summary = {
+        let plays = ctx.data().plays.read().await;
+        let plays = plays.get(&guild_id).map(Vec::as_slice).unwrap_or_default();
+        summarize(plays, period.since(now()), user.as_ref().map(|u| u.id))
+    };
+
+    let title = match &user {
+        Some(user) => format!("{} · {}", user.display_name(), period.name()),
+        None => period.name().to_owned(),
+    };
+    ctx.send(CreateReply::default().embed(summary.embed(&title)))
+        .await?;
+    Ok(())
+}
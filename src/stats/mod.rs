@@ -0,0 +1,408 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    all::{Cache, CreateEmbed, CreateEmbedFooter, CreateMessage, GuildId, Http, UserId},
+    async_trait,
+};
+use songbird::{Event, EventContext, EventHandler as VoiceEventHandler};
+use tracing::warn;
+
+use crate::{
+    helpers::normalize::Normalized,
+    voice::metadata::{Catalog, Metadata, RequestedBy},
+    Data,
+};
+
+pub mod command;
+pub mod store;
+
+/// How long plays are kept, a little over the longest period stats are shown for.
+const KEEP_FOR: Duration = Duration::from_secs(400 * 24 * 60 * 60);
+/// Tracks skipped sooner than this don't count as played.
+const MIN_LISTENED: Duration = Duration::from_secs(30);
+/// How many of each are shown.
+const TOP: usize = 5;
+const WEEK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// How often guilds are checked for a recap that's due.
+const RECAP_CHECK: Duration = Duration::from_secs(60 * 60);
+
+/// A track that was listened to in a guild.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Play {
+    pub title: String,
+    pub artist: Option<String>,
+    pub url: Option<String>,
+    pub requester: Option<UserId>,
+    /// From the Apple Music song the track was matched to.
+    #[serde(default)]
+    pub genres: Vec<String>,
+    /// How long it actually played for, in seconds.
+    pub listened: u64,
+    /// Everyone in the call but bots, when it finished.
+    pub listeners: u32,
+    /// Unix timestamp, in seconds.
+    pub played_at: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// How far back `/stats` looks.
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Period {
+    #[name = "Today"]
+    Day,
+    #[name = "This week"]
+    Week,
+    #[name = "This month"]
+    Month,
+    #[name = "This year"]
+    Year,
+    #[name = "All time"]
+    All,
+}
+
+impl Period {
+    /// The Unix timestamp the period starts at, looking back from `now`.
+    pub fn since(&self, now: u64) -> u64 {
+        let days = match self {
+            Period::Day => 1,
+            Period::Week => 7,
+            Period::Month => 30,
+            Period::Year => 365,
+            Period::All => return 0,
+        };
+        now.saturating_sub(days * 24 * 60 * 60)
+    }
+}
+
+/// What a guild listened to over some period.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub plays: usize,
+    pub listened: Duration,
+    pub tracks: Vec<(String, usize)>,
+    pub artists: Vec<(String, usize)>,
+    pub genres: Vec<(String, usize)>,
+    pub requesters: Vec<(UserId, usize)>,
+    pub peak_listeners: u32,
+}
+
+/// Counts names case insensitively, keeping whichever spelling came first.
+#[derive(Default)]
+struct Tally(HashMap<String, (String, usize)>);
+
+impl Tally {
+    fn add(&mut self, name: &str) {
+        let name = name.trim();
+        if name.is_empty() {
+            return;
+        }
+        self.0
+            .entry(name.to_lowercase())
+            .or_insert_with(|| (name.to_owned(), 0))
+            .1 += 1;
+    }
+
+    fn top(self) -> Vec<(String, usize)> {
+        let mut counts: Vec<_> = self.0.into_values().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(TOP);
+        counts
+    }
+}
+
+/// Adds up the plays since `since`, only those `requester` asked for when given.
+pub fn summarize(plays: &[Play], since: u64, requester: Option<UserId>) -> Summary {
+    let mut summary = Summary::default();
+    let mut tracks = Tally::default();
+    let mut artists = Tally::default();
+    let mut genres = Tally::default();
+    let mut requesters: HashMap<UserId, usize> = HashMap::new();
+
+    let plays = plays
+        .iter()
+        .filter(|p| p.played_at >= since)
+        .filter(|p| requester.is_none() || p.requester == requester);
+    for play in plays {
+        summary.plays += 1;
+        summary.listened += Duration::from_secs(play.listened);
+        summary.peak_listeners = summary.peak_listeners.max(play.listeners);
+
+        match &play.artist {
+            Some(artist) => {
+                tracks.add(&format!("{} — {}", play.title, artist));
+                artists.add(artist);
+            }
+            None => tracks.add(&play.title),
+        }
+        for genre in &play.genres {
+            genres.add(genre);
+        }
+        if let Some(requester) = play.requester {
+            *requesters.entry(requester).or_default() += 1;
+        }
+    }
+
+    summary.tracks = tracks.top();
+    summary.artists = artists.top();
+    summary.genres = genres.top();
+    let mut requesters: Vec<_> = requesters.into_iter().collect();
+    requesters.sort_by_key(|(user, count)| (Reverse(*count), *user));
+    requesters.truncate(TOP);
+    summary.requesters = requesters;
+    summary
+}
+
+impl Summary {
+    pub fn embed(&self, title: &str) -> CreateEmbed {
+        let list = |items: Vec<String>| match items.is_empty() {
+            true => "nothing yet".to_owned(),
+            false => items.join("\n"),
+        };
+        let ranked = |counts: &[(String, usize)]| {
+            list(
+                counts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, count))| format!("{}. {} ({})", i + 1, name, count))
+                    .collect(),
+            )
+        };
+
+        let mut embed = CreateEmbed::default()
+            .title(title)
+            .field(
+                "Listened",
+                format!("{:.1} hours", self.listened.as_secs_f64() / 3600.0),
+                true,
+            )
+            .field("Plays", self.plays.to_string(), true)
+            .field("Peak listeners", self.peak_listeners.to_string(), true)
+            .field("Top tracks", ranked(&self.tracks), false)
+            .field("Top artists", ranked(&self.artists), true)
+            .field("Top genres", ranked(&self.genres), true);
+        if !self.requesters.is_empty() {
+            let requesters = self
+                .requesters
+                .iter()
+                .enumerate()
+                .map(|(i, (user, count))| format!("{}. <@{}> ({})", i + 1, user, count))
+                .collect();
+            embed = embed.field("Top requesters", list(requesters), false);
+        }
+        embed.footer(CreateEmbedFooter::new("Genres from Apple Music"))
+    }
+}
+
+/// Records every track that finishes in a call, for `/stats`.
+pub struct RecordPlays {
+    pub data: Arc<Data>,
+    pub cache: Arc<Cache>,
+    pub guild_id: GuildId,
+}
+
+impl RecordPlays {
+    /// Everyone in the same call as the bot, not counting bots.
+    fn listeners(&self) -> u32 {
+        let me = self.cache.current_user().id;
+        let Some(guild) = self.cache.guild(self.guild_id) else {
+            return 0;
+        };
+        let Some(channel) = guild.voice_states.get(&me).and_then(|v| v.channel_id) else {
+            return 0;
+        };
+        guild
+            .voice_states
+            .values()
+            .filter(|v| v.channel_id == Some(channel) && v.user_id != me)
+            .filter(|v| {
+                let member = v.member.as_ref().or_else(|| guild.members.get(&v.user_id));
+                !member.is_some_and(|m| m.user.bot)
+            })
+            .count() as u32
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for RecordPlays {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(tracks) = ctx else {
+            return None;
+        };
+        let listeners = self.listeners();
+
+        for (state, handle) in tracks.iter() {
+            let typemap = handle.typemap().read().await;
+            let Some(metadata) = typemap.get::<Metadata>() else {
+                continue;
+            };
+            let listened = state.play_time;
+            let counts = MIN_LISTENED.min(metadata.duration.unwrap_or(MIN_LISTENED));
+            let Some(normalized) = Normalized::from_metadata(metadata) else {
+                continue;
+            };
+            if listened < counts {
+                continue;
+            }
+
+            // the Apple Music song it was matched to has the proper title, artist credit and
+            // genres, so the same song counts as one however it was found
+            let catalog = typemap.get::<Catalog>();
+            let play = Play {
+                title: catalog.map(|c| c.title.clone()).unwrap_or(normalized.title),
+                artist: catalog.map(|c| c.artist.clone()).or(normalized.artist),
+                url: metadata.source_url.clone(),
+                requester: typemap.get::<RequestedBy>().map(|r| r.id),
+                genres: catalog.map(|c| c.genres.clone()).unwrap_or_default(),
+                listened: listened.as_secs(),
+                listeners,
+                played_at: now(),
+            };
+            tokio::spawn(record(self.data.clone(), self.guild_id, play));
+        }
+        None
+    }
+}
+
+async fn record(data: Arc<Data>, guild_id: GuildId, play: Play) {
+    if let Err(e) = data.plays.record(guild_id, play).await {
+        warn!("Couldn't save a play: {}", e);
+    }
+}
+
+fn recap_due(last_recap: u64, now: u64) -> bool {
+    now.saturating_sub(last_recap) >= WEEK.as_secs()
+}
+
+/// Posts last week's stats to every guild with a recap channel, once a week.
+pub fn spawn_recaps(http: Arc<Http>, data: Arc<Data>) {
+    tokio::spawn(async move {
+        loop {
+            post_recaps(&http, &data).await;
+            tokio::time::sleep(RECAP_CHECK).await;
+        }
+    });
+}
+
+async fn post_recaps(http: &Http, data: &Data) {
+    let guilds: Vec<_> = data.plays.read().await.keys().copied().collect();
+    let now = now();
+    for guild_id in guilds {
+        let Some(channel) = data.settings.get(guild_id).await.recap_channel else {
+            continue;
+        };
+
+        let summary = match data.plays.last_recap(guild_id).await {
+            // the first week starts counting now
+            None => None,
+            Some(last) if recap_due(last, now) => {
+                let plays = data.plays.read().await;
+                let plays = plays.get(&guild_id).map(Vec::as_slice).unwrap_or_default();
+                Some(summarize(plays, now.saturating_sub(WEEK.as_secs()), None))
+            }
+            Some(_) => continue,
+        };
+
+        let saved = data.plays.set_last_recap(guild_id, now).await;
+        if let Err(e) = saved {
+            warn!("Couldn't save when the recap was posted: {}", e);
+            continue;
+        }
+        let Some(summary) = summary.filter(|s| s.plays > 0) else {
+            continue;
+        };
+        let message = CreateMessage::new().embed(summary.embed("This week in music"));
+        if let Err(e) = channel.send_message(http, message).await {
+            warn!("Couldn't post the weekly recap: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(title: &str, artist: Option<&str>, requester: u64, played_at: u64) -> Play {
+        Play {
+            title: title.to_owned(),
+            artist: artist.map(str::to_owned),
+            url: None,
+            requester: Some(UserId::new(requester)),
+            genres: vec!["Pop".to_owned()],
+            listened: 1800,
+            listeners: requester as u32,
+            played_at,
+        }
+    }
+
+    #[test]
+    fn summarizes_the_period() {
+        let plays = [
+            play("Old", Some("Someone"), 1, 10),
+            play("Dancing Queen", Some("ABBA"), 1, 100),
+            play("dancing queen", Some("abba"), 2, 110),
+            play("Waterloo", Some("ABBA"), 2, 120),
+            play("Untitled", None, 3, 130),
+        ];
+
+        let summary = summarize(&plays, 100, None);
+        assert_eq!(summary.plays, 4);
+        assert_eq!(summary.listened, Duration::from_secs(4 * 1800));
+        assert_eq!(summary.peak_listeners, 3);
+        assert_eq!(
+            summary.tracks,
+            [
+                ("Dancing Queen — ABBA".to_owned(), 2),
+                ("Untitled".to_owned(), 1),
+                ("Waterloo — ABBA".to_owned(), 1),
+            ]
+        );
+        assert_eq!(summary.artists, [("ABBA".to_owned(), 3)]);
+        assert_eq!(summary.genres, [("Pop".to_owned(), 4)]);
+        assert_eq!(
+            summary.requesters,
+            [
+                (UserId::new(2), 2),
+                (UserId::new(1), 1),
+                (UserId::new(3), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn summarizes_one_requester() {
+        let plays = [
+            play("Dancing Queen", Some("ABBA"), 1, 100),
+            play("Waterloo", Some("ABBA"), 2, 120),
+        ];
+        let summary = summarize(&plays, 0, Some(UserId::new(2)));
+        assert_eq!(summary.plays, 1);
+        assert_eq!(summary.tracks, [("Waterloo — ABBA".to_owned(), 1)]);
+    }
+
+    #[test]
+    fn recaps_weekly() {
+        let week = WEEK.as_secs();
+        assert!(!recap_due(week, week * 2 - 1));
+        assert!(recap_due(week, week * 2));
+    }
+
+    #[test]
+    fn periods_look_back_from_now() {
+        assert_eq!(Period::Day.since(100_000), 100_000 - 86_400);
+        assert_eq!(Period::Week.since(1000), 0);
+        assert_eq!(Period::All.since(100_000), 0);
+    }
+}
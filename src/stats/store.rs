@@ -0,0 +1,219 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use serenity::all::GuildId;
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{RwLock, RwLockReadGuard},
+};
+use tracing::warn;
+
+use crate::store::JsonStore;
+
+use super::{now, Play, KEEP_FOR};
+
+/// How far past [`KEEP_FOR`] the oldest play can get before a guild's log is rewritten without
+/// the expired ones, so that happens about once a week rather than with every play.
+const COMPACT_AFTER: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Every guild's plays, oldest first. Each guild's are appended to `plays/<guild>.jsonl` as they
+/// happen, one per line, so recording a play doesn't rewrite everything played before it.
+pub struct PlayStore {
+    dir: PathBuf,
+    plays: RwLock<HashMap<GuildId, Vec<Play>>>,
+    /// When each guild's last weekly recap was posted, as a Unix timestamp.
+    recaps: JsonStore<HashMap<GuildId, u64>>,
+}
+
+impl PlayStore {
+    /// Loads the logs in `<data_dir>/plays`, skipping lines that can't be read.
+    pub fn open(data_dir: &Path) -> Self {
+        let dir = data_dir.join("plays");
+        let mut plays = HashMap::new();
+        for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|e| e != "jsonl") {
+                continue;
+            }
+            let Some(guild_id) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse().ok())
+            else {
+                continue;
+            };
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) => {
+                    warn!("Ignoring unreadable {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let guild: Vec<Play> = text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                // a crash mid-write only loses the play it was writing
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect();
+            plays.insert(GuildId::new(guild_id), guild);
+        }
+
+        Self {
+            recaps: JsonStore::open_at(dir.join("recaps.json")),
+            dir,
+            plays: RwLock::new(plays),
+        }
+    }
+
+    pub async fn read(&self) -> RwLockReadGuard<'_, HashMap<GuildId, Vec<Play>>> {
+        self.plays.read().await
+    }
+
+    /// Saves a play, forgetting the ones older than a year or so now and then.
+    pub async fn record(&self, guild_id: GuildId, play: Play) -> Result<()> {
+        let mut plays = self.plays.write().await;
+        let guild = plays.entry(guild_id).or_default();
+        guild.push(play);
+
+        let path = self.path(guild_id);
+        let cutoff = now().saturating_sub(KEEP_FOR.as_secs());
+        if guild
+            .first()
+            .is_some_and(|p| p.played_at + COMPACT_AFTER.as_secs() < cutoff)
+        {
+            guild.retain(|p| p.played_at >= cutoff);
+            return rewrite(&path, guild).await;
+        }
+        append(&path, guild.last().expect("just pushed")).await
+    }
+
+    pub async fn last_recap(&self, guild_id: GuildId) -> Option<u64> {
+        self.recaps.read().await.get(&guild_id).copied()
+    }
+
+    pub async fn set_last_recap(&self, guild_id: GuildId, at: u64) -> Result<()> {
+        self.recaps
+            .update(|recaps| recaps.insert(guild_id, at))
+            .await?;
+        Ok(())
+    }
+
+    fn path(&self, guild_id: GuildId) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", guild_id))
+    }
+}
+
+async fn append(path: &Path, play: &Play) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut line = serde_json::to_vec(play)?;
+    line.push(b'\n');
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(&line).await?;
+    Ok(())
+}
+
+async fn rewrite(path: &Path, plays: &[Play]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut text = Vec::new();
+    for play in plays {
+        serde_json::to_writer(&mut text, play)?;
+        text.push(b'\n');
+    }
+    // like the JSON stores, so a crash can't leave half a log behind
+    let tmp = path.with_extension("jsonl.tmp");
+    tokio::fs::write(&tmp, text).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(title: &str, played_at: u64) -> Play {
+        Play {
+            title: title.to_owned(),
+            artist: None,
+            url: None,
+            requester: None,
+            genres: vec![],
+            listened: 200,
+            listeners: 1,
+            played_at,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("marine-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn appends_plays_and_reads_them_back() {
+        let dir = temp_dir("plays");
+        let guild = GuildId::new(1);
+        let store = PlayStore::open(&dir);
+        store.record(guild, play("One", now())).await.unwrap();
+        store.record(guild, play("Two", now())).await.unwrap();
+        store
+            .record(GuildId::new(2), play("Three", now()))
+            .await
+            .unwrap();
+        store.set_last_recap(guild, 42).await.unwrap();
+
+        let log = std::fs::read_to_string(dir.join("plays/1.jsonl")).unwrap();
+        assert_eq!(log.lines().count(), 2);
+        // a play that was cut off halfway is skipped
+        std::fs::write(dir.join("plays/1.jsonl"), log + "{\"title\":").unwrap();
+
+        let store = PlayStore::open(&dir);
+        let plays = store.read().await;
+        let titles: Vec<_> = plays[&guild].iter().map(|p| p.title.as_str()).collect();
+        assert_eq!(titles, ["One", "Two"]);
+        assert_eq!(plays[&GuildId::new(2)].len(), 1);
+        assert_eq!(store.last_recap(guild).await, Some(42));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn drops_expired_plays_once_theyve_piled_up() {
+        let dir = temp_dir("plays-compact");
+        let guild = GuildId::new(1);
+        let store = PlayStore::open(&dir);
+        let cutoff = now() - KEEP_FOR.as_secs();
+
+        // only just expired, so it's kept around for now
+        store
+            .record(guild, play("Recent", cutoff - 60))
+            .await
+            .unwrap();
+        store.record(guild, play("New", now())).await.unwrap();
+        assert_eq!(store.read().await[&guild].len(), 2);
+
+        store.plays.write().await.get_mut(&guild).unwrap()[0].played_at =
+            cutoff - COMPACT_AFTER.as_secs() - 60;
+        store.record(guild, play("Newer", now())).await.unwrap();
+        let titles: Vec<_> = store.read().await[&guild]
+            .iter()
+            .map(|p| p.title.clone())
+            .collect();
+        assert_eq!(titles, ["New", "Newer"]);
+
+        let log = std::fs::read_to_string(dir.join("plays/1.jsonl")).unwrap();
+        assert_eq!(log.lines().count(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::{RwLock, RwLockReadGuard};
+use tracing::warn;
+
+/// A value backed by a JSON file, loaded once and written back after every change.
+pub struct JsonStore<T> {
+    path: PathBuf,
+    value: RwLock<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> JsonStore<T> {
+    /// Loads `<data_dir>/<name>.json`, starting empty if it doesn't exist yet.
+    pub fn open(data_dir: &Path, name: &str) -> Self {
+        Self::open_at(data_dir.join(format!("{}.json", name)))
+    }
+
+    /// Loads the store at `path`. A file that can't be parsed is moved aside to
+    /// `<path>.corrupt-<unix time>` rather than being overwritten by the next save.
+    pub fn open_at(path: PathBuf) -> Self {
+        let value = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                let secs = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+                let mut aside = path.clone().into_os_string();
+                aside.push(format!(".corrupt-{}", secs));
+                match std::fs::rename(&path, &aside) {
+                    Ok(()) => warn!(
+                        "Couldn't read {}, moved it to {}: {}",
+                        path.display(),
+                        Path::new(&aside).display(),
+                        e
+                    ),
+                    // nothing's been lost yet, better to stop than save over it
+                    Err(rename) => panic!(
+                        "Couldn't read {} ({}) or move it aside: {}",
+                        path.display(),
+                        e,
+                        rename
+                    ),
+                }
+                T::default()
+            }),
+            Err(_) => T::default(),
+        };
+
+        Self {
+            path,
+            value: RwLock::new(value),
+        }
+    }
+
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.value.read().await
+    }
+
+    /// Applies `f` and saves the result.
+    pub async fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R> {
+        let mut value = self.value.write().await;
+        let out = f(&mut value);
+        save(&self.path, &*value).await?;
+        Ok(out)
+    }
+}
+
+async fn save<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    // write to a temporary file first so a crash can't leave a half-written store behind
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, serde_json::to_vec_pretty(value)?).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn moves_unreadable_files_aside() {
+        let dir = std::env::temp_dir().join(format!("marine-store-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("settings.json"), "{\"1\": ").unwrap();
+
+        let store: JsonStore<HashMap<String, u32>> = JsonStore::open(&dir, "settings");
+        assert!(store.read().await.is_empty());
+        store.update(|m| m.insert("a".to_owned(), 1)).await.unwrap();
+
+        let aside: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().into_string().unwrap())
+            .filter(|name| name.starts_with("settings.json.corrupt-"))
+            .collect();
+        assert_eq!(aside.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(dir.join(&aside[0])).unwrap(),
+            "{\"1\": "
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
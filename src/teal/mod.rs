@@ -0,0 +1,400 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, PoisonError},
+};
+
+use anyhow::{anyhow, bail, Result};
+use reqwest::{redirect, Client as HttpClient, Url};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use serenity::all::UserId;
+use thiserror::Error;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::Mutex;
+use tracing::{debug, instrument, warn};
+
+use crate::{likes::Like, store::JsonStore, Data};
+
+/// Where accounts log in when they don't say otherwise, which finds the PDS of any Bluesky
+/// hosted account.
+pub const DEFAULT_SERVICE: &str = "https://bsky.social";
+/// The record a liked track is saved as in the account's repository.
+const LIKE_COLLECTION: &str = "fm.teal.alpha.feed.like";
+/// Tells teal.fm which client the record came from.
+const CLIENT_AGENT: &str = concat!("marine/", env!("CARGO_PKG_VERSION"));
+
+/// The teal.fm account each user linked, saved to `teal.json`.
+pub type Accounts = JsonStore<HashMap<UserId, Account>>;
+
+/// A lock for each user, so their likes are written one at a time. Each write uses up the
+/// account's refresh token, so two at once would both try to use the same one.
+#[derive(Default)]
+pub struct Writes(std::sync::Mutex<HashMap<UserId, Arc<Mutex<()>>>>);
+
+impl Writes {
+    fn lock_for(&self, user: UserId) -> Arc<Mutex<()>> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(user)
+            .or_default()
+            .clone()
+    }
+}
+
+/// An ATProto account that likes are written to. Only the refresh token is kept, never the app
+/// password it was made with.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub did: String,
+    pub handle: String,
+    /// The account's personal data server.
+    pub pds: String,
+    refresh_jwt: String,
+}
+
+impl fmt::Debug for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Account")
+            .field("did", &self.did)
+            .field("handle", &self.handle)
+            .field("pds", &self.pds)
+            .field("refresh_jwt", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    did: String,
+    handle: String,
+    access_jwt: String,
+    refresh_jwt: String,
+    did_doc: Option<DidDocument>,
+}
+
+#[derive(Deserialize)]
+struct DidDocument {
+    #[serde(default)]
+    service: Vec<Service>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Service {
+    id: String,
+    service_endpoint: String,
+}
+
+impl Session {
+    /// The PDS the account lives on, going by its DID document.
+    fn pds(&self) -> Option<&str> {
+        self.did_doc
+            .as_ref()?
+            .service
+            .iter()
+            .find(|s| s.id == "#atproto_pds" || s.id.ends_with("#atproto_pds"))
+            .map(|s| s.service_endpoint.trim_end_matches('/'))
+    }
+}
+
+/// Why logging in didn't work, fit to show the user. What the server said is only logged.
+#[derive(Debug, Error)]
+pub enum LoginError {
+    #[error("the service has to be an https:// address on the internet")]
+    BadService,
+    #[error("your account's server isn't an https:// address on the internet")]
+    BadPds,
+    #[error("check your handle and app password")]
+    Failed,
+}
+
+/// Logs in with an app password, keeping only what's needed to write records later.
+#[instrument(name = "teal_login", skip(password))]
+pub async fn login(service: &str, identifier: &str, password: &str) -> Result<Account, LoginError> {
+    debug!("Logging in to ATProto");
+
+    let service = server_url(service).map_err(|_| LoginError::BadService)?;
+    let http = connect(&service).await.map_err(|e| {
+        warn!("Won't log in to {}: {}", service, e);
+        LoginError::BadService
+    })?;
+    let session: Session = xrpc(
+        http.post(format!("{}/xrpc/com.atproto.server.createSession", service))
+            .json(&json!({ "identifier": identifier, "password": password })),
+    )
+    .await
+    .map_err(|e| {
+        warn!("Couldn't log in to {}: {}", service, e);
+        LoginError::Failed
+    })?;
+
+    // the stored PDS is where every like goes from now on, so it gets the same checks
+    let pds = match session.pds() {
+        Some(pds) => server_url(pds).map_err(|_| LoginError::BadPds)?,
+        None => service,
+    };
+    Ok(Account {
+        pds,
+        did: session.did,
+        handle: session.handle,
+        refresh_jwt: session.refresh_jwt,
+    })
+}
+
+/// Saves a like to the user's teal.fm account, if they've linked one.
+pub async fn sync_like(data: &Data, user: UserId, like: &Like) {
+    let lock = data.teal_writes.lock_for(user);
+    let _writing = lock.lock().await;
+    // read under the lock, so it's the refresh token the last write left behind
+    let Some(account) = data.teal.read().await.get(&user).cloned() else {
+        return;
+    };
+    if let Err(e) = write_like(data, user, account, like).await {
+        warn!("Couldn't save a like to teal.fm: {}", e);
+    }
+}
+
+#[instrument(name = "teal_like", skip_all, fields(did = %account.did))]
+async fn write_like(data: &Data, user: UserId, account: Account, like: &Like) -> Result<()> {
+    let pds = server_url(&account.pds)?;
+    let http = connect(&pds).await?;
+    // refresh tokens are single use, so the new one has to be kept before anything else
+    let session: Session = xrpc(
+        http.post(format!("{}/xrpc/com.atproto.server.refreshSession", pds))
+            .bearer_auth(&account.refresh_jwt),
+    )
+    .await?;
+    data.teal
+        .update(|accounts| {
+            if let Some(account) = accounts.get_mut(&user) {
+                account.refresh_jwt = session.refresh_jwt.clone();
+            }
+        })
+        .await?;
+
+    let _: Value = xrpc(
+        http.post(format!("{}/xrpc/com.atproto.repo.createRecord", pds))
+            .bearer_auth(&session.access_jwt)
+            .json(&json!({
+                "repo": account.did,
+                "collection": LIKE_COLLECTION,
+                "record": record(like),
+            })),
+    )
+    .await?;
+    Ok(())
+}
+
+/// The like as an ATProto record, with the same track fields as teal.fm's plays.
+fn record(like: &Like) -> Value {
+    let mut record = json!({
+        "$type": LIKE_COLLECTION,
+        "trackName": like.title.as_deref().unwrap_or("Unknown track"),
+        "artistNames": like.artist.iter().collect::<Vec<_>>(),
+        "submissionClientAgent": CLIENT_AGENT,
+        "createdAt": OffsetDateTime::from_unix_timestamp(like.liked_at as i64)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+            .format(&Rfc3339)
+            .unwrap_or_default(),
+    });
+    let fields = [
+        ("originUrl", like.source_url.clone().map(Value::from)),
+        ("isrc", like.isrc.clone().map(Value::from)),
+        ("duration", like.duration.map(|d| Value::from(d.as_secs()))),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            record[name] = value;
+        }
+    }
+    record
+}
+
+async fn xrpc<T: DeserializeOwned>(request: reqwest::RequestBuilder) -> Result<T> {
+    let response = request.send().await?;
+    if response.status().is_success() {
+        Ok(response.json().await?)
+    } else {
+        let status = response.status();
+        let text = response.text().await?;
+        Err(anyhow!("{}: {}", status, text))
+    }
+}
+
+/// `url` without a trailing slash, as long as it's https and its host isn't obviously private.
+/// Names are checked once they're resolved, by [`connect`].
+fn server_url(url: &str) -> Result<String> {
+    let parsed = Url::parse(url.trim())?;
+    if parsed.scheme() != "https" {
+        bail!("{} isn't https", url);
+    }
+    let public = match parsed.host_str() {
+        Some(host) => match host.trim_start_matches('[').trim_end_matches(']').parse() {
+            Ok(ip) => is_public(ip),
+            Err(_) => true,
+        },
+        None => false,
+    };
+    if !public {
+        bail!("{} isn't on the internet", url);
+    }
+    Ok(url.trim().trim_end_matches('/').to_owned())
+}
+
+/// Whether `ip` is somewhere on the internet, rather than this machine or its network.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => !is_private_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "this network" and carrier-grade NAT
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local
+        || (first & 0xfe00) == 0xfc00
+        // link-local
+        || (first & 0xffc0) == 0xfe80
+}
+
+/// A client for talking to `server`. Anyone can say where their account is, so its name is
+/// resolved up front and the client is held to those addresses, which all have to be public.
+/// Redirects aren't followed either.
+async fn connect(server: &str) -> Result<HttpClient> {
+    let url = Url::parse(server)?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("{} has no host", server))?;
+    let mut builder = HttpClient::builder().redirect(redirect::Policy::none());
+    if host.parse::<IpAddr>().is_err() && !host.starts_with('[') {
+        let port = url.port_or_known_default().unwrap_or(443);
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+        if addrs.is_empty() {
+            bail!("{} doesn't resolve", host);
+        }
+        if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+            bail!("{} resolves to {}, which isn't public", host, addr.ip());
+        }
+        builder = builder.resolve_to_addrs(host, &addrs);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::voice::source::TrackSource;
+
+    use super::*;
+
+    #[test]
+    fn finds_the_pds_in_the_did_document() {
+        let session: Session = serde_json::from_str(
+            r##"{
+                "did": "did:plc:abc",
+                "handle": "someone.bsky.social",
+                "accessJwt": "a",
+                "refreshJwt": "r",
+                "didDoc": {
+                    "id": "did:plc:abc",
+                    "service": [{
+                        "id": "#atproto_pds",
+                        "type": "AtprotoPersonalDataServer",
+                        "serviceEndpoint": "https://morel.us-east.host.bsky.network/"
+                    }]
+                }
+            }"##,
+        )
+        .unwrap();
+        assert_eq!(
+            session.pds(),
+            Some("https://morel.us-east.host.bsky.network")
+        );
+    }
+
+    #[test]
+    fn only_talks_to_https_servers_on_the_internet() {
+        assert_eq!(
+            server_url("https://bsky.social/").unwrap(),
+            "https://bsky.social"
+        );
+        assert_eq!(server_url("https://1.1.1.1").unwrap(), "https://1.1.1.1");
+        for url in [
+            "http://bsky.social",
+            "file:///etc/passwd",
+            "https://127.0.0.1",
+            "https://10.0.0.5:8080",
+            "https://192.168.1.1",
+            "https://169.254.169.254/latest/meta-data",
+            "https://0.0.0.0",
+            "https://100.64.0.1",
+            "https://[::1]",
+            "https://[fd00::1]",
+            "https://[fe80::1]",
+            "https://[::ffff:127.0.0.1]",
+            "not a url",
+        ] {
+            assert!(server_url(url).is_err(), "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_connect_to_names_for_private_addresses() {
+        assert!(connect("https://localhost").await.is_err());
+    }
+
+    #[test]
+    fn writes_for_the_same_user_take_turns() {
+        let writes = Writes::default();
+        let first = writes.lock_for(UserId::new(1));
+        let _writing = first.try_lock().unwrap();
+        assert!(writes.lock_for(UserId::new(1)).try_lock().is_err());
+        assert!(writes.lock_for(UserId::new(2)).try_lock().is_ok());
+    }
+
+    #[test]
+    fn builds_a_like_record() {
+        let like = Like {
+            source: TrackSource::YoutubeDl("https://youtu.be/dQw4w9WgXcQ".to_owned()),
+            title: Some("Never Gonna Give You Up".to_owned()),
+            artist: Some("Rick Astley".to_owned()),
+            duration: Some(Duration::from_secs(213)),
+            source_url: Some("https://youtu.be/dQw4w9WgXcQ".to_owned()),
+            apple_music_id: None,
+            isrc: Some("GBARL9300135".to_owned()),
+            liked_at: 0,
+        };
+        let record = record(&like);
+        assert_eq!(record["$type"], LIKE_COLLECTION);
+        assert_eq!(record["trackName"], "Never Gonna Give You Up");
+        assert_eq!(record["artistNames"], json!(["Rick Astley"]));
+        assert_eq!(record["isrc"], "GBARL9300135");
+        assert_eq!(record["duration"], 213);
+        assert_eq!(record["createdAt"], "1970-01-01T00:00:00Z");
+    }
+}
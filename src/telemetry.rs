@@ -0,0 +1,120 @@
+use poise::serenity_prelude as serenity;
+use serenity::{async_trait, Client, FullEvent, Interaction};
+use tracing::{field::Empty, info_span, Instrument, Span};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::config::{LogConfig, LogFormat};
+
+/// Keeps the trace exporter running until it's shut down.
+pub struct Telemetry {
+    #[cfg(feature = "otlp")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Telemetry {
+    /// Sends off any traces that haven't been exported yet.
+    pub fn shutdown(self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Sets up logging in the configured format, with levels from `RUST_LOG`.
+pub fn init(config: &LogConfig) -> Telemetry {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let fmt = match config.format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
+    let registry = tracing_subscriber::registry().with(filter).with(fmt);
+
+    #[cfg(feature = "otlp")]
+    {
+        let provider = config.otlp_endpoint.as_deref().and_then(|endpoint| {
+            otlp_provider(endpoint)
+                .inspect_err(|e| eprintln!("Not exporting traces to {}: {}", endpoint, e))
+                .ok()
+        });
+        let layer = provider.as_ref().map(|provider| {
+            use opentelemetry::trace::TracerProvider as _;
+            tracing_opentelemetry::layer().with_tracer(provider.tracer("marine"))
+        });
+        registry.with(layer).init();
+        Telemetry { provider }
+    }
+    #[cfg(not(feature = "otlp"))]
+    {
+        registry.init();
+        Telemetry {}
+    }
+}
+
+#[cfg(feature = "otlp")]
+fn otlp_provider(
+    endpoint: &str,
+) -> Result<opentelemetry_sdk::trace::TracerProvider, opentelemetry::trace::TraceError> {
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    Ok(opentelemetry_sdk::trace::TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(opentelemetry_sdk::Resource::new([
+            opentelemetry::KeyValue::new("service.name", "marine"),
+        ]))
+        .build())
+}
+
+/// Runs everything a command does inside a span with who used it where, so its logs can be told
+/// apart from everyone else's. The command name is filled in by `pre_command`, once poise has
+/// worked out which command it is.
+pub struct Traced<F>(pub F);
+
+#[async_trait]
+impl<F: serenity::Framework> serenity::Framework for Traced<F> {
+    async fn init(&mut self, client: &Client) {
+        self.0.init(client).await;
+    }
+
+    async fn dispatch(&self, ctx: serenity::Context, event: FullEvent) {
+        let span = match &event {
+            FullEvent::InteractionCreate {
+                interaction: Interaction::Command(command),
+            } => command_span(command.guild_id, command.user.id, command.id.get()),
+            FullEvent::InteractionCreate {
+                interaction: Interaction::Component(component),
+            } => {
+                let span = command_span(component.guild_id, component.user.id, component.id.get());
+                span.record("command", component.data.custom_id.as_str());
+                span
+            }
+            FullEvent::Message { new_message } if !new_message.author.bot => command_span(
+                new_message.guild_id,
+                new_message.author.id,
+                new_message.id.get(),
+            ),
+            _ => Span::none(),
+        };
+        self.0.dispatch(ctx, event).instrument(span).await;
+    }
+}
+
+fn command_span(guild: Option<serenity::GuildId>, user: serenity::UserId, invocation: u64) -> Span {
+    info_span!(
+        "command",
+        guild = guild.map(|g| g.get()),
+        user = user.get(),
+        command = Empty,
+        invocation,
+    )
+}
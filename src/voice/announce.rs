@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serenity::{
+    all::{
+        ChannelId, CreateMessage, EditMessage, GetMessages, GuildId, Http, Mentionable,
+        MessageBuilder, MessageId,
+    },
+    async_trait,
+};
+use songbird::{typemap::TypeMapKey, Event, EventContext, EventHandler as VoiceEventHandler};
+use tokio::sync::Mutex;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{AppError, Context, Data};
+
+use super::{
+    controls::can_control,
+    metadata::{title_of, CoverArt, Metadata, RequestedBy},
+    play::build_play_embed,
+};
+
+/// How a guild wants to hear about each new track.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, poise::ChoiceParameter,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnounceMode {
+    Off,
+    /// A single line of text.
+    Compact,
+    /// The full now playing card.
+    #[default]
+    Full,
+}
+
+impl AnnounceMode {
+    /// How tracks get announced in this mode, to finish "New tracks are announced ...".
+    pub fn describe(&self) -> &'static str {
+        match self {
+            AnnounceMode::Off => "nowhere, announcements are off",
+            AnnounceMode::Compact => "in a single line",
+            AnnounceMode::Full => "with the full card",
+        }
+    }
+}
+
+/// Marks a track that started as soon as `/play` queued it, whose reply already showed its card.
+pub struct ShownInReply;
+
+impl TypeMapKey for ShownInReply {
+    type Value = ();
+}
+
+struct Announcement {
+    track: Uuid,
+    channel: ChannelId,
+    message: MessageId,
+}
+
+/// Posts a card whenever a new track starts, replacing the one for the track before.
+pub struct TrackStartNotifier {
+    chan_id: ChannelId,
+    http: Arc<Http>,
+    data: Arc<Data>,
+    guild_id: GuildId,
+    last: Mutex<Option<Announcement>>,
+}
+
+impl TrackStartNotifier {
+    pub fn new(chan_id: ChannelId, http: Arc<Http>, data: Arc<Data>, guild_id: GuildId) -> Self {
+        Self {
+            chan_id,
+            http,
+            data,
+            guild_id,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Whether nothing's been said in the channel since `message`, so editing it keeps it in view.
+    async fn is_latest(&self, channel: ChannelId, message: MessageId) -> bool {
+        channel
+            .messages(&self.http, GetMessages::new().limit(1))
+            .await
+            .ok()
+            .and_then(|messages| messages.first().map(|m| m.id))
+            == Some(message)
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for TrackStartNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(&[(_, handle)]) = ctx else {
+            return None;
+        };
+
+        let settings = self.data.settings.get(self.guild_id).await;
+        let mode = settings.announce;
+        if mode == AnnounceMode::Off {
+            return None;
+        }
+        let channel = settings.announce_channel.unwrap_or(self.chan_id);
+
+        let mut last = self.last.lock().await;
+        // resuming a paused track fires this again
+        if last.as_ref().is_some_and(|l| l.track == handle.uuid()) {
+            return None;
+        }
+
+        let (metadata, title, cover, requester) = {
+            let typemap = handle.typemap().read().await;
+            if typemap.contains_key::<ShownInReply>() {
+                return None;
+            }
+            (
+                typemap.get::<Metadata>()?.clone(),
+                title_of(&typemap),
+                typemap.get::<CoverArt>().cloned(),
+                typemap.get::<RequestedBy>().cloned(),
+            )
+        };
+
+        let (content, embed, attachment) = match mode {
+            AnnounceMode::Compact => {
+                let mut msg = MessageBuilder::new();
+                msg.push("Now playing ")
+                    .push_bold_safe(title.unwrap_or("a track".to_owned()));
+                if let Some(artist) = &metadata.artist {
+                    msg.push(" - ").push_bold_safe(artist);
+                }
+                if let Some(requester) = &requester {
+                    msg.push(", requested by ").push_safe(&requester.name);
+                }
+                (msg.build(), None, None)
+            }
+            _ => {
+                let mut embed = build_play_embed(&metadata, true, None).await;
+                if let Some(requester) = &requester {
+                    embed = embed.field("Requested by", requester.id.mention().to_string(), true);
+                }
+                let mut attachment = None;
+                if let Some(cover) = cover {
+                    let (file, url) = cover.attachment();
+                    embed = embed.image(url);
+                    attachment = Some(file);
+                }
+                (String::new(), Some(embed), attachment)
+            }
+        };
+
+        let previous = last.take();
+        let edited = match &previous {
+            // attachments can't be swapped out in an edit, so those always get a new message
+            Some(previous)
+                if previous.channel == channel
+                    && attachment.is_none()
+                    && self.is_latest(channel, previous.message).await =>
+            {
+                let edit = EditMessage::new()
+                    .content(content.clone())
+                    .embeds(embed.clone().into_iter().collect());
+                channel
+                    .edit_message(&self.http, previous.message, edit)
+                    .await
+                    .map(|m| m.id)
+                    .ok()
+            }
+            _ => None,
+        };
+
+        let message = match edited {
+            Some(message) => Some(message),
+            None => {
+                if let Some(previous) = previous {
+                    if let Err(e) = previous
+                        .channel
+                        .delete_message(&self.http, previous.message)
+                        .await
+                    {
+                        warn!("Failed to delete previous announcement: {}", e);
+                    }
+                }
+
+                let mut msg = CreateMessage::new().content(content);
+                if let Some(embed) = embed {
+                    msg = msg.embed(embed);
+                }
+                if let Some(attachment) = attachment {
+                    msg = msg.add_file(attachment);
+                }
+                match channel.send_message(&self.http, msg).await {
+                    Ok(message) => Some(message.id),
+                    Err(e) => {
+                        warn!("Failed to send message: {}", e);
+                        None
+                    }
+                }
+            }
+        };
+
+        *last = message.map(|message| Announcement {
+            track: handle.uuid(),
+            channel,
+            message,
+        });
+
+        None
+    }
+}
+
+/// Chooses how new tracks are announced
+#[poise::command(category = "Music", slash_command, prefix_command, guild_only)]
+pub async fn announce(
+    ctx: Context<'_>,
+    #[description = "Off, a single line, or the full card"] mode: Option<AnnounceMode>,
+) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+
+    let settings = match mode {
+        Some(mode) => {
+            can_control(ctx).await?;
+            ctx.data()
+                .settings
+                .update(guild_id, |settings| settings.announce = mode)
+                .await?
+                .0
+        }
+        None => ctx.data().settings.get(guild_id).await,
+    };
+
+    ctx.say(format!(
+        "New tracks are announced {}",
+        settings.announce.describe()
+    ))
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use reqwest::Client as HttpClient;
+use serenity::all::{ChannelId, CreateMessage, EditMessage, GuildId, Http};
+use songbird::Call;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::{
+    err::{AppError, ErrorKind},
+    helpers::get_http_client,
+    Context, Data,
+};
+
+use super::{metadata::Requester, play, source::TrackSource};
+
+/// Tracks queued before answering. Resolving a long list takes longer than an interaction lasts,
+/// so the rest are queued afterwards.
+const QUEUE_FIRST: usize = 10;
+/// How often the message about the rest of a list being queued is updated, in tracks.
+const PROGRESS_EVERY: usize = 25;
+
+/// Queues a list of tracks for the command's author, the first few straight away and the rest in
+/// the background. Returns what to tell them.
+pub async fn queue_list(
+    ctx: Context<'_>,
+    guild_id: GuildId,
+    call: Arc<Mutex<Call>>,
+    name: String,
+    mut tracks: Vec<TrackSource>,
+) -> String {
+    let http = get_http_client(ctx.serenity_context()).await;
+    let requester = Requester::new(ctx.author());
+    let rest = tracks.split_off(QUEUE_FIRST.min(tracks.len()));
+    let (queued, stopped) =
+        queue_tracks(ctx.data(), &http, guild_id, &call, tracks, &requester).await;
+    ctx.data().now_playing.refresh(guild_id).await;
+
+    let mut content = format!("Queued {} tracks from {}", queued, name);
+    if let Some(e) = stopped {
+        content.push_str(&format!(", then stopped: {}", e.message()));
+    } else if !rest.is_empty() {
+        content.push_str(&format!(", the other {} are on their way", rest.len()));
+        tokio::spawn(queue_rest(
+            ctx.data().clone(),
+            ctx.serenity_context().http.clone(),
+            http,
+            guild_id,
+            ctx.channel_id(),
+            call,
+            name,
+            rest,
+            requester,
+        ));
+    }
+    content
+}
+
+/// Queues `tracks` in order, returning how many made it and what stopped the rest, if anything
+/// did.
+async fn queue_tracks(
+    data: &Data,
+    http: &HttpClient,
+    guild_id: GuildId,
+    call: &Mutex<Call>,
+    tracks: impl IntoIterator<Item = TrackSource>,
+    requester: &Requester,
+) -> (usize, Option<AppError>) {
+    let mut queued = 0;
+    for source in tracks {
+        match play::add(
+            data,
+            http.clone(),
+            guild_id,
+            call,
+            source,
+            requester.clone(),
+        )
+        .await
+        {
+            Ok(_) => queued += 1,
+            // the queue's full or the server doesn't allow this, the rest won't fare any better
+            Err(e) if matches!(e.kind(), ErrorKind::PermissionDenied(_)) => {
+                return (queued, Some(e));
+            }
+            Err(e) => warn!("Skipping a track: {}", e.chain()),
+        }
+    }
+    (queued, None)
+}
+
+/// Queues what's left of a list after the command has answered, keeping a message in the
+/// channel up to date as it goes.
+#[allow(clippy::too_many_arguments)]
+async fn queue_rest(
+    data: Arc<Data>,
+    discord: Arc<Http>,
+    http: HttpClient,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    call: Arc<Mutex<Call>>,
+    name: String,
+    tracks: Vec<TrackSource>,
+    requester: Requester,
+) {
+    let total = tracks.len();
+    let message = channel_id
+        .send_message(
+            &discord,
+            CreateMessage::new().content(format!("Queueing {} more tracks from {}", total, name)),
+        )
+        .await
+        .inspect_err(|e| warn!("Failed to send queueing progress: {}", e))
+        .ok();
+
+    let mut queued = 0;
+    let mut stopped = None;
+    let mut left = false;
+    for (i, chunk) in tracks.chunks(PROGRESS_EVERY).enumerate() {
+        // nobody's listening any more
+        if call.lock().await.current_channel().is_none() {
+            left = true;
+            break;
+        }
+        let (added, error) = queue_tracks(
+            &data,
+            &http,
+            guild_id,
+            &call,
+            chunk.iter().cloned(),
+            &requester,
+        )
+        .await;
+        queued += added;
+        data.now_playing.refresh(guild_id).await;
+        if error.is_some() {
+            stopped = error;
+            break;
+        }
+
+        let done = ((i + 1) * PROGRESS_EVERY).min(total);
+        if done < total {
+            if let Some(message) = &message {
+                let content = format!(
+                    "Queueing {} more tracks from {}, {} done",
+                    total, name, done
+                );
+                let _ = channel_id
+                    .edit_message(&discord, message.id, EditMessage::new().content(content))
+                    .await;
+            }
+        }
+    }
+
+    let mut content = format!("Queued {} more tracks from {}", queued, name);
+    if let Some(e) = stopped {
+        content.push_str(&format!(", then stopped: {}", e.message()));
+    } else if left {
+        content.push_str(", then stopped since I left the call");
+    }
+    if let Some(message) = message {
+        if let Err(e) = channel_id
+            .edit_message(&discord, message.id, EditMessage::new().content(content))
+            .await
+        {
+            warn!("Failed to update queueing progress: {}", e);
+        }
+    }
+}
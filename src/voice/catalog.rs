@@ -0,0 +1,345 @@
+use std::{sync::Arc, time::Duration};
+
+use songbird::tracks::TrackHandle;
+use tracing::{debug, warn};
+
+use crate::{
+    apol::search::{search_tracks, AppleMusicSongDatum, Attributes},
+    helpers::normalize::{normalize, split_artists, Normalized},
+};
+
+use super::metadata::{AlbumId, Catalog, CatalogTrack, Metadata};
+
+/// How many search results are scored against the track.
+const CANDIDATES: u8 = 5;
+/// Matches less sure than this are thrown away.
+const MIN_CONFIDENCE: f32 = 0.75;
+/// How far apart the durations can be, for tracks shorter than 200 seconds. Longer tracks are
+/// allowed 5% of their length.
+const DURATION_TOLERANCE: Duration = Duration::from_secs(10);
+/// Durations this close are as good as the same.
+const DURATION_EXACT: Duration = Duration::from_secs(2);
+const ARTWORK_SIZE: &str = "600";
+
+/// Matches the track against the Apple Music catalog in the background, attaching what's found
+/// as [`Catalog`], and its album as [`AlbumId`] for gapless playback.
+pub fn match_track(track: TrackHandle, storefront: String) {
+    tokio::spawn(async move {
+        let (normalized, duration) = {
+            let typemap = track.typemap().read().await;
+            if typemap.contains_key::<Catalog>() {
+                return;
+            }
+            let Some(metadata) = typemap.get::<Metadata>() else {
+                return;
+            };
+            let Some(normalized) = Normalized::from_metadata(metadata) else {
+                return;
+            };
+            (normalized, metadata.duration)
+        };
+
+        let song = match search_tracks(normalized.query(), &storefront, CANDIDATES).await {
+            Ok(song) => song,
+            Err(e) => {
+                warn!("Apple Music lookup failed: {}", e);
+                return;
+            }
+        };
+        let candidates = song.and_then(|s| s.data).unwrap_or_default();
+        let Some(found) = best_match(&normalized, duration, candidates) else {
+            debug!("No good Apple Music match for {}", normalized.query());
+            return;
+        };
+
+        let mut typemap = track.typemap().write().await;
+        if let Some(album_id) = found.album_id.clone() {
+            typemap.insert::<AlbumId>(album_id);
+        }
+        typemap.insert::<Catalog>(Arc::new(found));
+    });
+}
+
+/// The candidate most like the track, if it's sure enough.
+fn best_match(
+    normalized: &Normalized,
+    duration: Option<Duration>,
+    candidates: Vec<AppleMusicSongDatum>,
+) -> Option<CatalogTrack> {
+    candidates
+        .into_iter()
+        .filter_map(|datum| {
+            let confidence = score(normalized, duration, datum.attributes.as_ref()?);
+            catalog_track(datum, confidence)
+        })
+        .filter(|found| found.confidence >= MIN_CONFIDENCE)
+        .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+}
+
+fn catalog_track(datum: AppleMusicSongDatum, confidence: f32) -> Option<CatalogTrack> {
+    let attributes = datum.attributes?;
+    let url = attributes.url;
+    Some(CatalogTrack {
+        id: datum.id?,
+        isrc: attributes.isrc,
+        title: attributes.name?,
+        artist: attributes.artist_name?,
+        album: attributes.album_name,
+        album_id: url.as_deref().and_then(album_id_from_url),
+        artwork: attributes.artwork.and_then(|a| a.url).map(|url| {
+            url.replace("{w}", ARTWORK_SIZE)
+                .replace("{h}", ARTWORK_SIZE)
+        }),
+        genres: attributes
+            .genre_names
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|g| g != "Music")
+            .collect(),
+        composer: attributes.composer_name,
+        url,
+        confidence,
+    })
+}
+
+/// How sure it is that a catalog song is the track, from 0 to 1. The title counts most, then
+/// the artist, then how close the durations are.
+fn score(normalized: &Normalized, duration: Option<Duration>, candidate: &Attributes) -> f32 {
+    let Some(name) = &candidate.name else {
+        return 0.0;
+    };
+    // Apple Music puts featured artists and remaster notes in the title too
+    let title = similarity(&normalized.title, &normalize(name, None).title);
+
+    let duration_score = match (duration, candidate.duration_in_millis) {
+        (Some(duration), Some(millis)) => {
+            let candidate = Duration::from_millis(millis.max(0) as u64);
+            let difference = duration.abs_diff(candidate);
+            let tolerance = DURATION_TOLERANCE.max(duration / 20);
+            if difference <= DURATION_EXACT {
+                Some(1.0)
+            } else if difference <= tolerance {
+                let over = (difference - DURATION_EXACT).as_secs_f32();
+                Some(1.0 - 0.5 * over / (tolerance - DURATION_EXACT).as_secs_f32())
+            } else {
+                Some(0.0)
+            }
+        }
+        _ => None,
+    };
+
+    let confidence = match &normalized.artist {
+        Some(artist) => {
+            let credited = candidate.artist_name.as_deref().unwrap_or_default();
+            let artist = split_artists(credited)
+                .iter()
+                .map(|c| similarity(artist, c))
+                .fold(similarity(artist, credited), f32::max);
+            0.5 * title + 0.3 * artist + 0.2 * duration_score.unwrap_or(0.5)
+        }
+        None => 0.7 * title + 0.3 * duration_score.unwrap_or(0.5),
+    };
+    // the same song at a different length is a different recording
+    if duration_score == Some(0.0) {
+        confidence * 0.5
+    } else {
+        confidence
+    }
+}
+
+/// How alike two names are, going by the words they share.
+fn similarity(a: &str, b: &str) -> f32 {
+    let words = |s: &str| -> Vec<String> {
+        s.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let (a, b) = (words(a), words(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    if a == b {
+        return 1.0;
+    }
+    let shared = a.iter().filter(|w| b.contains(w)).count();
+    2.0 * shared as f32 / (a.len() + b.len()) as f32
+}
+
+/// Pulls the album ID out of a song link like
+/// `https://music.apple.com/us/album/some-song/1440857781?i=1440857786`.
+fn album_id_from_url(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let mut segments = path.split('/').skip_while(|s| *s != "album");
+    segments.next()?;
+    let id = segments.last()?;
+    id.chars()
+        .all(|c| c.is_ascii_digit())
+        .then(|| id.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, artist: &str, seconds: u64) -> Attributes {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "artistName": artist,
+            "durationInMillis": seconds * 1000,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn scores_candidates() {
+        let cases = [
+            // title, channel, duration, catalog title, catalog artist, catalog duration, matches
+            (
+                "Rick Astley - Never Gonna Give You Up (Official Music Video)",
+                "RickAstleyVEVO",
+                213,
+                "Never Gonna Give You Up",
+                "Rick Astley",
+                213,
+                true,
+            ),
+            (
+                "Daft Punk - Get Lucky (Official Audio) ft. Pharrell Williams, Nile Rodgers",
+                "Daft Punk",
+                369,
+                "Get Lucky (feat. Pharrell Williams & Nile Rodgers)",
+                "Daft Punk",
+                367,
+                true,
+            ),
+            (
+                "Fleetwood Mac - Dreams (2004 Remaster)",
+                "Fleetwood Mac",
+                257,
+                "Dreams (2004 Remaster)",
+                "Fleetwood Mac",
+                254,
+                true,
+            ),
+            (
+                "Bohemian Rhapsody",
+                "Queen - Topic",
+                355,
+                "Bohemian Rhapsody",
+                "Queen",
+                355,
+                true,
+            ),
+            // a cover by someone else
+            (
+                "Bohemian Rhapsody",
+                "Queen - Topic",
+                355,
+                "Bohemian Rhapsody",
+                "Panic! At the Disco",
+                362,
+                false,
+            ),
+            // a music video with a long intro
+            (
+                "Michael Jackson - Thriller (Official Video)",
+                "michaeljacksonVEVO",
+                833,
+                "Thriller",
+                "Michael Jackson",
+                357,
+                false,
+            ),
+            // a different song by the same artist
+            (
+                "Coldplay - Yellow",
+                "Coldplay",
+                269,
+                "Viva La Vida",
+                "Coldplay",
+                242,
+                false,
+            ),
+            // the live version isn't the studio one
+            (
+                "Nirvana - Lithium (Live at Reading 1992)",
+                "Nirvana",
+                253,
+                "Lithium",
+                "Nirvana",
+                257,
+                false,
+            ),
+        ];
+
+        for (title, channel, seconds, name, artist, catalog_seconds, matches) in cases {
+            let normalized = normalize(title, Some(channel));
+            let confidence = score(
+                &normalized,
+                Some(Duration::from_secs(seconds)),
+                &candidate(name, artist, catalog_seconds),
+            );
+            assert_eq!(
+                confidence >= MIN_CONFIDENCE,
+                matches,
+                "{} against {} by {} scored {}",
+                title,
+                name,
+                artist,
+                confidence
+            );
+        }
+    }
+
+    #[test]
+    fn picks_the_most_confident_candidate() {
+        let normalized = normalize("Queen - Bohemian Rhapsody", None);
+        let datum = |id: &str, name: &str, artist: &str| -> AppleMusicSongDatum {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "attributes": {
+                    "name": name,
+                    "artistName": artist,
+                    "durationInMillis": 355_000,
+                    "genreNames": ["Rock", "Music"],
+                    "url": "https://music.apple.com/us/album/bohemian-rhapsody/1440650428?i=1440650711",
+                    "artwork": { "url": "https://is1-ssl.mzstatic.com/image/{w}x{h}bb.jpg" }
+                }
+            }))
+            .unwrap()
+        };
+
+        let found = best_match(
+            &normalized,
+            Some(Duration::from_secs(354)),
+            vec![
+                datum("1", "Bohemian Rhapsody", "Panic! At the Disco"),
+                datum("2", "Bohemian Rhapsody", "Queen"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(found.id, "2");
+        assert_eq!(found.album_id.as_deref(), Some("1440650428"));
+        assert_eq!(found.genres, ["Rock"]);
+        assert_eq!(
+            found.artwork.as_deref(),
+            Some("https://is1-ssl.mzstatic.com/image/600x600bb.jpg")
+        );
+
+        assert!(best_match(&normalized, None, vec![datum("3", "Yellow", "Coldplay")]).is_none());
+    }
+
+    #[test]
+    fn reads_album_ids() {
+        assert_eq!(
+            album_id_from_url("https://music.apple.com/us/album/some-song/1440857781?i=1440857786")
+                .as_deref(),
+            Some("1440857781")
+        );
+        assert_eq!(
+            album_id_from_url("https://music.apple.com/us/song/some-song/1440857786"),
+            None
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use poise::serenity_prelude as serenity;
+use rand::seq::SliceRandom;
+use serenity::all::{Cache, GuildId, Member, RoleId, UserId};
+use songbird::tracks::{LoopState, PlayMode, TrackQueue};
+
+use crate::{
+    err::{AppError, ErrorKind},
+    Context, Data,
+};
+
+/// Something the now playing buttons (and the matching commands) can do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    PauseResume,
+    Skip,
+    Loop,
+    Shuffle,
+    Stop,
+}
+
+impl Control {
+    pub const ALL: [Control; 5] = [
+        Control::PauseResume,
+        Control::Skip,
+        Control::Loop,
+        Control::Shuffle,
+        Control::Stop,
+    ];
+
+    pub fn custom_id(&self) -> &'static str {
+        match self {
+            Control::PauseResume => "np:pause",
+            Control::Skip => "np:skip",
+            Control::Loop => "np:loop",
+            Control::Shuffle => "np:shuffle",
+            Control::Stop => "np:stop",
+        }
+    }
+
+    pub fn from_custom_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.custom_id() == id)
+    }
+}
+
+/// Applies a control to the queue, returning what happened.
+pub async fn apply(queue: &TrackQueue, control: Control) -> String {
+    let current = queue.current();
+
+    match control {
+        Control::PauseResume => {
+            let Some(current) = current else {
+                return "Nothing is playing".to_owned();
+            };
+            let paused = current
+                .get_info()
+                .await
+                .is_ok_and(|info| info.playing == PlayMode::Pause);
+            if paused {
+                match queue.resume() {
+                    Ok(()) => "Resumed".to_owned(),
+                    Err(e) => format!("Failed to resume: {:?}", e),
+                }
+            } else {
+                match queue.pause() {
+                    Ok(()) => "Paused".to_owned(),
+                    Err(e) => format!("Failed to pause: {:?}", e),
+                }
+            }
+        }
+        Control::Skip => match queue.skip() {
+            Ok(()) => "Skipped".to_owned(),
+            Err(e) => format!("Failed to skip: {:?}", e),
+        },
+        Control::Loop => {
+            let Some(current) = current else {
+                return "Nothing is playing".to_owned();
+            };
+            let looping = current
+                .get_info()
+                .await
+                .is_ok_and(|info| info.loops != LoopState::Finite(0));
+            let result = if looping {
+                current
+                    .disable_loop()
+                    .map(|_| "No longer looping this track")
+            } else {
+                current.enable_loop().map(|_| "Looping this track")
+            };
+            match result {
+                Ok(msg) => msg.to_owned(),
+                Err(e) => format!("Failed to change looping: {:?}", e),
+            }
+        }
+        Control::Shuffle => {
+            let shuffled = queue.modify_queue(|queue| {
+                // leave whatever's playing where it is
+                let Some((_, upcoming)) = queue.make_contiguous().split_first_mut() else {
+                    return 0;
+                };
+                upcoming.shuffle(&mut rand::thread_rng());
+                upcoming.len()
+            });
+            format!("Shuffled {} upcoming tracks", shuffled)
+        }
+        Control::Stop => {
+            queue.stop();
+            "Stopped and cleared the queue".to_owned()
+        }
+    }
+}
+
+/// Only people listening along get to control playback, or only DJs when the server has a DJ
+/// role.
+pub async fn check_control(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> Result<(), AppError> {
+    if let Some(role) = data.settings.get(guild_id).await.dj_role {
+        let member = guild_id.member(ctx, user_id).await?;
+        return check_dj(&ctx.cache, guild_id, &member, role);
+    }
+
+    let user_channel = ctx
+        .cache
+        .guild(guild_id)
+        .and_then(|guild| guild.voice_states.get(&user_id)?.channel_id);
+
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let Some(call) = manager.get(guild_id) else {
+        return Ok(());
+    };
+    let Some(bot_channel) = call.lock().await.current_channel() else {
+        return Ok(());
+    };
+
+    if user_channel.map(|c| c.get()) == Some(bot_channel.0.get()) {
+        Ok(())
+    } else {
+        Err(ErrorKind::PermissionDenied(
+            "control playback from outside my voice channel".to_owned(),
+        )
+        .into())
+    }
+}
+
+/// With a DJ role set, only people with it, and whoever manages the server, control playback.
+pub fn check_dj(
+    cache: &Cache,
+    guild_id: GuildId,
+    member: &Member,
+    role: RoleId,
+) -> Result<(), AppError> {
+    let is_manager = cache
+        .guild(guild_id)
+        // managing the server isn't affected by channel overwrites
+        .is_some_and(|guild| {
+            #[allow(deprecated)]
+            guild.member_permissions(member).manage_guild()
+        });
+    if is_manager || member.roles.contains(&role) {
+        Ok(())
+    } else {
+        Err(ErrorKind::PermissionDenied("control playback without the DJ role".to_owned()).into())
+    }
+}
+
+/// Command check wrapping [`check_control`].
+pub async fn can_control(ctx: Context<'_>) -> Result<bool, AppError> {
+    if let Some(guild_id) = ctx.guild_id() {
+        check_control(
+            ctx.serenity_context(),
+            ctx.data(),
+            guild_id,
+            ctx.author().id,
+        )
+        .await?;
+    }
+    Ok(true)
+}
+
+async fn run(ctx: Context<'_>, control: Control) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let Some(call) = manager.get(guild_id) else {
+        return Err(ErrorKind::NotInVoice.into());
+    };
+    let queue = call.lock().await.queue().clone();
+
+    let msg = apply(&queue, control).await;
+    ctx.data().now_playing.refresh(guild_id).await;
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Loops the current track, or stops looping it
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    rename = "loop",
+    guild_only,
+    check = "can_control"
+)]
+pub async fn loop_track(ctx: Context<'_>) -> Result<(), AppError> {
+    run(ctx, Control::Loop).await
+}
+
+/// Shuffles the upcoming tracks
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    guild_only,
+    check = "can_control"
+)]
+pub async fn shuffle(ctx: Context<'_>) -> Result<(), AppError> {
+    run(ctx, Control::Shuffle).await
+}
+
+/// Stops playing and clears the queue
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    guild_only,
+    check = "can_control"
+)]
+pub async fn stop(ctx: Context<'_>) -> Result<(), AppError> {
+    run(ctx, Control::Stop).await
+}
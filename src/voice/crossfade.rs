@@ -0,0 +1,356 @@
+use std::{
+    f32::consts::FRAC_PI_2,
+    sync::{Arc, PoisonError},
+    time::Duration,
+};
+
+use serenity::{all::GuildId, async_trait};
+use songbird::{
+    tracks::{TrackHandle, TrackQueue},
+    typemap::TypeMapKey,
+    Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent,
+};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::{AppError, Context, Data};
+
+use super::{
+    controls::can_control,
+    metadata::{AlbumId, Metadata},
+    volume::track_volume,
+};
+
+/// How often a playing track checks whether it's time to start the next one.
+const TICK: Duration = Duration::from_millis(200);
+/// How long before the fade (or the end, when gapless) the next track starts loading.
+const PRELOAD_LEAD: Duration = Duration::from_secs(8);
+pub const MAX_CROSSFADE: u8 = 12;
+
+/// Marks tracks that already have a transition scheduled, since resuming fires `Play` again.
+struct Scheduled;
+
+impl TypeMapKey for Scheduled {
+    type Value = ();
+}
+
+/// Schedules the transition out of every track that starts playing in a call.
+pub struct Crossfader {
+    pub queue: TrackQueue,
+    pub data: Arc<Data>,
+    pub guild_id: GuildId,
+}
+
+#[async_trait]
+impl VoiceEventHandler for Crossfader {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(&[(_, handle)]) = ctx else {
+            return None;
+        };
+
+        let settings = self.data.settings.get(self.guild_id).await;
+        if settings.crossfade == 0 && !settings.gapless {
+            return None;
+        }
+
+        {
+            let mut typemap = handle.typemap().write().await;
+            if typemap.contains_key::<Scheduled>() {
+                return None;
+            }
+            typemap.insert::<Scheduled>(());
+        }
+
+        let state = Arc::new(Mutex::new(FadeState::default()));
+        let added = handle
+            .add_event(
+                Event::Periodic(TICK, None),
+                FadeOut {
+                    queue: self.queue.clone(),
+                    data: self.data.clone(),
+                    guild_id: self.guild_id,
+                    state: state.clone(),
+                },
+            )
+            .and_then(|_| {
+                handle.add_event(
+                    TrackEvent::End.into(),
+                    FadeEnd {
+                        data: self.data.clone(),
+                        guild_id: self.guild_id,
+                        state,
+                    },
+                )
+            });
+        if let Err(e) = added {
+            warn!("Couldn't schedule crossfade: {}", e);
+        }
+
+        None
+    }
+}
+
+#[derive(Default)]
+struct FadeState {
+    preloaded: bool,
+    /// The track fading in, once the fade has started.
+    next: Option<TrackHandle>,
+}
+
+/// Watches a playing track's position, readies the next track and ramps the two volumes.
+struct FadeOut {
+    queue: TrackQueue,
+    data: Arc<Data>,
+    guild_id: GuildId,
+    state: Arc<Mutex<FadeState>>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for FadeOut {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(&[(track_state, handle)]) = ctx else {
+            return None;
+        };
+
+        // live streams never end, so there's nothing to fade into
+        let Some(duration) = handle
+            .typemap()
+            .read()
+            .await
+            .get::<Metadata>()
+            .and_then(|m| m.duration)
+        else {
+            return Some(Event::Cancel);
+        };
+
+        let settings = self.data.settings.get(self.guild_id).await;
+        let speed = self
+            .data
+            .playback
+            .filters(self.guild_id)
+            .await
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .speed;
+        let remaining = remaining(duration, speed, track_state.position);
+        let fade = f64::from(settings.crossfade);
+
+        let mut state = self.state.lock().await;
+
+        let next = match &state.next {
+            Some(next) => next.clone(),
+            None => {
+                if !preload_due(remaining, fade) {
+                    return None;
+                }
+
+                // only the head of the queue hands over to the next track
+                let queue = self.queue.current_queue();
+                if queue.first().map(|t| t.uuid()) != Some(handle.uuid()) {
+                    return Some(Event::Cancel);
+                }
+                let next = queue.get(1)?.clone();
+
+                if !state.preloaded {
+                    state.preloaded = true;
+                    drop(next.make_playable());
+
+                    if settings.gapless && same_album(handle, &next).await {
+                        // already loaded, so the queue can switch over without a gap
+                        info!("Playing the next album track gaplessly");
+                        return Some(Event::Cancel);
+                    }
+                }
+
+                // not time to fade yet, or there's no fade to do
+                fade_progress(remaining, fade)?;
+
+                // take ourselves off the queue so the next track becomes current while we fade
+                self.queue.modify_queue(|queue| {
+                    if queue.front().map(|t| t.uuid()) == Some(handle.uuid()) {
+                        queue.pop_front();
+                    }
+                });
+                let _ = next.set_volume(0.0);
+                if let Err(e) = next.play() {
+                    warn!("Couldn't start the next track for crossfade: {}", e);
+                    return Some(Event::Cancel);
+                }
+                state.next = Some(next.clone());
+                next
+            }
+        };
+
+        // with crossfade turned off partway through, it just finishes
+        let progress = fade_progress(remaining, fade).unwrap_or(1.0);
+        let (fade_out, fade_in) = equal_power(progress);
+        let _ = handle.set_volume(track_volume(handle, &settings).await * fade_out);
+        let _ = next.set_volume(track_volume(&next, &settings).await * fade_in);
+
+        if progress >= 1.0 {
+            let _ = handle.stop();
+            return Some(Event::Cancel);
+        }
+
+        None
+    }
+}
+
+/// Makes sure the incoming track ends up at full volume, however the outgoing one finished.
+struct FadeEnd {
+    data: Arc<Data>,
+    guild_id: GuildId,
+    state: Arc<Mutex<FadeState>>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for FadeEnd {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let next = self.state.lock().await.next.clone()?;
+        let settings = self.data.settings.get(self.guild_id).await;
+        let _ = next.set_volume(track_volume(&next, &settings).await);
+        None
+    }
+}
+
+/// Seconds of real time left in a track, going by how fast it's playing.
+fn remaining(duration: Duration, speed: f64, position: Duration) -> f64 {
+    duration.as_secs_f64() / speed - position.as_secs_f64()
+}
+
+/// Whether the next track should start loading, `remaining` seconds from the end with a `fade`
+/// second crossfade.
+fn preload_due(remaining: f64, fade: f64) -> bool {
+    remaining <= fade + PRELOAD_LEAD.as_secs_f64()
+}
+
+/// How far through the crossfade a track is, from 0 to 1, or `None` before it starts or when
+/// crossfade is off.
+fn fade_progress(remaining: f64, fade: f64) -> Option<f32> {
+    if fade <= 0.0 || remaining > fade {
+        return None;
+    }
+    Some((1.0 - remaining / fade).clamp(0.0, 1.0) as f32)
+}
+
+/// Gains for the outgoing and incoming track, keeping the overall loudness steady.
+fn equal_power(progress: f32) -> (f32, f32) {
+    let angle = progress * FRAC_PI_2;
+    (angle.cos(), angle.sin())
+}
+
+async fn same_album(a: &TrackHandle, b: &TrackHandle) -> bool {
+    let a = a.typemap().read().await.get::<AlbumId>().cloned();
+    let b = b.typemap().read().await.get::<AlbumId>().cloned();
+    matches!((a, b), (Some(a), Some(b)) if a == b)
+}
+
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    aliases("xfade"),
+    guild_only
+)]
+pub async fn crossfade(
+    ctx: Context<'_>,
+    #[description = "Seconds to fade between tracks, 0 to turn off"]
+    #[min = 0]
+    #[max = 12]
+    seconds: Option<u8>,
+    #[description = "Play tracks from the same album back to back without fading"] gapless: Option<
+        bool,
+    >,
+) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+
+    if let Some(seconds) = seconds {
+        if seconds > MAX_CROSSFADE {
+            ctx.say(format!(
+                "Crossfade has to be between 0 and {} seconds",
+                MAX_CROSSFADE
+            ))
+            .await?;
+            return Ok(());
+        }
+    }
+
+    if seconds.is_some() || gapless.is_some() {
+        can_control(ctx).await?;
+    }
+    let (settings, _) = ctx
+        .data()
+        .settings
+        .update(guild_id, |settings| {
+            if let Some(seconds) = seconds {
+                settings.crossfade = seconds;
+            }
+            if let Some(gapless) = gapless {
+                settings.gapless = gapless;
+            }
+        })
+        .await?;
+
+    let crossfade = match settings.crossfade {
+        0 => "off".to_owned(),
+        n => format!("{} seconds", n),
+    };
+    ctx.say(format!(
+        "Crossfade is {}, gapless album playback is {}",
+        crossfade,
+        if settings.gapless { "on" } else { "off" }
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_power_steady() {
+        let (out, into) = equal_power(0.0);
+        assert!((out - 1.0).abs() < 1e-6 && into.abs() < 1e-6);
+        let (out, into) = equal_power(1.0);
+        assert!(out.abs() < 1e-6 && (into - 1.0).abs() < 1e-6);
+        let (out, into) = equal_power(0.5);
+        assert!((out - into).abs() < 1e-6);
+
+        for progress in [0.0, 0.1, 0.25, 0.5, 0.75, 0.9, 1.0] {
+            let (out, into) = equal_power(progress);
+            assert!((out * out + into * into - 1.0).abs() < 1e-5, "{}", progress);
+        }
+    }
+
+    #[test]
+    fn works_out_whats_left_at_any_speed() {
+        let minutes = Duration::from_secs(180);
+        assert_eq!(remaining(minutes, 1.0, Duration::from_secs(170)), 10.0);
+        // twice as fast, so there's half as long to go
+        assert_eq!(remaining(minutes, 2.0, Duration::from_secs(80)), 10.0);
+    }
+
+    #[test]
+    fn preloads_before_the_fade() {
+        let lead = PRELOAD_LEAD.as_secs_f64();
+        assert!(!preload_due(5.0 + lead + 0.1, 5.0));
+        assert!(preload_due(5.0 + lead, 5.0));
+        assert!(preload_due(1.0, 5.0));
+        // gapless only, without a fade
+        assert!(!preload_due(lead + 0.1, 0.0));
+        assert!(preload_due(lead, 0.0));
+    }
+
+    #[test]
+    fn fades_over_the_last_seconds() {
+        assert_eq!(fade_progress(6.0, 5.0), None);
+        assert_eq!(fade_progress(5.0, 5.0), Some(0.0));
+        assert_eq!(fade_progress(2.5, 5.0), Some(0.5));
+        assert_eq!(fade_progress(0.0, 5.0), Some(1.0));
+        // past the end, which happens when a tick comes late
+        assert_eq!(fade_progress(-1.0, 5.0), Some(1.0));
+        assert_eq!(fade_progress(1.0, 0.0), None);
+    }
+}
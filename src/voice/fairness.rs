@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    time::Duration,
+};
+
+use serenity::all::UserId;
+use songbird::tracks::TrackQueue;
+use uuid::Uuid;
+
+use crate::{
+    err::{AppError, ErrorKind},
+    helpers::d2hms,
+    settings::GuildSettings,
+    Context,
+};
+
+use super::metadata::RequestedBy;
+
+/// Orders `items` in rounds, where each round has the next item of every key in the order the
+/// keys first appear. Items with the same key keep their relative order.
+pub fn fair_order<T, K: Eq + Hash>(items: Vec<T>, key: impl Fn(&T) -> K) -> Vec<T> {
+    let mut groups: Vec<VecDeque<T>> = Vec::new();
+    let mut index: HashMap<K, usize> = HashMap::new();
+    let len = items.len();
+
+    for item in items {
+        let group = *index.entry(key(&item)).or_insert_with(|| {
+            groups.push(VecDeque::new());
+            groups.len() - 1
+        });
+        groups[group].push_back(item);
+    }
+
+    let mut ordered = Vec::with_capacity(len);
+    while ordered.len() < len {
+        for group in &mut groups {
+            if let Some(item) = group.pop_front() {
+                ordered.push(item);
+            }
+        }
+    }
+    ordered
+}
+
+/// Reorders the upcoming tracks round-robin by requester. The playing track counts as its
+/// requester's turn, so the next one goes to somebody else when anyone else is waiting.
+pub async fn rebalance(queue: &TrackQueue) {
+    let tracks = queue.current_queue();
+    let mut requesters = Vec::with_capacity(tracks.len());
+    for track in &tracks {
+        let requester = track
+            .typemap()
+            .read()
+            .await
+            .get::<RequestedBy>()
+            .map(|r| r.id);
+        requesters.push((track.uuid(), requester));
+    }
+
+    let positions: HashMap<Uuid, usize> = fair_order(requesters, |(_, requester)| *requester)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (uuid, _))| (uuid, i))
+        .collect();
+
+    queue.modify_queue(|queue| {
+        // whatever's playing stays put, even if it changed since we looked
+        if let Some((_, upcoming)) = queue.make_contiguous().split_first_mut() {
+            upcoming.sort_by_key(|t| positions.get(&t.uuid()).copied().unwrap_or(usize::MAX));
+        }
+    });
+}
+
+/// Makes sure `user` may queue another track of `duration`.
+pub async fn check_limits(
+    settings: &GuildSettings,
+    queue: &TrackQueue,
+    user: UserId,
+    duration: Option<Duration>,
+) -> Result<(), AppError> {
+    if let (Some(max), Some(duration)) = (settings.max_duration(), duration) {
+        if duration > max {
+            return Err(ErrorKind::PermissionDenied(format!(
+                "queue tracks longer than {}",
+                d2hms(max)
+            ))
+            .into());
+        }
+    }
+
+    if let Some(max) = settings.max_queue {
+        if queue.len() >= max {
+            return Err(ErrorKind::PermissionDenied(format!(
+                "add more than {} tracks to the queue",
+                max
+            ))
+            .into());
+        }
+    }
+
+    if let Some(max) = settings.max_per_user {
+        let mut queued = 0;
+        for track in queue.current_queue() {
+            if track
+                .typemap()
+                .read()
+                .await
+                .get::<RequestedBy>()
+                .is_some_and(|r| r.id == user)
+            {
+                queued += 1;
+            }
+        }
+        if queued >= max {
+            return Err(ErrorKind::PermissionDenied(format!(
+                "have more than {} tracks in the queue at once",
+                max
+            ))
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets how the queue is shared between the people using it
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    rename = "queuerules",
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn queue_rules(
+    ctx: Context<'_>,
+    #[description = "Take turns between requesters instead of first come, first served"]
+    fair: Option<bool>,
+    #[description = "Tracks each person can have queued at once, 0 for no limit"] per_user: Option<
+        u16,
+    >,
+    #[description = "Longest track anyone can queue, in minutes, 0 for no limit"]
+    max_minutes: Option<u32>,
+) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+
+    let (settings, _) = ctx
+        .data()
+        .settings
+        .update(guild_id, |settings| {
+            if let Some(fair) = fair {
+                settings.fair_queue = fair;
+            }
+            if let Some(per_user) = per_user {
+                settings.max_per_user = Some(per_user as usize).filter(|&n| n > 0);
+            }
+            if let Some(minutes) = max_minutes {
+                settings.max_track_minutes = Some(minutes).filter(|&m| m > 0);
+            }
+        })
+        .await?;
+
+    if fair == Some(true) {
+        if let Some(call) = songbird::get(ctx.serenity_context())
+            .await
+            .expect("Songbird Voice client placed in at initialisation.")
+            .get(guild_id)
+        {
+            let queue = call.lock().await.queue().clone();
+            rebalance(&queue).await;
+            ctx.data().now_playing.refresh(guild_id).await;
+        }
+    }
+
+    ctx.say(format!(
+        "Fair queue is {}, each person can queue {}, tracks can be {}",
+        if settings.fair_queue { "on" } else { "off" },
+        match settings.max_per_user {
+            Some(n) => format!("up to {} tracks", n),
+            None => "as many tracks as they like".to_owned(),
+        },
+        match settings.max_duration() {
+            Some(d) => format!("up to {} long", d2hms(d)),
+            None => "any length".to_owned(),
+        }
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_turns_in_order_of_first_request() {
+        let queue = vec![("a", 1), ("a", 2), ("a", 3), ("b", 1), ("c", 1), ("b", 2)];
+        let ordered = fair_order(queue, |(who, _)| *who);
+        assert_eq!(
+            ordered,
+            vec![("a", 1), ("b", 1), ("c", 1), ("a", 2), ("b", 2), ("a", 3)]
+        );
+    }
+
+    #[test]
+    fn single_requester_keeps_their_order() {
+        let queue = vec![1, 2, 3];
+        assert_eq!(fair_order(queue, |_| ()), vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,218 @@
+//! Small DSP building blocks, all working on interleaved `f32` PCM.
+
+use std::f32::consts::PI;
+
+/// Second-order IIR filter using the RBJ audio EQ cookbook formulas.
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    // per-channel (x1, x2, y1, y2)
+    state: Vec<[f32; 4]>,
+}
+
+impl Biquad {
+    fn from_coefficients(b: [f32; 3], a: [f32; 3], channels: usize) -> Self {
+        Self {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0],
+            state: vec![[0.0; 4]; channels],
+        }
+    }
+
+    /// Boosts or cuts a band around `freq`.
+    pub fn peaking(sample_rate: u32, freq: f32, q: f32, gain_db: f32, channels: usize) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+
+        Self::from_coefficients(
+            [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+            [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            channels,
+        )
+    }
+
+    /// Boosts or cuts everything below `freq`.
+    pub fn low_shelf(sample_rate: u32, freq: f32, gain_db: f32, channels: usize) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate as f32;
+        let cos = w0.cos();
+        // shelf slope of 1
+        let alpha = w0.sin() / 2.0 * 2f32.sqrt();
+        let sqrt_a = 2.0 * a.sqrt() * alpha;
+
+        Self::from_coefficients(
+            [
+                a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a),
+            ],
+            [
+                (a + 1.0) + (a - 1.0) * cos + sqrt_a,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - sqrt_a,
+            ],
+            channels,
+        )
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        let channels = self.state.len();
+        for frame in samples.chunks_exact_mut(channels) {
+            for (sample, state) in frame.iter_mut().zip(self.state.iter_mut()) {
+                let [x1, x2, y1, y2] = *state;
+                let x0 = *sample;
+                let y0 = self.b0 * x0 + self.b1 * x1 + self.b2 * x2 - self.a1 * y1 - self.a2 * y2;
+                *state = [x0, x1, y0, y1];
+                *sample = y0;
+            }
+        }
+    }
+}
+
+/// Plays audio back faster or slower by resampling, which shifts pitch along with speed
+/// (the classic nightcore/vaporwave sound).
+#[derive(Debug, Clone)]
+pub struct Timescale {
+    rate: f64,
+    channels: usize,
+    // fractional read position into the current buffer; -1 refers to `prev`
+    pos: f64,
+    prev: Vec<f32>,
+}
+
+impl Timescale {
+    pub fn new(rate: f64, channels: usize) -> Self {
+        Self {
+            rate,
+            channels,
+            pos: 0.0,
+            prev: vec![0.0; channels],
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let channels = self.channels;
+        let frames = input.len() / channels;
+        if frames == 0 {
+            return Vec::new();
+        }
+
+        let frame = |i: isize| -> &[f32] {
+            if i < 0 {
+                &self.prev
+            } else {
+                &input[i as usize * channels..][..channels]
+            }
+        };
+
+        let mut out = Vec::with_capacity((frames as f64 / self.rate) as usize * channels + 1);
+        let mut pos = self.pos;
+        while pos < (frames - 1) as f64 {
+            let i0 = pos.floor();
+            let t = (pos - i0) as f32;
+            let (a, b) = (frame(i0 as isize), frame(i0 as isize + 1));
+            out.extend(a.iter().zip(b).map(|(a, b)| a + (b - a) * t));
+            pos += self.rate;
+        }
+
+        self.pos = pos - frames as f64;
+        self.prev
+            .copy_from_slice(&input[(frames - 1) * channels..][..channels]);
+        out
+    }
+}
+
+/// Folds every channel into their average, keeping the channel count.
+pub fn downmix_mono(samples: &mut [f32], channels: usize) {
+    if channels < 2 {
+        return;
+    }
+    for frame in samples.chunks_exact_mut(channels) {
+        let avg = frame.iter().sum::<f32>() / channels as f32;
+        frame.fill(avg);
+    }
+}
+
+/// Keeps boosted signals from wrapping around when they're converted back for the mixer.
+pub fn hard_clip(samples: &mut [f32]) {
+    for sample in samples {
+        *sample = sample.clamp(-1.0, 1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, sample_rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (2.0 * PI * freq * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn low_shelf_boosts_bass_and_leaves_treble() {
+        let mut low = sine(50.0, 48_000, 48_000);
+        let mut high = sine(8_000.0, 48_000, 48_000);
+        let (low_before, high_before) = (rms(&low), rms(&high));
+
+        Biquad::low_shelf(48_000, 150.0, 12.0, 1).process(&mut low);
+        Biquad::low_shelf(48_000, 150.0, 12.0, 1).process(&mut high);
+
+        assert!(rms(&low) > low_before * 3.0);
+        assert!((rms(&high) / high_before - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn peaking_with_no_gain_is_transparent() {
+        let mut samples = sine(1_000.0, 48_000, 4_800);
+        let original = samples.clone();
+        Biquad::peaking(48_000, 1_000.0, 1.0, 0.0, 1).process(&mut samples);
+        for (a, b) in samples.iter().zip(&original) {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn timescale_changes_length_across_buffers() {
+        let input = sine(440.0, 48_000, 48_000);
+        let mut fast = Timescale::new(1.25, 1);
+        let out: usize = input.chunks(1_000).map(|c| fast.process(c).len()).sum();
+        assert!((out as i64 - 38_400).abs() <= 2, "got {out} frames");
+
+        let mut slow = Timescale::new(0.8, 2);
+        let stereo: Vec<f32> = input.iter().flat_map(|s| [*s, -*s]).collect();
+        let out = slow.process(&stereo);
+        assert_eq!(out.len() % 2, 0);
+        assert!((out.len() as i64 / 2 - 60_000).abs() <= 2);
+    }
+
+    #[test]
+    fn timescale_at_unity_is_passthrough() {
+        let input: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let mut ts = Timescale::new(1.0, 1);
+        let mut out = ts.process(&input[..50]);
+        out.extend(ts.process(&input[50..]));
+        assert_eq!(&out[..], &input[..99]);
+    }
+
+    #[test]
+    fn mono_averages_channels() {
+        let mut samples = vec![1.0, 0.0, 0.5, -0.5];
+        downmix_mono(&mut samples, 2);
+        assert_eq!(samples, vec![0.5, 0.5, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,292 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
+use dsp::{downmix_mono, hard_clip, Biquad, Timescale};
+use poise::ChoiceParameter;
+
+use crate::{AppError, Context};
+
+use super::controls::can_control;
+
+pub mod dsp;
+pub mod source;
+
+pub type SharedFilters = Arc<RwLock<FilterSettings>>;
+
+/// Centre frequencies of the equalizer bands, in Hz.
+pub const EQ_BANDS: [f32; 5] = [60.0, 230.0, 910.0, 3_600.0, 14_000.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum EqPreset {
+    Flat,
+    Rock,
+    Pop,
+    Jazz,
+    Classical,
+    Electronic,
+    Vocal,
+}
+
+impl EqPreset {
+    /// Gain for each of [`EQ_BANDS`], in dB.
+    pub fn gains(&self) -> [f32; 5] {
+        match self {
+            EqPreset::Flat => [0.0, 0.0, 0.0, 0.0, 0.0],
+            EqPreset::Rock => [4.0, 2.0, -2.0, 2.0, 4.0],
+            EqPreset::Pop => [-1.0, 2.0, 4.0, 2.0, -1.0],
+            EqPreset::Jazz => [3.0, 1.0, -1.0, 1.0, 3.0],
+            EqPreset::Classical => [3.0, 1.0, 0.0, 2.0, 3.0],
+            EqPreset::Electronic => [5.0, 3.0, 0.0, 2.0, 4.0],
+            EqPreset::Vocal => [-2.0, -1.0, 3.0, 4.0, 1.0],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Filter {
+    Equalizer,
+    #[name = "Bass boost"]
+    BassBoost,
+    Nightcore,
+    Vaporwave,
+    Speed,
+    Mono,
+}
+
+/// Which filters a guild has switched on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterSettings {
+    pub eq: Option<EqPreset>,
+    /// Low-shelf boost in dB.
+    pub bass_boost: Option<f32>,
+    /// Playback rate, where 1.0 is unchanged.
+    pub speed: f64,
+    pub mono: bool,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        Self {
+            eq: None,
+            bass_boost: None,
+            speed: 1.0,
+            mono: false,
+        }
+    }
+}
+
+impl FilterSettings {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Human readable list of active filters.
+    pub fn describe(&self) -> Vec<String> {
+        let mut active = Vec::new();
+        if let Some(eq) = self.eq {
+            active.push(format!("Equalizer: {}", eq.name()));
+        }
+        if let Some(db) = self.bass_boost {
+            active.push(format!("Bass boost: +{:.0} dB", db));
+        }
+        if self.speed != 1.0 {
+            active.push(format!("Speed: {:.2}x", self.speed));
+        }
+        if self.mono {
+            active.push("Mono".to_owned());
+        }
+        active
+    }
+}
+
+/// A ready-to-run set of filters for one stream.
+pub struct FilterChain {
+    channels: usize,
+    eq: Vec<Biquad>,
+    bass: Option<Biquad>,
+    timescale: Option<Timescale>,
+    mono: bool,
+}
+
+impl FilterChain {
+    pub fn new(settings: &FilterSettings, sample_rate: u32, channels: usize) -> Self {
+        let eq = settings
+            .eq
+            .map(|preset| {
+                EQ_BANDS
+                    .iter()
+                    .zip(preset.gains())
+                    .filter(|(_, gain)| *gain != 0.0)
+                    .map(|(freq, gain)| Biquad::peaking(sample_rate, *freq, 1.0, gain, channels))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            channels,
+            eq,
+            bass: settings
+                .bass_boost
+                .map(|db| Biquad::low_shelf(sample_rate, 150.0, db, channels)),
+            timescale: (settings.speed != 1.0).then(|| Timescale::new(settings.speed, channels)),
+            mono: settings.mono,
+        }
+    }
+
+    /// Runs a buffer of interleaved samples through the chain.
+    /// The output can be a different length from the input when the speed is changed.
+    pub fn process(&mut self, mut samples: Vec<f32>) -> Vec<f32> {
+        for band in &mut self.eq {
+            band.process(&mut samples);
+        }
+        if let Some(bass) = &mut self.bass {
+            bass.process(&mut samples);
+        }
+        if let Some(timescale) = &mut self.timescale {
+            samples = timescale.process(&samples);
+        }
+        if self.mono {
+            downmix_mono(&mut samples, self.channels);
+        }
+        hard_clip(&mut samples);
+        samples
+    }
+}
+
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    subcommands("set", "clear", "list"),
+    subcommand_required,
+    guild_only
+)]
+pub async fn filter(_ctx: Context<'_>) -> Result<(), AppError> {
+    Ok(())
+}
+
+/// Turns on an audio filter for this server
+#[poise::command(slash_command, prefix_command, guild_only, check = "can_control")]
+pub async fn set(
+    ctx: Context<'_>,
+    #[description = "The filter to turn on"] filter: Filter,
+    #[description = "Equalizer preset"] preset: Option<EqPreset>,
+    #[description = "Bass boost in dB (1-15) or speed multiplier (0.5-2.0)"] amount: Option<f64>,
+) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+    let filters = ctx.data().playback.filters(guild_id).await;
+
+    let result = {
+        let mut settings = filters.write().unwrap_or_else(PoisonError::into_inner);
+        match filter {
+            Filter::Equalizer => {
+                settings.eq = Some(preset.unwrap_or(EqPreset::Flat));
+                Ok(())
+            }
+            Filter::BassBoost => match amount.unwrap_or(6.0) {
+                db if (1.0..=15.0).contains(&db) => {
+                    settings.bass_boost = Some(db as f32);
+                    Ok(())
+                }
+                _ => Err("Bass boost has to be between 1 and 15 dB"),
+            },
+            Filter::Nightcore => {
+                settings.speed = 1.25;
+                Ok(())
+            }
+            Filter::Vaporwave => {
+                settings.speed = 0.8;
+                Ok(())
+            }
+            Filter::Speed => match amount {
+                Some(speed) if (0.5..=2.0).contains(&speed) => {
+                    settings.speed = speed;
+                    Ok(())
+                }
+                _ => Err("Speed has to be between 0.5 and 2.0"),
+            },
+            Filter::Mono => {
+                settings.mono = true;
+                Ok(())
+            }
+        }
+    };
+
+    match result {
+        Ok(()) => ctx.say(format!("Turned on {}", filter.name())).await?,
+        Err(msg) => ctx.say(msg).await?,
+    };
+
+    Ok(())
+}
+
+/// Turns off one audio filter, or all of them
+#[poise::command(slash_command, prefix_command, guild_only, check = "can_control")]
+pub async fn clear(
+    ctx: Context<'_>,
+    #[description = "The filter to turn off, leave empty for all"] filter: Option<Filter>,
+) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+    let filters = ctx.data().playback.filters(guild_id).await;
+
+    {
+        let mut settings = filters.write().unwrap_or_else(PoisonError::into_inner);
+        match filter {
+            None => *settings = FilterSettings::default(),
+            Some(Filter::Equalizer) => settings.eq = None,
+            Some(Filter::BassBoost) => settings.bass_boost = None,
+            Some(Filter::Nightcore | Filter::Vaporwave | Filter::Speed) => settings.speed = 1.0,
+            Some(Filter::Mono) => settings.mono = false,
+        }
+    }
+
+    match filter {
+        Some(filter) => ctx.say(format!("Turned off {}", filter.name())).await?,
+        None => ctx.say("Turned off all filters").await?,
+    };
+
+    Ok(())
+}
+
+/// Shows the active and available audio filters
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn list(ctx: Context<'_>) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+    let active = ctx
+        .data()
+        .playback
+        .filters(guild_id)
+        .await
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .describe();
+
+    let mut msg = String::new();
+    if active.is_empty() {
+        msg.push_str("No filters active.\n");
+    } else {
+        msg.push_str("Active filters:\n");
+        for filter in active {
+            msg.push_str(&format!("- {}\n", filter));
+        }
+    }
+
+    msg.push_str("\nAvailable: ");
+    msg.push_str(
+        &Filter::list()
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    msg.push_str("\nEqualizer presets: ");
+    msg.push_str(
+        &EqPreset::list()
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+
+    ctx.say(msg).await?;
+    Ok(())
+}
@@ -0,0 +1,373 @@
+use std::{
+    io::{self, ErrorKind, Read, Seek, SeekFrom},
+    sync::PoisonError,
+};
+
+use serenity::async_trait;
+use songbird::input::{
+    codecs::{CODEC_REGISTRY, PROBE},
+    AudioStream, AudioStreamError, AuxMetadata, Compose, Input, RawAdapter,
+};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions},
+    errors::Error as SymphoniaError,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::{MediaSource, MediaSourceStream},
+    meta::MetadataOptions,
+    units::{Time, TimeBase},
+};
+use tracing::warn;
+
+use super::{FilterChain, FilterSettings, SharedFilters};
+
+/// Length of the header [`RawAdapter`] puts in front of the PCM, which seek offsets include.
+const RAW_HEADER_LEN: u64 = 16;
+
+/// Wraps a lazy input so its decoded audio runs through the guild's filter chain
+/// before it reaches the mixer.
+pub struct FilteredInput {
+    inner: Box<dyn Compose>,
+    filters: SharedFilters,
+}
+
+impl FilteredInput {
+    /// Wraps `input`. Inputs which are already live are passed through untouched.
+    pub fn wrap(input: Input, filters: SharedFilters) -> Input {
+        match input {
+            Input::Lazy(inner) => Self { inner, filters }.into(),
+            live => live,
+        }
+    }
+}
+
+impl From<FilteredInput> for Input {
+    fn from(val: FilteredInput) -> Self {
+        Input::Lazy(Box::new(val))
+    }
+}
+
+#[async_trait]
+impl Compose for FilteredInput {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let stream = self.inner.create()?;
+        filtered_stream(stream, self.filters.clone())
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let stream = if self.inner.should_create_async() {
+            self.inner.create_async().await?
+        } else {
+            self.inner.create()?
+        };
+
+        // probing reads from the stream, so keep it off the async runtime
+        let filters = self.filters.clone();
+        tokio::task::spawn_blocking(move || filtered_stream(stream, filters))
+            .await
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        self.inner.aux_metadata().await
+    }
+}
+
+fn filtered_stream(
+    stream: AudioStream<Box<dyn MediaSource>>,
+    filters: SharedFilters,
+) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+    let hint = stream.hint.unwrap_or_default();
+    let seekable = stream.input.is_seekable();
+    let mss = MediaSourceStream::new(stream.input, Default::default());
+
+    let probed = PROBE
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+
+    let format = probed.format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| AudioStreamError::Fail("No playable track in stream".into()))?;
+    let track_id = track.id;
+    let time_base = track.codec_params.time_base;
+    let decoder = CODEC_REGISTRY
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+
+    let mut source = FilteredSource {
+        format,
+        decoder,
+        track_id,
+        time_base,
+        seekable,
+        filters,
+        chain: None,
+        sample_rate: 0,
+        channels: 0,
+        out: Vec::new(),
+        out_pos: 0,
+        skip_frames: 0,
+    };
+
+    // decode the first packet up front so we know the real output format
+    source
+        .fill()
+        .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+
+    let (sample_rate, channels) = (source.sample_rate, source.channels as u32);
+    Ok(AudioStream {
+        input: Box::new(RawAdapter::new(source, sample_rate, channels)),
+        hint: None,
+    })
+}
+
+/// Decodes the inner stream and hands out filtered, interleaved `f32` PCM bytes.
+struct FilteredSource {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    /// Whether the stream underneath can seek, which decides whether we can.
+    seekable: bool,
+    filters: SharedFilters,
+    chain: Option<(FilterSettings, FilterChain)>,
+    sample_rate: u32,
+    channels: usize,
+    out: Vec<u8>,
+    out_pos: usize,
+    /// Frames still to drop after a seek landed short of where it was asked to.
+    skip_frames: usize,
+}
+
+impl FilteredSource {
+    /// Decodes packets until there's output to hand out. Returns `false` at end of stream.
+    fn fill(&mut self) -> io::Result<bool> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                    return Ok(false)
+                }
+                Err(SymphoniaError::ResetRequired) => return Ok(false),
+                Err(e) => return Err(io::Error::other(e)),
+            };
+
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(SymphoniaError::DecodeError(e)) => {
+                    warn!("Skipping undecodable packet: {}", e);
+                    continue;
+                }
+                Err(e) => return Err(io::Error::other(e)),
+            };
+
+            let spec = *decoded.spec();
+            if self.channels == 0 {
+                self.sample_rate = spec.rate;
+                self.channels = spec.channels.count();
+            }
+
+            let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buf.copy_interleaved_ref(decoded);
+
+            let skipped = (self.skip_frames * self.channels).min(buf.samples().len());
+            self.skip_frames -= skipped / self.channels.max(1);
+            let samples = self.run_chain(buf.samples()[skipped..].to_vec());
+            if samples.is_empty() {
+                continue;
+            }
+
+            self.out.clear();
+            self.out_pos = 0;
+            self.out
+                .extend(samples.iter().flat_map(|sample| sample.to_le_bytes()));
+            return Ok(true);
+        }
+    }
+
+    fn run_chain(&mut self, samples: Vec<f32>) -> Vec<f32> {
+        // the settings are plain values, so a panic while they were being changed can't leave
+        // them half written, and the audio thread shouldn't die over it
+        let settings = self
+            .filters
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+
+        // rebuild the chain whenever someone changes the guild's filters mid-track
+        if self.chain.as_ref().map(|(active, _)| active) != Some(&settings) {
+            let chain = FilterChain::new(&settings, self.sample_rate, self.channels);
+            self.chain = Some((settings, chain));
+        }
+
+        match &mut self.chain {
+            Some((active, chain)) if !active.is_empty() => chain.process(samples),
+            _ => samples,
+        }
+    }
+}
+
+impl Read for FilteredSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.out_pos >= self.out.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+
+        let n = buf.len().min(self.out.len() - self.out_pos);
+        buf[..n].copy_from_slice(&self.out[self.out_pos..][..n]);
+        self.out_pos += n;
+        Ok(n)
+    }
+}
+
+impl Seek for FilteredSource {
+    /// Seeks the stream underneath to the frame `RawAdapter` asks for. Offsets count the raw
+    /// header, and the position returned is of the PCM alone, which is how `RawAdapter` expects
+    /// them.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let SeekFrom::Start(pos) = pos else {
+            return Err(ErrorKind::Unsupported.into());
+        };
+        if !self.seekable || self.channels == 0 || self.sample_rate == 0 {
+            return Err(ErrorKind::Unsupported.into());
+        }
+
+        let frame_len = (self.channels * std::mem::size_of::<f32>()) as u64;
+        let frame = pos.saturating_sub(RAW_HEADER_LEN) / frame_len;
+        // aim half a frame in, so rounding the time back to a timestamp can't land a frame short
+        let time = Time::new(
+            frame / self.sample_rate as u64,
+            ((frame % self.sample_rate as u64) as f64 + 0.5) / self.sample_rate as f64,
+        );
+
+        let seeked = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time,
+                    track_id: Some(self.track_id),
+                },
+            )
+            .map_err(io::Error::other)?;
+
+        // demuxers can land on the packet before the one asked for, so drop the difference
+        let early = seeked.required_ts.saturating_sub(seeked.actual_ts);
+        self.skip_frames = match self.time_base {
+            Some(base) => {
+                let early = base.calc_time(early);
+                ((early.seconds as f64 + early.frac) * self.sample_rate as f64).round() as usize
+            }
+            None => 0,
+        };
+        self.decoder.reset();
+        self.out.clear();
+        self.out_pos = 0;
+        // start the filters afresh, leftover echoes from before the seek would sound wrong
+        self.chain = None;
+
+        Ok(frame * frame_len)
+    }
+}
+
+impl MediaSource for FilteredSource {
+    fn is_seekable(&self) -> bool {
+        self.seekable
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        sync::{Arc, RwLock},
+    };
+
+    use super::*;
+
+    const RATE: u32 = 8_000;
+
+    /// A mono 16-bit WAV whose every sample is its frame number, so positions can be read back.
+    fn counting_wav(frames: u16) -> Vec<u8> {
+        let data_len = frames as u32 * 2;
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF");
+        wav.extend((36 + data_len).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(RATE.to_le_bytes());
+        wav.extend((RATE * 2).to_le_bytes());
+        wav.extend(2u16.to_le_bytes());
+        wav.extend(16u16.to_le_bytes());
+        wav.extend(b"data");
+        wav.extend(data_len.to_le_bytes());
+        for frame in 0..frames {
+            wav.extend(frame.to_le_bytes());
+        }
+        wav
+    }
+
+    fn stream(frames: u16) -> Box<dyn MediaSource> {
+        let input: Box<dyn MediaSource> = Box::new(Cursor::new(counting_wav(frames)));
+        let mut hint = symphonia::core::probe::Hint::new();
+        hint.with_extension("wav");
+        let filters = Arc::new(RwLock::new(FilterSettings::default()));
+        filtered_stream(
+            AudioStream {
+                input,
+                hint: Some(hint),
+            },
+            filters,
+        )
+        .unwrap()
+        .input
+    }
+
+    /// Reads the next sample, undoing the 16-bit to float conversion.
+    fn next_frame(stream: &mut Box<dyn MediaSource>) -> u16 {
+        let mut sample = [0; 4];
+        stream.read_exact(&mut sample).unwrap();
+        (f32::from_le_bytes(sample) * 32_768.0).round() as u16
+    }
+
+    #[test]
+    fn seeks_through_to_the_stream_underneath() {
+        let mut stream = stream(RATE as u16 * 2);
+        assert!(stream.is_seekable());
+
+        let mut header = [0; RAW_HEADER_LEN as usize];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(next_frame(&mut stream), 0);
+
+        for frame in [12_000, 3, 0, 9_001] {
+            stream
+                .seek(SeekFrom::Start(RAW_HEADER_LEN + frame * 4))
+                .unwrap();
+            assert_eq!(next_frame(&mut stream), frame as u16);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use serenity::{all::GuildId, async_trait};
+use songbird::{typemap::TypeMapKey, Event, EventContext, EventHandler as VoiceEventHandler};
+use tokio::sync::RwLock;
+
+use crate::Data;
+
+use super::metadata::{title_of, Metadata, RequestedBy, Requester};
+
+/// How many played tracks are remembered per guild.
+const KEEP: usize = 50;
+
+/// A track that started playing.
+#[derive(Debug, Clone, Serialize)]
+pub struct Played {
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub requester: Option<Requester>,
+    /// Unix timestamp, in seconds.
+    pub played_at: u64,
+}
+
+/// What each guild has played recently, newest first.
+#[derive(Default)]
+pub struct History {
+    guilds: RwLock<HashMap<GuildId, VecDeque<Played>>>,
+}
+
+impl History {
+    pub async fn record(&self, guild_id: GuildId, played: Played) {
+        let mut guilds = self.guilds.write().await;
+        let history = guilds.entry(guild_id).or_default();
+        history.push_front(played);
+        history.truncate(KEEP);
+    }
+
+    pub async fn recent(&self, guild_id: GuildId) -> Vec<Played> {
+        self.guilds
+            .read()
+            .await
+            .get(&guild_id)
+            .map(|h| h.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Marks a track that's already in the history, since resuming it fires the play event again.
+struct Recorded;
+
+impl TypeMapKey for Recorded {
+    type Value = ();
+}
+
+/// Adds every track that starts playing in a call to the guild's history.
+pub struct RecordHistory {
+    pub data: Arc<Data>,
+    pub guild_id: GuildId,
+}
+
+#[async_trait]
+impl VoiceEventHandler for RecordHistory {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(tracks) = ctx else {
+            return None;
+        };
+        let played_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        for (_, handle) in tracks.iter() {
+            let mut typemap = handle.typemap().write().await;
+            if typemap.contains_key::<Recorded>() {
+                continue;
+            }
+            typemap.insert::<Recorded>(());
+            let metadata = typemap.get::<Metadata>();
+            let played = Played {
+                title: title_of(&typemap),
+                url: metadata.and_then(|m| m.source_url.clone()),
+                requester: typemap.get::<RequestedBy>().cloned(),
+                played_at,
+            };
+            self.data.history.record(self.guild_id, played).await;
+        }
+        None
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serenity::{all::GuildId, async_trait};
+use songbird::{
+    tracks::TrackQueue, Event, EventContext, EventHandler as VoiceEventHandler, Songbird,
+};
+use tracing::{info, warn};
+
+use crate::Data;
+
+/// How often the call checks whether it's been idle for too long.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Leaves the call once the queue has been empty for the guild's idle timeout.
+pub struct IdleTimeout {
+    manager: Arc<Songbird>,
+    data: Arc<Data>,
+    guild_id: GuildId,
+    queue: TrackQueue,
+    idle_since: Mutex<Option<Instant>>,
+}
+
+impl IdleTimeout {
+    pub fn new(
+        manager: Arc<Songbird>,
+        data: Arc<Data>,
+        guild_id: GuildId,
+        queue: TrackQueue,
+    ) -> Self {
+        Self {
+            manager,
+            data,
+            guild_id,
+            queue,
+            idle_since: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for IdleTimeout {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let idle_for = {
+            let mut idle_since = self.idle_since.lock().unwrap();
+            if !self.queue.is_empty() {
+                *idle_since = None;
+                return None;
+            }
+            idle_since.get_or_insert_with(Instant::now).elapsed()
+        };
+
+        let minutes = self.data.settings.get(self.guild_id).await.idle_timeout;
+        if minutes == 0 || idle_for < Duration::from_secs(minutes as u64 * 60) {
+            return None;
+        }
+
+        info!(
+            "Leaving guild {} after {} idle minutes",
+            self.guild_id, minutes
+        );
+        if let Err(e) = self.manager.remove(self.guild_id).await {
+            warn!("Failed to leave idle call: {}", e);
+        }
+        Some(Event::Cancel)
+    }
+}
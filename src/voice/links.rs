@@ -0,0 +1,201 @@
+use reqwest::{Client as HttpClient, Url};
+
+use crate::{err::ErrorKind, odesli, AppError};
+
+use super::source::TrackSource;
+
+/// Music platforms we can pick song links out of messages for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    YouTube,
+    Spotify,
+    AppleMusic,
+    SoundCloud,
+}
+
+impl Platform {
+    fn of(url: &Url) -> Option<Self> {
+        let host = url.host_str()?;
+        let host = host.strip_prefix("www.").unwrap_or(host);
+        match host {
+            "youtube.com" | "m.youtube.com" | "music.youtube.com" | "youtu.be" => {
+                Some(Platform::YouTube)
+            }
+            "open.spotify.com" => Some(Platform::Spotify),
+            "music.apple.com" => Some(Platform::AppleMusic),
+            "soundcloud.com" | "m.soundcloud.com" | "on.soundcloud.com" => {
+                Some(Platform::SoundCloud)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A song link somebody posted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub platform: Platform,
+    pub url: String,
+}
+
+/// Every link to a platform we know in `text`, in order and without repeats. Links can be
+/// wrapped in `<>` to hide their embed or be part of a markdown link.
+pub fn find_links(text: &str) -> Vec<Link> {
+    let mut links: Vec<Link> = Vec::new();
+    for word in text.split(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '(' | '[' | ']'))
+    {
+        let Some(start) = word.find("https://") else {
+            continue;
+        };
+        let word = word[start..].trim_end_matches(['.', ',', '!', '?', ')', '*', '_', '|']);
+        let Ok(url) = Url::parse(word) else {
+            continue;
+        };
+        let Some(platform) = Platform::of(&url) else {
+            continue;
+        };
+        if !links.iter().any(|l| l.url == word) {
+            links.push(Link {
+                platform,
+                url: word.to_owned(),
+            });
+        }
+    }
+    links
+}
+
+/// Where to play a link from. yt-dlp can't play Spotify or Apple Music, so those are swapped
+/// for the same song on YouTube, or a search for it when Odesli doesn't know of one.
+pub async fn source_for(
+    http: &HttpClient,
+    link: &Link,
+    country: &str,
+) -> Result<TrackSource, AppError> {
+    if matches!(link.platform, Platform::YouTube | Platform::SoundCloud) {
+        return Ok(TrackSource::YoutubeDl(link.url.clone()));
+    }
+
+    let links = odesli::lookup(http, &link.url, country)
+        .await
+        .map_err(ErrorKind::SourceResolution)?;
+    source_from(link, links)
+}
+
+/// Where to play a link from when it's already been looked up on Odesli.
+pub fn source_from(link: &Link, links: odesli::Links) -> Result<TrackSource, AppError> {
+    if matches!(link.platform, Platform::YouTube | Platform::SoundCloud) {
+        return Ok(TrackSource::YoutubeDl(link.url.clone()));
+    }
+    if let Some(youtube) = links.youtube() {
+        return Ok(TrackSource::YoutubeDl(youtube.to_owned()));
+    }
+    match (links.artist, links.title) {
+        (Some(artist), Some(title)) => Ok(TrackSource::search(&format!("{} - {}", artist, title))),
+        (None, Some(title)) => Ok(TrackSource::search(&title)),
+        _ => Err(ErrorKind::SourceResolution(anyhow::anyhow!(
+            "couldn't find {} anywhere playable",
+            link.url
+        ))
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(platform: Platform, url: &str) -> Link {
+        Link {
+            platform,
+            url: url.to_owned(),
+        }
+    }
+
+    #[test]
+    fn finds_song_links_in_a_message() {
+        assert_eq!(
+            find_links(
+                "check this out https://youtu.be/dQw4w9WgXcQ! and \
+                 <https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC>"
+            ),
+            [
+                link(Platform::YouTube, "https://youtu.be/dQw4w9WgXcQ"),
+                link(
+                    Platform::Spotify,
+                    "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC"
+                ),
+            ]
+        );
+        assert_eq!(
+            find_links("[song](https://music.apple.com/us/album/x/1440857781?i=1440857786)."),
+            [link(
+                Platform::AppleMusic,
+                "https://music.apple.com/us/album/x/1440857781?i=1440857786"
+            )]
+        );
+        assert_eq!(
+            find_links("https://www.youtube.com/watch?v=a https://music.youtube.com/watch?v=b"),
+            [
+                link(Platform::YouTube, "https://www.youtube.com/watch?v=a"),
+                link(Platform::YouTube, "https://music.youtube.com/watch?v=b"),
+            ]
+        );
+        assert_eq!(
+            find_links("https://soundcloud.com/artist/track"),
+            [link(
+                Platform::SoundCloud,
+                "https://soundcloud.com/artist/track"
+            )]
+        );
+    }
+
+    #[test]
+    fn plays_links_from_youtube_or_a_search() {
+        let spotify = link(
+            Platform::Spotify,
+            "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+        );
+        let mut links = odesli::Links {
+            page_url: "https://song.link/s/4uLU6hMCjMI75M1A2tKUQC".to_owned(),
+            title: Some("Never Gonna Give You Up".to_owned()),
+            artist: Some("Rick Astley".to_owned()),
+            thumbnail: None,
+            platforms: [("spotify".to_owned(), spotify.url.clone())].into(),
+        };
+        assert_eq!(
+            source_from(&spotify, links.clone()).unwrap(),
+            TrackSource::search("Rick Astley - Never Gonna Give You Up")
+        );
+
+        links.platforms.insert(
+            "youtubeMusic".to_owned(),
+            "https://music.youtube.com/watch?v=lYBUbBu4W08".to_owned(),
+        );
+        assert_eq!(
+            source_from(&spotify, links.clone()).unwrap(),
+            TrackSource::YoutubeDl("https://music.youtube.com/watch?v=lYBUbBu4W08".to_owned())
+        );
+
+        // links that already play are left alone
+        let youtube = link(Platform::YouTube, "https://youtu.be/dQw4w9WgXcQ");
+        assert_eq!(
+            source_from(&youtube, links.clone()).unwrap(),
+            TrackSource::YoutubeDl(youtube.url.clone())
+        );
+
+        links.platforms.remove("youtubeMusic");
+        links.title = None;
+        assert!(source_from(&spotify, links).is_err());
+    }
+
+    #[test]
+    fn skips_other_links_and_repeats() {
+        assert!(
+            find_links("https://example.com/song.mp3 http://youtu.be/a youtube.com").is_empty()
+        );
+        assert_eq!(
+            find_links("https://youtu.be/a https://youtu.be/a"),
+            [link(Platform::YouTube, "https://youtu.be/a")]
+        );
+    }
+}
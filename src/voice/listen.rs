@@ -0,0 +1,165 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{
+    CreateActionRow, CreateAllowedMentions, CreateButton, CreateEmbed, CreateEmbedFooter,
+    CreateMessage, Message,
+};
+use tracing::warn;
+
+use crate::{err::AppError, helpers::get_http_client, odesli, Data};
+
+use super::{
+    links::{find_links, source_from},
+    metadata::{title_of, Requester},
+    play,
+};
+
+/// Most links answered in a single message, so a pasted list doesn't flood the channel.
+const MAX_LINKS: usize = 3;
+
+/// Answers song links posted in the guild's listen channel with the song on every platform,
+/// queueing them too when the guild wants that and the bot's in a call.
+pub async fn on_message(
+    ctx: &serenity::Context,
+    message: &Message,
+    data: &Data,
+) -> Result<(), AppError> {
+    let Some(guild_id) = message.guild_id else {
+        return Ok(());
+    };
+    if message.author.bot {
+        return Ok(());
+    }
+    let settings = data.settings.get(guild_id).await;
+    if settings.listen_channel != Some(message.channel_id) {
+        return Ok(());
+    }
+    let links = find_links(&message.content);
+    if links.is_empty() {
+        return Ok(());
+    }
+
+    let http = get_http_client(ctx).await;
+    let country = settings.storefront_or(&data.config.apple_music.storefront);
+    let call = if settings.listen_queue {
+        songbird::get(ctx)
+            .await
+            .expect("Songbird Voice client placed in at initialisation.")
+            .get(guild_id)
+    } else {
+        None
+    };
+
+    for link in links.into_iter().take(MAX_LINKS) {
+        let found = match odesli::lookup(&http, &link.url, &country).await {
+            Ok(found) => found,
+            Err(e) => {
+                warn!("Couldn't look up {} on Odesli: {}", link.url, e);
+                continue;
+            }
+        };
+
+        let queued = match &call {
+            Some(call) => {
+                let requester = Requester::new(&message.author);
+                // it's been looked up already, so don't ask Odesli again
+                let result = match source_from(&link, found.clone()) {
+                    Ok(source) => {
+                        play::add(data, http.clone(), guild_id, call, source, requester).await
+                    }
+                    Err(e) => Err(e),
+                };
+                Some(match result {
+                    Ok(handle) => {
+                        data.now_playing.refresh(guild_id).await;
+                        let typemap = handle.typemap().read().await;
+                        match title_of(&typemap) {
+                            Some(title) => format!("Queued {}", title),
+                            None => "Queued".to_owned(),
+                        }
+                    }
+                    Err(e) => format!("Couldn't queue it: {}", e.message()),
+                })
+            }
+            None => None,
+        };
+
+        let (embed, components) = card(&found, queued);
+        let reply = CreateMessage::new()
+            .reference_message(message)
+            .allowed_mentions(CreateAllowedMentions::new())
+            .embed(embed)
+            .components(components);
+        message.channel_id.send_message(&ctx.http, reply).await?;
+    }
+
+    Ok(())
+}
+
+/// The song with a link for every platform it's on, like song.link shows it.
+fn card(links: &odesli::Links, footer: Option<String>) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let title = match (&links.title, &links.artist) {
+        (Some(title), Some(artist)) => format!("{} — {}", title, artist),
+        (Some(title), None) => title.clone(),
+        _ => "This song".to_owned(),
+    };
+    let platforms = links
+        .by_platform()
+        .into_iter()
+        .map(|(name, url)| format!("[{}]({})", name, url))
+        .collect::<Vec<_>>()
+        .join(" · ");
+
+    let mut embed = CreateEmbed::default().title(title).url(&links.page_url);
+    // Discord turns down an empty description, and with it the whole message
+    if !platforms.is_empty() {
+        embed = embed.description(platforms);
+    }
+    if let Some(thumbnail) = &links.thumbnail {
+        embed = embed.thumbnail(thumbnail);
+    }
+    if let Some(footer) = footer {
+        embed = embed.footer(CreateEmbedFooter::new(footer));
+    }
+
+    let button = CreateButton::new_link(&links.page_url).label("Listen anywhere");
+    (embed, vec![CreateActionRow::Buttons(vec![button])])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(platforms: &[(&str, &str)]) -> odesli::Links {
+        odesli::Links {
+            page_url: "https://song.link/s/4uLU6hMCjMI75M1A2tKUQC".to_owned(),
+            title: Some("Never Gonna Give You Up".to_owned()),
+            artist: Some("Rick Astley".to_owned()),
+            thumbnail: None,
+            platforms: platforms
+                .iter()
+                .map(|(name, url)| (name.to_string(), url.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn leaves_out_the_description_without_platforms() {
+        let (embed, _) = card(&links(&[]), None);
+        let embed = serde_json::to_value(embed).unwrap();
+        assert_eq!(embed["title"], "Never Gonna Give You Up — Rick Astley");
+        assert!(embed.get("description").is_none());
+
+        let (embed, _) = card(
+            &links(&[(
+                "spotify",
+                "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+            )]),
+            None,
+        );
+        let embed = serde_json::to_value(embed).unwrap();
+        assert!(embed["description"]
+            .as_str()
+            .unwrap()
+            .contains("(https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC)"));
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Result};
+use once_cell::sync::OnceCell;
+use reqwest::{Client as HttpClient, Url};
+use serde_json::json;
+use songbird::typemap::TypeMapKey;
+use tracing::{debug, warn};
+
+/// Normalization gain applied on top of the guild volume for a single track.
+pub struct TrackGain;
+
+impl TypeMapKey for TrackGain {
+    type Value = f32;
+}
+
+// Never boost a quiet track by more than this, so we don't amplify noise into clipping.
+const MAX_BOOST: f32 = 2.0;
+const MIN_GAIN: f32 = 0.1;
+
+/// The web client version loudness lookups claim to be, unless `youtube.client_version` says
+/// otherwise.
+pub const DEFAULT_CLIENT_VERSION: &str = "2.20240101.00.00";
+
+static CLIENT_VERSION: OnceCell<String> = OnceCell::new();
+
+/// Sets the web client version for loudness lookups. Only the first call has any effect.
+pub fn set_client_version(version: String) {
+    let _ = CLIENT_VERSION.set(version);
+}
+
+fn client_version() -> &'static str {
+    CLIENT_VERSION
+        .get()
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_CLIENT_VERSION)
+}
+
+/// Converts a loudness offset (in dB, relative to the reference level) into a linear gain
+/// that brings the track back to the reference level.
+pub fn gain_for_loudness(loudness_db: f32) -> f32 {
+    10f32.powf(-loudness_db / 20.0).clamp(MIN_GAIN, MAX_BOOST)
+}
+
+/// Converts a ReplayGain track gain (the adjustment to apply, in dB) into a linear gain.
+pub fn gain_for_replay_gain(gain_db: f32) -> f32 {
+    10f32.powf(gain_db / 20.0).clamp(MIN_GAIN, MAX_BOOST)
+}
+
+/// Pulls the video ID out of the usual YouTube URL shapes.
+pub fn youtube_video_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?.trim_start_matches("www.");
+
+    match host {
+        "youtu.be" => url.path_segments()?.next().map(str::to_owned),
+        "youtube.com" | "m.youtube.com" | "music.youtube.com" => {
+            let mut segments = url.path_segments()?;
+            match segments.next()? {
+                "watch" => url
+                    .query_pairs()
+                    .find(|(k, _)| k == "v")
+                    .map(|(_, v)| v.into_owned()),
+                "shorts" | "live" | "embed" => segments.next().map(str::to_owned),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+    .filter(|id| !id.is_empty())
+}
+
+/// Asks YouTube for the loudness offset it measured for a video.
+/// Positive values mean the upload is louder than YouTube's reference level.
+pub async fn youtube_loudness(http: &HttpClient, url: &str) -> Result<f32> {
+    let video_id = youtube_video_id(url).ok_or_else(|| anyhow!("Not a YouTube URL"))?;
+
+    let body = json!({
+        "context": {
+            "client": {
+                "clientName": "WEB",
+                "clientVersion": client_version(),
+            }
+        },
+        "videoId": video_id,
+    });
+
+    let response: serde_json::Value = http
+        .post("https://www.youtube.com/youtubei/v1/player")
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    response
+        .pointer("/playerConfig/audioConfig/loudnessDb")
+        .and_then(|v| v.as_f64())
+        .map(|v| v as f32)
+        .ok_or_else(|| anyhow!("No loudness data for {}", video_id))
+}
+
+/// Works out the normalization gain for a track, falling back to unity when there's no data.
+pub async fn normalization_gain(http: &HttpClient, url: &str) -> f32 {
+    if youtube_video_id(url).is_none() {
+        debug!("No loudness metadata for {}, it isn't on YouTube", url);
+        return 1.0;
+    }
+    match youtube_loudness(http, url).await {
+        Ok(db) => gain_for_loudness(db),
+        Err(e) => {
+            // YouTube's player endpoint isn't public, so this is how a stale client version shows
+            warn!(
+                "Loudness lookup failed for {} with client version {}: {}",
+                url,
+                client_version(),
+                e
+            );
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_video_ids() {
+        let cases = [
+            (
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                Some("dQw4w9WgXcQ"),
+            ),
+            (
+                "https://youtube.com/watch?list=PL1&v=dQw4w9WgXcQ&t=42",
+                Some("dQw4w9WgXcQ"),
+            ),
+            (
+                "https://m.youtube.com/watch?v=dQw4w9WgXcQ",
+                Some("dQw4w9WgXcQ"),
+            ),
+            (
+                "https://music.youtube.com/watch?v=dQw4w9WgXcQ",
+                Some("dQw4w9WgXcQ"),
+            ),
+            ("https://youtu.be/dQw4w9WgXcQ?si=abc", Some("dQw4w9WgXcQ")),
+            (
+                "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+                Some("dQw4w9WgXcQ"),
+            ),
+            (
+                "https://www.youtube.com/live/dQw4w9WgXcQ",
+                Some("dQw4w9WgXcQ"),
+            ),
+            (
+                "https://www.youtube.com/embed/dQw4w9WgXcQ",
+                Some("dQw4w9WgXcQ"),
+            ),
+            ("https://www.youtube.com/watch?v=", None),
+            ("https://www.youtube.com/@RickAstleyYT", None),
+            ("https://youtu.be/", None),
+            ("https://soundcloud.com/artist/track", None),
+            ("https://notyoutube.com/watch?v=dQw4w9WgXcQ", None),
+            ("not a url", None),
+        ];
+        for (url, id) in cases {
+            assert_eq!(youtube_video_id(url).as_deref(), id, "{}", url);
+        }
+    }
+
+    #[test]
+    fn turns_loudness_into_gain() {
+        assert_eq!(gain_for_loudness(0.0), 1.0);
+        // 6 dB too loud is about half as loud
+        assert!((gain_for_loudness(6.0) - 0.501).abs() < 0.001);
+        assert!((gain_for_loudness(-3.0) - 1.413).abs() < 0.001);
+        // quiet tracks aren't boosted into clipping, loud ones aren't silenced
+        assert_eq!(gain_for_loudness(-20.0), MAX_BOOST);
+        assert_eq!(gain_for_loudness(40.0), MIN_GAIN);
+    }
+}
@@ -0,0 +1,333 @@
+use std::time::Duration;
+
+use poise::{serenity_prelude as serenity, CreateReply};
+use serenity::all::{
+    ActivityType, ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton,
+    CreateEmbed, CreateEmbedAuthor, CreateInteractionResponse, GatewayIntents, Mentionable,
+};
+use tracing::warn;
+
+use crate::{
+    apol::search::search_track, err::ErrorKind, helpers::get_http_client, AppError, Context,
+};
+
+use super::{
+    get_or_join_call,
+    links::{find_links, source_for, Link, Platform},
+    metadata::{title_of, Requester},
+    play,
+    source::{TrackSource, AUDIO_EXTENSIONS},
+};
+
+/// Most tracks queued from a single message.
+const MAX_TRACKS: usize = 10;
+/// How long the offer to queue someone's song stays up.
+const OFFER_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Something playable in a message.
+enum Found {
+    Link(Link),
+    File(String),
+}
+
+/// Queues the YouTube, Spotify, Apple Music and SoundCloud links and audio files in a message
+#[poise::command(category = "Music", context_menu_command = "Play this", guild_only)]
+pub async fn play_this(ctx: Context<'_>, message: serenity::Message) -> Result<(), AppError> {
+    let mut links = find_links(&message.content);
+    for url in message.embeds.iter().filter_map(|e| e.url.as_deref()) {
+        for link in find_links(url) {
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+    }
+    let files = message.attachments.iter().filter(|a| {
+        a.content_type
+            .as_deref()
+            .is_some_and(|t| t.starts_with("audio/"))
+            || a.filename
+                .rsplit_once('.')
+                .is_some_and(|(_, ext)| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+    });
+    let found: Vec<_> = links
+        .into_iter()
+        .map(Found::Link)
+        .chain(files.map(|a| Found::File(a.url.clone())))
+        .take(MAX_TRACKS)
+        .collect();
+
+    if found.is_empty() {
+        ctx.send(
+            CreateReply::default()
+                .content("There's nothing I can play in that message.")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let (guild_id, channel_id) = super::guild_info(ctx).await?;
+    let Ok(call) = get_or_join_call(&manager, ctx, guild_id, channel_id).await else {
+        return Err(ErrorKind::NotInVoice.into());
+    };
+    ctx.defer().await?;
+
+    let data = ctx.data();
+    let http = get_http_client(ctx.serenity_context()).await;
+    let country = data
+        .settings
+        .get(guild_id)
+        .await
+        .storefront_or(&data.config.apple_music.storefront);
+
+    let mut queued = Vec::new();
+    let mut failed = Vec::new();
+    for found in found {
+        let source = match found {
+            Found::Link(link) => source_for(&http, &link, &country).await,
+            Found::File(url) => {
+                TrackSource::parse(&url, None).map_err(|e| ErrorKind::SourceResolution(e).into())
+            }
+        };
+        let requester = Requester::new(ctx.author());
+        let result = match source {
+            Ok(source) => play::add(data, http.clone(), guild_id, &call, source, requester).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(handle) => {
+                let typemap = handle.typemap().read().await;
+                let title = title_of(&typemap);
+                queued.push(title.unwrap_or_else(|| "an untitled track".to_owned()));
+            }
+            Err(e) => {
+                warn!("Couldn't queue a track from a message: {}", e.chain());
+                failed.push(e);
+            }
+        }
+    }
+
+    if queued.is_empty() {
+        // nothing worked, so the first reason is as good as any
+        return Err(failed.remove(0));
+    }
+    let mut content = match queued.as_slice() {
+        [title] => format!("Queued **{}** from {}.", title, message.link()),
+        titles => format!(
+            "Queued {} tracks from {}:\n{}",
+            titles.len(),
+            message.link(),
+            titles
+                .iter()
+                .map(|t| format!("- {}", t))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    };
+    if let Some(e) = failed.first() {
+        content.push_str(&format!(
+            "\nCouldn't queue {} of them: {}",
+            failed.len(),
+            e.message()
+        ));
+    }
+    data.now_playing.refresh(guild_id).await;
+    ctx.say(content).await?;
+
+    Ok(())
+}
+
+/// A song someone's listening to, going by their Discord activity.
+struct Listening {
+    title: String,
+    artist: Option<String>,
+    album: Option<String>,
+}
+
+/// Offers to queue the song someone's listening to on Spotify or anything else Discord shows
+#[poise::command(
+    category = "Music",
+    context_menu_command = "What are they listening to?",
+    guild_only
+)]
+pub async fn listening_to(ctx: Context<'_>, user: serenity::User) -> Result<(), AppError> {
+    let data = ctx.data();
+    if !data
+        .config
+        .intents()
+        .contains(GatewayIntents::GUILD_PRESENCES)
+    {
+        ctx.send(
+            CreateReply::default()
+                .content(
+                    "I can't see what anyone's listening to without the `guild_presences` intent.",
+                )
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let (guild_id, channel_id) = super::guild_info(ctx).await?;
+    let activity = ctx.guild().and_then(|guild| {
+        let presence = guild.presences.get(&user.id)?;
+        presence
+            .activities
+            .iter()
+            .find(|a| a.kind == ActivityType::Listening && a.details.is_some())
+            .map(|a| Listening {
+                title: a.details.clone().unwrap_or_default(),
+                // Spotify separates artists with semicolons
+                artist: a.state.as_ref().map(|s| s.replace("; ", ", ")),
+                album: a
+                    .assets
+                    .as_ref()
+                    .and_then(|assets| assets.large_text.clone()),
+            })
+    });
+    let Some(listening) = activity else {
+        ctx.send(
+            CreateReply::default()
+                .content(format!(
+                    "{} isn't listening to anything I can see.",
+                    user.mention()
+                ))
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    };
+    ctx.defer_ephemeral().await?;
+
+    let storefront = data
+        .settings
+        .get(guild_id)
+        .await
+        .storefront_or(&data.config.apple_music.storefront);
+    let query = match &listening.artist {
+        Some(artist) => format!("{} {}", listening.title, artist),
+        None => listening.title.clone(),
+    };
+    let song = match search_track(query, &storefront).await {
+        Ok(song) => song
+            .and_then(|s| s.data)
+            .and_then(|data| data.into_iter().next())
+            .and_then(|datum| datum.attributes),
+        Err(e) => {
+            warn!("Apple Music lookup failed: {}", e);
+            None
+        }
+    };
+
+    // Apple Music's names are tidier than whatever the activity had
+    let title = song
+        .as_ref()
+        .and_then(|s| s.name.clone())
+        .unwrap_or(listening.title);
+    let artist = song
+        .as_ref()
+        .and_then(|s| s.artist_name.clone())
+        .or(listening.artist);
+    let album = song
+        .as_ref()
+        .and_then(|s| s.album_name.clone())
+        .or(listening.album);
+    let apple_music = song.as_ref().and_then(|s| s.url.clone());
+    let artwork = song
+        .as_ref()
+        .and_then(|s| s.artwork.as_ref())
+        .and_then(|a| a.url.as_ref())
+        .map(|url| url.replace("{w}", "600").replace("{h}", "600"));
+
+    let mut embed = CreateEmbed::default()
+        .author(CreateEmbedAuthor::new(format!(
+            "{} is listening to",
+            user.display_name()
+        )))
+        .title(&title);
+    if let Some(url) = &apple_music {
+        embed = embed.url(url);
+    }
+    let mut description = String::new();
+    if let Some(artist) = &artist {
+        description.push_str(&format!("Artist: {}\n", artist));
+    }
+    if let Some(album) = &album {
+        description.push_str(&format!("Album: {}\n", album));
+    }
+    if !description.is_empty() {
+        embed = embed.description(description);
+    }
+    if let Some(artwork) = artwork {
+        embed = embed.thumbnail(artwork);
+    }
+
+    let custom_id = format!("listening:{}", ctx.id());
+    let button = CreateButton::new(&custom_id)
+        .label("Queue it")
+        .style(ButtonStyle::Primary);
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .embed(embed.clone())
+                .components(vec![CreateActionRow::Buttons(vec![button])]),
+        )
+        .await?;
+
+    let pressed = ComponentInteractionCollector::new(ctx.serenity_context())
+        .author_id(ctx.author().id)
+        .custom_ids(vec![custom_id])
+        .timeout(OFFER_TIMEOUT)
+        .await;
+    let Some(pressed) = pressed else {
+        reply
+            .edit(ctx, CreateReply::default().embed(embed).components(vec![]))
+            .await?;
+        return Ok(());
+    };
+    pressed
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    let http = get_http_client(ctx.serenity_context()).await;
+    let source = match apple_music {
+        Some(url) => {
+            let link = Link {
+                platform: Platform::AppleMusic,
+                url,
+            };
+            source_for(&http, &link, &storefront).await?
+        }
+        None => TrackSource::search(&match &artist {
+            Some(artist) => format!("{} - {}", artist, title),
+            None => title.clone(),
+        }),
+    };
+
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let Ok(call) = get_or_join_call(&manager, ctx, guild_id, channel_id).await else {
+        return Err(ErrorKind::NotInVoice.into());
+    };
+    let requester = Requester::new(ctx.author());
+    play::add(data, http, guild_id, &call, source, requester).await?;
+    data.now_playing.refresh(guild_id).await;
+
+    reply
+        .edit(
+            ctx,
+            CreateReply::default()
+                .content(format!("Queued **{}**.", title))
+                .embed(embed)
+                .components(vec![]),
+        )
+        .await?;
+
+    Ok(())
+}
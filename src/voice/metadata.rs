@@ -1,7 +1,182 @@
-use songbird::{input::AuxMetadata, typemap::TypeMapKey};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateAttachment, User, UserId};
+use songbird::{
+    input::AuxMetadata,
+    typemap::{TypeMap, TypeMapKey},
+};
+
+use crate::helpers::normalize::Normalized;
 
 pub struct Metadata;
 
 impl TypeMapKey for Metadata {
     type Value = AuxMetadata;
 }
+
+/// Cover art embedded in a file's tags.
+#[derive(Debug, Clone)]
+pub struct Cover {
+    pub data: Vec<u8>,
+    pub media_type: String,
+}
+
+impl Cover {
+    /// Builds an attachment for the cover, along with the URL an embed can use to show it.
+    pub fn attachment(&self) -> (CreateAttachment, String) {
+        let ext = match self.media_type.as_str() {
+            "image/png" => "png",
+            "image/gif" => "gif",
+            "image/webp" => "webp",
+            _ => "jpg",
+        };
+        let filename = format!("cover.{}", ext);
+        let url = format!("attachment://{}", filename);
+        (CreateAttachment::bytes(self.data.clone(), filename), url)
+    }
+}
+
+pub struct CoverArt;
+
+impl TypeMapKey for CoverArt {
+    type Value = Arc<Cover>;
+}
+
+/// The Apple Music catalog album a track was matched to.
+pub struct AlbumId;
+
+impl TypeMapKey for AlbumId {
+    type Value = String;
+}
+
+/// The Apple Music catalog song a track was matched to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogTrack {
+    pub id: String,
+    pub isrc: Option<String>,
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    pub album_id: Option<String>,
+    pub artwork: Option<String>,
+    /// Without the "Music" genre every song has.
+    pub genres: Vec<String>,
+    pub composer: Option<String>,
+    pub url: Option<String>,
+    /// How sure the match is, from 0 to 1.
+    pub confidence: f32,
+}
+
+pub struct Catalog;
+
+impl TypeMapKey for Catalog {
+    type Value = Arc<CatalogTrack>;
+}
+
+/// Who asked for a track.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Requester {
+    pub id: UserId,
+    pub name: String,
+}
+
+impl Requester {
+    pub fn new(user: &User) -> Self {
+        Self {
+            id: user.id,
+            name: user.display_name().to_owned(),
+        }
+    }
+}
+
+pub struct RequestedBy;
+
+impl TypeMapKey for RequestedBy {
+    type Value = Requester;
+}
+
+/// The track's title, without the artist when it's been stuffed in there too, or anything like
+/// "(Official Video)" tacked on.
+pub fn display_title(metadata: &AuxMetadata) -> Option<String> {
+    Normalized::from_metadata(metadata)
+        .map(|n| n.title)
+        .filter(|title| !title.is_empty())
+        .or_else(|| metadata.title.clone())
+}
+
+/// A queued track's title, from the Apple Music song it was matched to when there is one.
+pub fn title_of(typemap: &TypeMap) -> Option<String> {
+    match typemap.get::<Catalog>() {
+        Some(catalog) => Some(catalog.title.clone()),
+        None => typemap.get::<Metadata>().and_then(display_title),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titled(title: &str, artist: Option<&str>) -> AuxMetadata {
+        AuxMetadata {
+            title: Some(title.to_owned()),
+            artist: artist.map(str::to_owned),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn takes_the_artist_out_of_titles() {
+        for (title, artist, want) in [
+            (
+                "Rick Astley - Never Gonna Give You Up",
+                Some("Rick Astley"),
+                "Never Gonna Give You Up",
+            ),
+            (
+                "Never Gonna Give You Up - Rick Astley",
+                Some("Rick Astley"),
+                "Never Gonna Give You Up",
+            ),
+            (
+                "Rick Astley - Never Gonna Give You Up (Official Video)",
+                None,
+                "Never Gonna Give You Up",
+            ),
+            ("Song with spaces", None, "Song with spaces"),
+        ] {
+            assert_eq!(
+                display_title(&titled(title, artist)).as_deref(),
+                Some(want),
+                "{:?}",
+                title
+            );
+        }
+        assert_eq!(display_title(&AuxMetadata::default()), None);
+    }
+
+    #[test]
+    fn prefers_the_catalog_title() {
+        let mut typemap = TypeMap::new();
+        typemap.insert::<Metadata>(titled("Get Lucky (Official Audio)", Some("Daft Punk")));
+        assert_eq!(title_of(&typemap).as_deref(), Some("Get Lucky"));
+
+        typemap.insert::<Catalog>(Arc::new(CatalogTrack {
+            id: "617154366".to_owned(),
+            isrc: None,
+            title: "Get Lucky (feat. Pharrell Williams & Nile Rodgers)".to_owned(),
+            artist: "Daft Punk".to_owned(),
+            album: None,
+            album_id: None,
+            artwork: None,
+            genres: vec![],
+            composer: None,
+            url: None,
+            confidence: 1.0,
+        }));
+        assert_eq!(
+            title_of(&typemap).as_deref(),
+            Some("Get Lucky (feat. Pharrell Williams & Nile Rodgers)")
+        );
+    }
+}
@@ -1,14 +1,42 @@
-use std::sync::Arc;
+use std::sync::{atomic::AtomicU32, Arc};
 
 use serenity::all::{ChannelId, GuildId};
-use songbird::{Songbird, TrackEvent};
+use songbird::{Event, Songbird, TrackEvent};
 
-use crate::{err::AppError, helpers::track_end::TrackEndNotifier, Context};
+use crate::{
+    err::AppError,
+    helpers::{
+        get_http_client,
+        track_error::{TrackErrorNotifier, TrackFailureReset},
+    },
+    metrics, Context,
+};
 
+pub mod announce;
+pub mod bulk;
+pub mod catalog;
+pub mod controls;
+pub mod crossfade;
+pub mod fairness;
+pub mod filters;
+pub mod history;
+pub mod idle;
+pub mod links;
+pub mod listen;
+pub mod loudness;
+pub mod menus;
 pub mod metadata;
+pub mod nowplaying;
 pub mod pause;
 pub mod play;
+pub mod prefetch;
 pub mod queue;
+pub mod radio;
+pub mod saved;
+pub mod source;
+pub mod state;
+pub mod tags;
+pub mod volume;
 
 pub async fn guild_info(ctx: Context<'_>) -> Result<(GuildId, ChannelId), AppError> {
     let guild_id = ctx
@@ -31,14 +59,75 @@ pub async fn init_call(
 > {
     if manager.get(guild_id).is_none() {
         if let Ok(handler_lock) = manager.join(guild_id, channel_id).await {
+            ctx.data()
+                .playback
+                .update(guild_id, |prefs| prefs.channel = Some(channel_id))
+                .await;
             {
                 let mut handler = handler_lock.lock().await;
                 let send_http = ctx.serenity_context().http.clone();
                 handler.add_global_event(
-                    TrackEvent::End.into(),
-                    TrackEndNotifier {
+                    TrackEvent::Play.into(),
+                    announce::TrackStartNotifier::new(
+                        channel_id,
+                        send_http,
+                        ctx.data().clone(),
+                        guild_id,
+                    ),
+                );
+                let failures = Arc::new(AtomicU32::new(0));
+                handler.add_global_event(
+                    TrackEvent::Error.into(),
+                    TrackErrorNotifier {
                         chan_id: channel_id,
-                        http: send_http,
+                        http: ctx.serenity_context().http.clone(),
+                        client: get_http_client(ctx.serenity_context()).await,
+                        manager: manager.clone(),
+                        guild_id,
+                        data: ctx.data().clone(),
+                        failures: failures.clone(),
+                    },
+                );
+                handler.add_global_event(TrackEvent::End.into(), TrackFailureReset { failures });
+                handler.add_global_event(TrackEvent::Play.into(), metrics::CountPlays);
+                handler.add_global_event(
+                    TrackEvent::Play.into(),
+                    history::RecordHistory {
+                        data: ctx.data().clone(),
+                        guild_id,
+                    },
+                );
+                handler.add_global_event(
+                    TrackEvent::End.into(),
+                    crate::stats::RecordPlays {
+                        data: ctx.data().clone(),
+                        cache: ctx.serenity_context().cache.clone(),
+                        guild_id,
+                    },
+                );
+                let queue = handler.queue().clone();
+                handler.add_global_event(
+                    TrackEvent::Play.into(),
+                    prefetch::PrefetchNext {
+                        queue: queue.clone(),
+                        cache: ctx.data().cache.clone(),
+                    },
+                );
+                handler.add_global_event(
+                    Event::Periodic(idle::CHECK_INTERVAL, None),
+                    idle::IdleTimeout::new(
+                        manager.clone(),
+                        ctx.data().clone(),
+                        guild_id,
+                        queue.clone(),
+                    ),
+                );
+                handler.add_global_event(
+                    TrackEvent::Play.into(),
+                    crossfade::Crossfader {
+                        queue,
+                        data: ctx.data().clone(),
+                        guild_id,
                     },
                 );
             }
@@ -0,0 +1,304 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use poise::{serenity_prelude as serenity, CreateReply};
+use serenity::all::{
+    ButtonStyle, ChannelId, ComponentInteraction, CreateActionRow, CreateButton, CreateEmbed,
+    CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, EditMessage,
+    GuildId, Http, Mentionable, MessageId,
+};
+use songbird::tracks::{LoopState, PlayMode, TrackHandle, TrackQueue};
+use tokio::{
+    sync::{Mutex, Notify},
+    task::JoinHandle,
+    time::{sleep, Instant},
+};
+use tracing::warn;
+
+use crate::{err::AppError, likes::LIKE_BUTTON, Data};
+
+use super::{
+    controls::{self, check_control, Control},
+    metadata::{Cover, CoverArt, Metadata, RequestedBy},
+    play::build_play_embed,
+};
+
+/// How often the message is refreshed on its own.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+/// Discord allows about five edits per five seconds per channel, stay well clear of that.
+const MIN_EDIT_GAP: Duration = Duration::from_secs(2);
+const BAR_WIDTH: usize = 20;
+
+struct LiveMessage {
+    channel: ChannelId,
+    message: MessageId,
+    wake: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+/// The live now playing message of each guild, at most one at a time.
+#[derive(Default)]
+pub struct LiveMessages {
+    guilds: Mutex<HashMap<GuildId, LiveMessage>>,
+}
+
+impl LiveMessages {
+    /// Makes `message` the guild's live message, replacing any older one, and keeps it updated
+    /// until playback stops.
+    pub async fn start(
+        &self,
+        data: Arc<Data>,
+        http: Arc<Http>,
+        guild_id: GuildId,
+        (channel, message): (ChannelId, MessageId),
+        queue: TrackQueue,
+    ) {
+        let wake = Arc::new(Notify::new());
+        let task = tokio::spawn(keep_updated(
+            data,
+            http.clone(),
+            guild_id,
+            (channel, message),
+            queue,
+            wake.clone(),
+        ));
+
+        let old = self.guilds.lock().await.insert(
+            guild_id,
+            LiveMessage {
+                channel,
+                message,
+                wake,
+                task,
+            },
+        );
+        if let Some(old) = old {
+            old.task.abort();
+            delete(&http, old.channel, old.message).await;
+        }
+    }
+
+    /// Updates the guild's live message soon, e.g. after the queue was changed by a command.
+    pub async fn refresh(&self, guild_id: GuildId) {
+        if let Some(live) = self.guilds.lock().await.get(&guild_id) {
+            live.wake.notify_one();
+        }
+    }
+
+    /// Forgets the guild's live message if it's still `message`.
+    async fn finish(&self, guild_id: GuildId, message: MessageId) {
+        let mut guilds = self.guilds.lock().await;
+        if guilds.get(&guild_id).is_some_and(|l| l.message == message) {
+            guilds.remove(&guild_id);
+        }
+    }
+}
+
+async fn delete(http: &Http, channel: ChannelId, message: MessageId) {
+    if let Err(e) = channel.delete_message(http, message).await {
+        warn!("Failed to delete now playing message: {}", e);
+    }
+}
+
+async fn keep_updated(
+    data: Arc<Data>,
+    http: Arc<Http>,
+    guild_id: GuildId,
+    (channel, message): (ChannelId, MessageId),
+    queue: TrackQueue,
+    wake: Arc<Notify>,
+) {
+    let mut shown = queue.current().map(|t| t.uuid());
+    let mut last_edit = Instant::now();
+
+    loop {
+        tokio::select! {
+            _ = sleep(UPDATE_INTERVAL) => {}
+            _ = wake.notified() => {}
+        }
+        let since_edit = last_edit.elapsed();
+        if since_edit < MIN_EDIT_GAP {
+            sleep(MIN_EDIT_GAP - since_edit).await;
+        }
+
+        let Some(current) = queue.current() else {
+            // playback stopped, so there's nothing left to show
+            delete(&http, channel, message).await;
+            break;
+        };
+
+        let view = NowPlaying::render(&current).await;
+        let mut edit = EditMessage::new()
+            .embed(view.embed)
+            .components(view.components);
+        if shown != Some(current.uuid()) {
+            edit = edit.remove_all_attachments();
+            if let Some(cover) = view.cover {
+                edit = edit.new_attachment(cover.attachment().0);
+            }
+            shown = Some(current.uuid());
+        }
+
+        last_edit = Instant::now();
+        if let Err(e) = channel.edit_message(&http, message, edit).await {
+            // most likely someone deleted it
+            warn!("Stopped updating now playing message: {}", e);
+            break;
+        }
+    }
+
+    data.now_playing.finish(guild_id, message).await;
+}
+
+/// Everything that goes into the now playing message.
+pub struct NowPlaying {
+    pub embed: CreateEmbed,
+    pub components: Vec<CreateActionRow>,
+    pub cover: Option<Arc<Cover>>,
+}
+
+impl NowPlaying {
+    pub async fn render(track: &TrackHandle) -> Self {
+        let (metadata, cover, requester) = {
+            let typemap = track.typemap().read().await;
+            (
+                typemap.get::<Metadata>().cloned().unwrap_or_default(),
+                typemap.get::<CoverArt>().cloned(),
+                typemap.get::<RequestedBy>().cloned(),
+            )
+        };
+        let info = track.get_info().await.ok();
+        let position = info.as_ref().map(|i| i.position);
+        let paused = info.as_ref().is_some_and(|i| i.playing == PlayMode::Pause);
+        let looping = info
+            .as_ref()
+            .is_some_and(|i| i.loops != LoopState::Finite(0));
+
+        let mut embed = build_play_embed(&metadata, true, position).await;
+        if metadata.title.is_none() {
+            embed = embed.title("Unknown track");
+        }
+        if let (Some(position), Some(duration)) = (position, metadata.duration) {
+            embed = embed.field("Progress", progress_bar(position, duration), false);
+        }
+        if let Some(requester) = requester {
+            embed = embed.field("Requested by", requester.id.mention().to_string(), true);
+        }
+        if let Some(cover) = &cover {
+            embed = embed.image(cover.attachment().1);
+        }
+
+        let mut status = vec![if paused { "Paused" } else { "Playing" }];
+        if looping {
+            status.push("Looping");
+        }
+        embed = embed.footer(CreateEmbedFooter::new(status.join(" · ")));
+
+        Self {
+            embed,
+            components: vec![controls_row(paused, looping), like_row()],
+            cover,
+        }
+    }
+}
+
+fn controls_row(paused: bool, looping: bool) -> CreateActionRow {
+    let button = |control: Control, label: &str| {
+        CreateButton::new(control.custom_id())
+            .label(label)
+            .style(ButtonStyle::Secondary)
+    };
+
+    CreateActionRow::Buttons(vec![
+        button(
+            Control::PauseResume,
+            if paused { "▶ Resume" } else { "⏸ Pause" },
+        ),
+        button(Control::Skip, "⏭ Skip"),
+        button(Control::Loop, if looping { "🔂 Unloop" } else { "🔂 Loop" }).style(if looping {
+            ButtonStyle::Primary
+        } else {
+            ButtonStyle::Secondary
+        }),
+        button(Control::Shuffle, "🔀 Shuffle"),
+        button(Control::Stop, "⏹ Stop").style(ButtonStyle::Danger),
+    ])
+}
+
+/// Kept apart from the controls, which only DJs can use, since anyone can like a track.
+fn like_row() -> CreateActionRow {
+    CreateActionRow::Buttons(vec![CreateButton::new(LIKE_BUTTON)
+        .label("❤️ Like")
+        .style(ButtonStyle::Secondary)])
+}
+
+fn progress_bar(position: Duration, duration: Duration) -> String {
+    let filled = if duration.is_zero() {
+        0
+    } else {
+        let ratio = position.as_secs_f64() / duration.as_secs_f64();
+        ((ratio * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH)
+    };
+    format!("{}●{}", "━".repeat(filled), "─".repeat(BAR_WIDTH - filled))
+}
+
+/// The reply `/np` sends, which becomes the live message once it's been posted.
+pub fn reply(view: NowPlaying) -> CreateReply {
+    let mut reply = CreateReply::default()
+        .embed(view.embed)
+        .components(view.components);
+    if let Some(cover) = view.cover {
+        reply = reply.attachment(cover.attachment().0);
+    }
+    reply
+}
+
+/// Handles a press of one of the now playing buttons.
+pub async fn handle_button(
+    ctx: &serenity::Context,
+    component: &ComponentInteraction,
+    data: &Arc<Data>,
+) -> Result<(), AppError> {
+    let Some(control) = Control::from_custom_id(&component.data.custom_id) else {
+        return Ok(());
+    };
+    let Some(guild_id) = component.guild_id else {
+        return Ok(());
+    };
+
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let call = match check_control(ctx, data, guild_id, component.user.id).await {
+        Ok(()) => manager.get(guild_id),
+        Err(e) => {
+            let reply = CreateInteractionResponseMessage::new()
+                .content(e.user_message())
+                .ephemeral(true);
+            component
+                .create_response(&ctx.http, CreateInteractionResponse::Message(reply))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let Some(call) = call else {
+        let reply = CreateInteractionResponseMessage::new()
+            .content("I'm not playing anything right now")
+            .ephemeral(true);
+        component
+            .create_response(&ctx.http, CreateInteractionResponse::Message(reply))
+            .await?;
+        return Ok(());
+    };
+
+    let queue = call.lock().await.queue().clone();
+    controls::apply(&queue, control).await;
+
+    component
+        .create_response(&ctx.http, CreateInteractionResponse::Acknowledge)
+        .await?;
+    data.now_playing.refresh(guild_id).await;
+
+    Ok(())
+}
@@ -1,13 +1,14 @@
-use crate::{voice::metadata::Metadata, AppError, Context};
+use crate::{err::ErrorKind, voice::metadata::Metadata, AppError, Context};
 
-use super::get_or_join_call;
+use super::{controls::can_control, get_or_join_call};
 
 #[poise::command(
     category = "Music",
     slash_command,
     prefix_command,
     aliases("s"),
-    guild_only
+    guild_only,
+    check = "can_control"
 )]
 pub async fn pause(ctx: Context<'_>) -> Result<(), AppError> {
     let manager = songbird::get(ctx.serenity_context())
@@ -25,6 +26,7 @@ pub async fn pause(ctx: Context<'_>) -> Result<(), AppError> {
         if let Err(result) = handler.queue().pause() {
             ctx.say(format!("Failed to pause: {:?}", result)).await?;
         }
+        ctx.data().now_playing.refresh(guild_id).await;
 
         if let Some(current) = current {
             if let Some(metadata) = current.typemap().read().await.get::<Metadata>() {
@@ -37,7 +39,7 @@ pub async fn pause(ctx: Context<'_>) -> Result<(), AppError> {
             ctx.say("Paused - no track playing").await?;
         }
     } else {
-        ctx.say("Not in a voice channel to pause").await?;
+        return Err(ErrorKind::NotInVoice.into());
     }
 
     Ok(())
@@ -1,16 +1,35 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use poise::CreateReply;
-use serenity::all::CreateEmbed;
-use songbird::input::{AuxMetadata, Compose, YoutubeDl};
+use reqwest::Client as HttpClient;
+use serenity::all::{Attachment, CreateEmbed, GuildId};
+use songbird::{
+    input::AuxMetadata,
+    tracks::{Track, TrackHandle},
+    Call,
+};
+use tokio::sync::Mutex;
 use tracing::info;
 
 use crate::{
-    helpers::{d2hms, get_http_client, trim_artist_from_title},
-    AppError, Context,
+    err::ErrorKind,
+    helpers::{d2hms, get_http_client},
+    settings::GuildSettings,
+    AppError, Context, Data,
 };
 
-use super::{get_or_join_call, metadata::Metadata};
+use super::{
+    announce::ShownInReply,
+    catalog::match_track,
+    controls::can_control,
+    fairness::{check_limits, rebalance},
+    filters::source::FilteredInput,
+    get_or_join_call,
+    loudness::TrackGain,
+    metadata::{display_title, CoverArt, Metadata, RequestedBy, Requester},
+    prefetch::{worth_prefetching, PrefetchUrl},
+    source::{list_local, resolve, OriginalSource, Resolved, TrackSource},
+};
 
 #[poise::command(
     category = "Music",
@@ -21,16 +40,32 @@ use super::{get_or_join_call, metadata::Metadata};
 )]
 pub async fn play(
     ctx: Context<'_>,
-    #[description = "The YouTube URL of the song to play"] song: Option<String>,
+    #[description = "A YouTube URL, direct audio link or file from the media directory"]
+    #[autocomplete = "autocomplete_local"]
+    song: Option<String>,
+    #[description = "An audio file to play"] file: Option<Attachment>,
 ) -> Result<(), AppError> {
-    if let Some(song) = song {
+    if let Some(file) = file {
+        play_inner(ctx, file.url).await
+    } else if let Some(song) = song {
         play_inner(ctx, song).await
     } else {
         resume(ctx).await
     }
 }
 
+async fn autocomplete_local(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    match &ctx.data().config.media_dir {
+        // don't bother walking the media directory for URLs
+        Some(dir) if !partial.starts_with("https://") => list_local(dir, partial, 25),
+        _ => Vec::new(),
+    }
+}
+
 pub async fn resume(ctx: Context<'_>) -> Result<(), AppError> {
+    // /play has no check of its own, since anyone can queue, but unpausing is a control
+    can_control(ctx).await?;
+
     let manager = songbird::get(ctx.serenity_context())
         .await
         .expect("Songbird Voice client placed in at initialisation.")
@@ -51,10 +86,7 @@ pub async fn resume(ctx: Context<'_>) -> Result<(), AppError> {
             if let Some(metadata) = current.typemap().read().await.get::<Metadata>() {
                 ctx.say(format!(
                     "Unpaused - {}",
-                    trim_artist_from_title(
-                        &metadata.title.clone().unwrap_or("None".to_string()),
-                        &metadata.artist.clone().unwrap_or("MY CLOCK".to_string())
-                    )
+                    display_title(metadata).unwrap_or("None".to_string())
                 ))
                 .await?;
             }
@@ -62,17 +94,16 @@ pub async fn resume(ctx: Context<'_>) -> Result<(), AppError> {
             ctx.say("Resumed!").await?;
         }
     } else {
-        ctx.say("Not in a voice channel to resume").await?;
+        return Err(ErrorKind::NotInVoice.into());
     }
 
     Ok(())
 }
 
 pub async fn play_inner(ctx: Context<'_>, song: String) -> Result<(), AppError> {
-    // check to make sure the url is valid
-    if !song.starts_with("https://") {
-        return Err(anyhow::anyhow!("Invalid URL").into());
-    }
+    // check to make sure we know where to get the track from
+    let source = TrackSource::parse(&song, ctx.data().config.media_dir.as_deref())
+        .map_err(ErrorKind::SourceResolution)?;
 
     // make sure songbird has been initialized
     let manager = songbird::get(ctx.serenity_context())
@@ -86,65 +117,199 @@ pub async fn play_inner(ctx: Context<'_>, song: String) -> Result<(), AppError>
 
     let channel_id = ctx.channel_id();
 
+    check_source(&ctx.data().settings.get(guild_id).await, &source)?;
+
     if let Ok(handler_lock) = get_or_join_call(&manager, ctx, guild_id, channel_id).await {
         // give us more time to load the track!
         ctx.defer().await?;
-        let mut handler = handler_lock.lock().await;
 
         let http = get_http_client(ctx.serenity_context()).await;
+        let requester = Requester::new(ctx.author());
+        let h = add_shown(ctx.data(), http, guild_id, &handler_lock, source, requester).await?;
 
-        // todo: variably create source based on url/track source
-        let mut src = YoutubeDl::new(http, song);
-
-        let metadata = src.aux_metadata().await;
-
-        if let Ok(metadata) = metadata {
+        let metadata = h.typemap().read().await.get::<Metadata>().cloned();
+        if let Some(metadata) = metadata {
             info!("Got metadata: {:?}", metadata);
 
-            let embed = build_play_embed(&metadata, false, None).await;
+            let mut embed = build_play_embed(&metadata, false, None).await;
 
-            let mut title = metadata.title.clone().unwrap_or("This track".to_string());
-
-            if title != "This track" {
-                title = trim_artist_from_title(
-                    &title,
-                    &metadata.artist.clone().unwrap_or("MY CLOCK".to_string()),
-                );
-            }
+            let title = display_title(&metadata).unwrap_or("This track".to_string());
 
             // build reply message
-            let queue = handler.queue();
-            let content = match queue.len() {
+            let position = handler_lock
+                .lock()
+                .await
+                .queue()
+                .current_queue()
+                .iter()
+                .position(|t| t.uuid() == h.uuid())
+                .unwrap_or_default();
+            let content = match position {
                 0 => format!("{title} is now playing."),
                 1 => format!("{title} is up next."),
                 2 => format!("{title} will play after this next track."),
-                _ => format!(
-                    "{title} will play after the next {} tracks.",
-                    queue.len() - 1
-                ),
+                n => format!("{title} will play after the next {} tracks.", n - 1),
             };
 
-            let reply = CreateReply::default().content(content).embed(embed);
+            let mut reply = CreateReply::default().content(content);
+            if let Some(cover) = h.typemap().read().await.get::<CoverArt>() {
+                let (attachment, url) = cover.attachment();
+                embed = embed.image(url);
+                reply = reply.attachment(attachment);
+            }
 
-            ctx.send(reply).await?;
-            let h = handler.enqueue_input(src.into()).await;
-            h.typemap()
-                .write()
-                .await
-                .insert::<Metadata>(metadata.clone());
+            ctx.send(reply.embed(embed)).await?;
         } else {
             info!("Failed to get metadata or no metadata available");
             ctx.say("Failed to get metadata, but playing anyways")
                 .await?;
-            handler.enqueue_input(src.into()).await;
         }
     } else {
-        ctx.say("Not in a voice channel to play in").await?;
+        return Err(ErrorKind::NotInVoice.into());
     }
 
     Ok(())
 }
 
+/// Refuses sources the server hasn't allowed.
+pub fn check_source(settings: &GuildSettings, source: &TrackSource) -> Result<(), AppError> {
+    if settings.allowed_sources.contains(&source.kind()) {
+        Ok(())
+    } else {
+        Err(ErrorKind::PermissionDenied(format!(
+            "play {} sources in this server",
+            source.kind().name()
+        ))
+        .into())
+    }
+}
+
+/// Looks up a source and queues it for `requester`, as long as the server's rules allow it.
+pub async fn add(
+    data: &Data,
+    http: HttpClient,
+    guild_id: GuildId,
+    call: &Mutex<Call>,
+    source: TrackSource,
+    requester: Requester,
+) -> Result<TrackHandle, AppError> {
+    add_inner(data, http, guild_id, call, source, requester, false).await
+}
+
+/// Like [`add`], for `/play`, whose reply already shows the track's card.
+async fn add_shown(
+    data: &Data,
+    http: HttpClient,
+    guild_id: GuildId,
+    call: &Mutex<Call>,
+    source: TrackSource,
+    requester: Requester,
+) -> Result<TrackHandle, AppError> {
+    add_inner(data, http, guild_id, call, source, requester, true).await
+}
+
+async fn add_inner(
+    data: &Data,
+    http: HttpClient,
+    guild_id: GuildId,
+    call: &Mutex<Call>,
+    source: TrackSource,
+    requester: Requester,
+    shown: bool,
+) -> Result<TrackHandle, AppError> {
+    let settings = data.settings.get(guild_id).await;
+    check_source(&settings, &source)?;
+
+    let resolved = resolve(
+        http,
+        source.clone(),
+        settings.normalize,
+        &data.cache,
+        &data.config.ytdl,
+    )
+    .await;
+
+    let mut handler = call.lock().await;
+    check_limits(
+        &settings,
+        handler.queue(),
+        requester.id,
+        resolved.metadata.as_ref().and_then(|m| m.duration),
+    )
+    .await?;
+
+    Ok(enqueue(
+        data,
+        guild_id,
+        &mut handler,
+        source,
+        resolved,
+        requester,
+        shown,
+    )
+    .await)
+}
+
+/// Adds a resolved track to the guild's queue, along with everything the rest of the bot keeps
+/// about it. `shown` says the caller's reply shows the track's card, so there's no need to
+/// announce it if it starts playing straight away.
+pub async fn enqueue(
+    data: &Data,
+    guild_id: GuildId,
+    handler: &mut Call,
+    source: TrackSource,
+    resolved: Resolved,
+    requester: Requester,
+    shown: bool,
+) -> TrackHandle {
+    let settings = data.settings.get(guild_id).await;
+    let filters = data.playback.filters(guild_id).await;
+    let input = FilteredInput::wrap(resolved.input, filters);
+
+    let prefetch_url = resolved
+        .prefetch_url
+        .filter(|_| worth_prefetching(resolved.metadata.as_ref()));
+    let h = handler
+        .enqueue(Track::from(input).volume(settings.volume_scale() * resolved.gain))
+        .await;
+    {
+        let mut typemap = h.typemap().write().await;
+        typemap.insert::<OriginalSource>(source);
+        typemap.insert::<RequestedBy>(requester);
+        if let Some(url) = prefetch_url.clone() {
+            typemap.insert::<PrefetchUrl>(url);
+        }
+        if let Some(metadata) = resolved.metadata {
+            typemap.insert::<Metadata>(metadata);
+        }
+        if let Some(cover) = resolved.cover {
+            typemap.insert::<CoverArt>(Arc::new(cover));
+        }
+        if settings.normalize {
+            typemap.insert::<TrackGain>(resolved.gain);
+        }
+        if shown && handler.queue().len() == 1 {
+            typemap.insert::<ShownInReply>(());
+        }
+    }
+
+    if settings.fair_queue {
+        rebalance(handler.queue()).await;
+    }
+    match_track(
+        h.clone(),
+        settings.storefront_or(&data.config.apple_music.storefront),
+    );
+
+    // whatever's now playing had no time to be fetched, but the track after it does
+    let up_next = handler.queue().current_queue().get(1).map(|t| t.uuid());
+    if let Some(url) = prefetch_url.filter(|_| up_next == Some(h.uuid())) {
+        data.cache.prefetch(url);
+    }
+
+    h
+}
+
 pub async fn build_play_embed(
     metadata: &AuxMetadata,
     title: bool,
@@ -153,7 +318,7 @@ pub async fn build_play_embed(
     let mut embed = CreateEmbed::default();
     let mut desc = String::new();
     if title {
-        if let Some(title) = metadata.title.clone() {
+        if let Some(title) = display_title(metadata) {
             embed = embed.title(title);
         }
     }
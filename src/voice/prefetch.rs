@@ -0,0 +1,508 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use serenity::async_trait;
+use sha2::{Digest, Sha256};
+use songbird::{
+    input::{AudioStream, AudioStreamError, AuxMetadata, Compose, File, Input, YoutubeDl},
+    tracks::TrackQueue,
+    typemap::TypeMapKey,
+    Event, EventContext, EventHandler as VoiceEventHandler,
+};
+use symphonia::core::io::MediaSource;
+use tokio::{
+    process::Command,
+    sync::{watch, Mutex, Semaphore},
+};
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::{config::YtdlConfig, metrics::metrics};
+
+use super::loudness::youtube_video_id;
+
+/// How long a track about to play will wait on a download that's already underway.
+const IN_FLIGHT_WAIT: Duration = Duration::from_secs(10);
+/// Downloads running at once, so a long queue doesn't hammer YouTube.
+const MAX_CONCURRENT_DOWNLOADS: usize = 2;
+/// Longer tracks are streamed without keeping a copy, they'd crowd everything else out.
+const MAX_LENGTH: Duration = Duration::from_secs(30 * 60);
+/// A download taking longer than this is given up on, so it doesn't hold its slot forever.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Whether a track is worth downloading ahead of time. Live streams never end, and have no
+/// duration to go by.
+pub fn worth_prefetching(metadata: Option<&AuxMetadata>) -> bool {
+    metadata
+        .and_then(|m| m.duration)
+        .is_some_and(|d| !d.is_zero() && d <= MAX_LENGTH)
+}
+
+/// The URL a queued track can be prefetched from.
+pub struct PrefetchUrl;
+
+impl TypeMapKey for PrefetchUrl {
+    type Value = String;
+}
+
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+#[derive(Default)]
+struct CacheIndex {
+    files: HashMap<String, CachedFile>,
+    in_flight: HashMap<String, watch::Receiver<bool>>,
+}
+
+/// Bounded on-disk cache of downloaded tracks, evicting by age and total size.
+pub struct TrackCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_age: Duration,
+    index: Mutex<CacheIndex>,
+    downloads: Semaphore,
+    ytdl: &'static YtdlConfig,
+}
+
+impl TrackCache {
+    /// Opens the cache in `dir`, picking up files left from a previous run.
+    pub fn open(
+        dir: PathBuf,
+        max_bytes: u64,
+        max_age: Duration,
+        ytdl: &'static YtdlConfig,
+    ) -> Self {
+        let mut index = CacheIndex::default();
+
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                // half-finished downloads from a crash
+                if name.starts_with("tmp-") {
+                    let _ = std::fs::remove_file(&path);
+                    continue;
+                }
+                let (Some(key), Ok(meta)) = (
+                    path.file_stem().and_then(|s| s.to_str()).map(str::to_owned),
+                    entry.metadata(),
+                ) else {
+                    continue;
+                };
+                index.files.insert(
+                    key,
+                    CachedFile {
+                        path,
+                        size: meta.len(),
+                        last_used: meta.modified().unwrap_or_else(|_| SystemTime::now()),
+                    },
+                );
+            }
+        }
+
+        info!(
+            "Track cache at {} has {} files",
+            dir.display(),
+            index.files.len()
+        );
+
+        Self {
+            dir,
+            max_bytes,
+            max_age,
+            index: Mutex::new(index),
+            downloads: Semaphore::new(MAX_CONCURRENT_DOWNLOADS),
+            ytdl,
+        }
+    }
+
+    /// The file name a URL is cached under. It has to stay the same from one build to the next,
+    /// or an upgrade would orphan the whole cache.
+    fn key(url: &str) -> String {
+        youtube_video_id(url).unwrap_or_else(|| {
+            Sha256::digest(url.as_bytes())[..16]
+                .iter()
+                .fold(String::new(), |mut key, byte| {
+                    let _ = write!(key, "{:02x}", byte);
+                    key
+                })
+        })
+    }
+
+    /// Returns the cached file for `url`, waiting briefly if it's being downloaded right now.
+    pub async fn get(&self, url: &str) -> Option<PathBuf> {
+        let key = Self::key(url);
+
+        let in_flight = {
+            let mut index = self.index.lock().await;
+            if let Some(file) = index.files.get_mut(&key) {
+                file.last_used = SystemTime::now();
+                return Some(file.path.clone());
+            }
+            index.in_flight.get(&key).cloned()
+        };
+
+        let mut done = in_flight?;
+        let _ = tokio::time::timeout(IN_FLIGHT_WAIT, done.wait_for(|done| *done)).await;
+
+        self.index
+            .lock()
+            .await
+            .files
+            .get(&key)
+            .map(|f| f.path.clone())
+    }
+
+    /// Forgets and deletes the cached copy of `url`, e.g. because it wouldn't play.
+    pub async fn remove(&self, url: &str) {
+        let Some(file) = self.index.lock().await.files.remove(&Self::key(url)) else {
+            return;
+        };
+        if let Err(e) = tokio::fs::remove_file(&file.path).await {
+            warn!("Failed to remove {}: {}", file.path.display(), e);
+        }
+    }
+
+    /// Starts downloading `url` in the background, unless it's cached or already downloading.
+    pub fn prefetch(self: &Arc<Self>, url: String) {
+        let cache = self.clone();
+        // made out here so the download shows up under whatever asked for it
+        let span = info_span!("ytdl_download", url = %url);
+        tokio::spawn(async move {
+            let key = Self::key(&url);
+            let done = {
+                let mut index = cache.index.lock().await;
+                if index.files.contains_key(&key) || index.in_flight.contains_key(&key) {
+                    return;
+                }
+                let (tx, rx) = watch::channel(false);
+                index.in_flight.insert(key.clone(), rx);
+                tx
+            };
+
+            let result = cache.download(&key, &url).instrument(span).await;
+
+            let mut index = cache.index.lock().await;
+            index.in_flight.remove(&key);
+            match result {
+                Ok(file) => {
+                    info!("Cached {} ({} bytes)", url, file.size);
+                    index.files.insert(key, file);
+                    cache.evict(&mut index).await;
+                }
+                Err(e) => warn!("Failed to prefetch {}: {}", url, e),
+            }
+            let _ = done.send(true);
+        });
+    }
+
+    async fn download(&self, key: &str, url: &str) -> Result<CachedFile> {
+        let _permit = self.downloads.acquire().await?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let downloaded = self.run_ytdl(key, url).await;
+        if downloaded.is_err() {
+            // whatever yt-dlp got through before it failed or was killed
+            self.remove_temp(key).await;
+        }
+        downloaded
+    }
+
+    async fn run_ytdl(&self, key: &str, url: &str) -> Result<CachedFile> {
+        let template = self.dir.join(format!("tmp-{}.%(ext)s", key));
+        let download = Command::new(&self.ytdl.program)
+            .args(&self.ytdl.args)
+            .arg("-f")
+            .arg("ba[abr>0][vcodec=none]/best")
+            .arg("--no-playlist")
+            // in case the track's metadata was wrong about it being worth fetching
+            .arg("--match-filter")
+            .arg(format!("!is_live & duration <= {}", MAX_LENGTH.as_secs()))
+            .arg("--print")
+            .arg("after_move:filepath")
+            .arg("-o")
+            .arg(&template)
+            .arg(url)
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(DOWNLOAD_TIMEOUT, download)
+            .await
+            .map_err(|_| anyhow!("yt-dlp took longer than {:?}", DOWNLOAD_TIMEOUT))??;
+
+        if !output.status.success() {
+            metrics().ytdl_failed();
+            return Err(anyhow!(
+                "yt-dlp failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let downloaded = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        if downloaded.is_empty() {
+            return Err(anyhow!("it's live or too long to keep"));
+        }
+        let downloaded = PathBuf::from(downloaded);
+        let ext = downloaded
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("audio");
+        let path = self.dir.join(format!("{}.{}", key, ext));
+        tokio::fs::rename(&downloaded, &path).await?;
+        let size = tokio::fs::metadata(&path).await?.len();
+
+        Ok(CachedFile {
+            path,
+            size,
+            last_used: SystemTime::now(),
+        })
+    }
+
+    async fn remove_temp(&self, key: &str) {
+        let prefix = format!("tmp-{}.", key);
+        let Ok(mut entries) = tokio::fs::read_dir(&self.dir).await else {
+            return;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                if let Err(e) = tokio::fs::remove_file(entry.path()).await {
+                    warn!("Failed to remove {}: {}", entry.path().display(), e);
+                }
+            }
+        }
+    }
+
+    /// Drops anything too old, then the least recently used files until we're under budget.
+    async fn evict(&self, index: &mut CacheIndex) {
+        let expired = expired(
+            &index.files,
+            SystemTime::now(),
+            self.max_age,
+            self.max_bytes,
+        );
+        for key in expired {
+            if let Some(file) = index.files.remove(&key) {
+                if let Err(e) = tokio::fs::remove_file(&file.path).await {
+                    warn!("Failed to evict {}: {}", file.path.display(), e);
+                }
+            }
+        }
+    }
+}
+
+/// The files to evict: anything unused for longer than `max_age`, then the least recently used
+/// until the rest fit in `max_bytes`.
+fn expired(
+    files: &HashMap<String, CachedFile>,
+    now: SystemTime,
+    max_age: Duration,
+    max_bytes: u64,
+) -> Vec<String> {
+    let mut expired: Vec<String> = files
+        .iter()
+        .filter(|(_, f)| {
+            now.duration_since(f.last_used)
+                .is_ok_and(|age| age > max_age)
+        })
+        .map(|(k, _)| k.clone())
+        .collect();
+
+    let mut total: u64 = files
+        .iter()
+        .filter(|(k, _)| !expired.contains(k))
+        .map(|(_, f)| f.size)
+        .sum();
+
+    if total > max_bytes {
+        let mut by_age: Vec<_> = files
+            .iter()
+            .filter(|(k, _)| !expired.contains(k))
+            .map(|(k, f)| (k.clone(), f.last_used, f.size))
+            .collect();
+        by_age.sort_by_key(|(_, last_used, _)| *last_used);
+
+        for (key, _, size) in by_age {
+            if total <= max_bytes {
+                break;
+            }
+            total -= size;
+            expired.push(key);
+        }
+    }
+
+    expired
+}
+
+/// A yt-dlp track which plays from the cache when it can, and falls back to streaming.
+pub struct CachedInput {
+    ytdl: YoutubeDl,
+    url: String,
+    cache: Arc<TrackCache>,
+}
+
+impl CachedInput {
+    pub fn new(ytdl: YoutubeDl, url: String, cache: Arc<TrackCache>) -> Self {
+        Self { ytdl, url, cache }
+    }
+}
+
+impl From<CachedInput> for Input {
+    fn from(val: CachedInput) -> Self {
+        Input::Lazy(Box::new(val))
+    }
+}
+
+#[async_trait]
+impl Compose for CachedInput {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        // a miss just streams, downloading it as well would fetch the same track twice
+        if let Some(path) = self.cache.get(&self.url).await {
+            match File::new(path).create_async().await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => warn!("Cached file for {} is unusable: {}", self.url, e),
+            }
+        }
+
+        self.ytdl.create_async().await
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        self.ytdl.aux_metadata().await
+    }
+}
+
+/// Starts downloading the next queued track whenever a track starts playing.
+pub struct PrefetchNext {
+    pub queue: TrackQueue,
+    pub cache: Arc<TrackCache>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for PrefetchNext {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let next = self.queue.current_queue().into_iter().nth(1)?;
+        let url = next.typemap().read().await.get::<PrefetchUrl>().cloned()?;
+        self.cache.prefetch(url);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn files(entries: &[(&str, u64, u64)]) -> (HashMap<String, CachedFile>, SystemTime) {
+        let now = SystemTime::UNIX_EPOCH + HOUR * 1000;
+        let files = entries
+            .iter()
+            .map(|(key, size, hours_ago)| {
+                let file = CachedFile {
+                    path: PathBuf::from(format!("{}.webm", key)),
+                    size: *size,
+                    last_used: now - HOUR * *hours_ago as u32,
+                };
+                (key.to_string(), file)
+            })
+            .collect();
+        (files, now)
+    }
+
+    fn sorted(mut keys: Vec<String>) -> Vec<String> {
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn evicts_old_files() {
+        let (files, now) = files(&[("fresh", 10, 1), ("stale", 10, 200), ("ancient", 10, 900)]);
+        assert_eq!(
+            sorted(expired(&files, now, HOUR * 168, 1000)),
+            ["ancient", "stale"]
+        );
+    }
+
+    #[test]
+    fn evicts_least_recently_used_until_under_budget() {
+        let (files, now) = files(&[("a", 40, 3), ("b", 40, 2), ("c", 40, 1), ("d", 40, 0)]);
+        assert_eq!(sorted(expired(&files, now, HOUR * 168, 100)), ["a", "b"]);
+        assert_eq!(
+            sorted(expired(&files, now, HOUR * 168, 160)),
+            Vec::<String>::new()
+        );
+        // old files count towards the budget being freed
+        assert_eq!(sorted(expired(&files, now, HOUR * 2, 80)), ["a", "b"]);
+        assert_eq!(sorted(expired(&files, now, HOUR * 2, 40)), ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn cleans_up_after_a_failed_download() {
+        let dir = std::env::temp_dir().join(format!("marine-prefetch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // gets partway through, like yt-dlp does, then gives up; the template is the 9th argument
+        let ytdl = Box::leak(Box::new(YtdlConfig {
+            program: "sh".to_owned(),
+            args: vec![
+                "-c".to_owned(),
+                r#"touch "${8%.*}.webm.part"; exit 1"#.to_owned(),
+            ],
+        }));
+        let cache = TrackCache::open(dir.clone(), 1 << 20, HOUR, ytdl);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tmp-other.webm.part"), b"").unwrap();
+
+        assert!(cache
+            .download("abc", "https://youtu.be/dQw4w9WgXcQ")
+            .await
+            .is_err());
+        let left: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        // only another download's file is left
+        assert_eq!(left, ["tmp-other.webm.part"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn keys_are_stable() {
+        assert_eq!(
+            TrackCache::key("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            "dQw4w9WgXcQ"
+        );
+        // the first half of the URL's SHA-256, so it never changes between builds
+        assert_eq!(TrackCache::key("abc"), "ba7816bf8f01cfea414140de5dae2223");
+    }
+
+    #[test]
+    fn only_prefetches_tracks_of_known_reasonable_length() {
+        let metadata = |duration: Option<u64>| AuxMetadata {
+            duration: duration.map(Duration::from_secs),
+            ..Default::default()
+        };
+        assert!(worth_prefetching(Some(&metadata(Some(213)))));
+        assert!(!worth_prefetching(Some(&metadata(Some(3 * 60 * 60)))));
+        assert!(!worth_prefetching(Some(&metadata(Some(0)))));
+        assert!(!worth_prefetching(Some(&metadata(None))));
+        assert!(!worth_prefetching(None));
+    }
+}
@@ -1,22 +1,32 @@
 use std::cmp::min;
 
 use poise::CreateReply;
+use serenity::all::GuildId;
+use songbird::tracks::{TrackHandle, TrackQueue};
 
 //nowplaying
 use crate::{
-    helpers::d2hms,
-    voice::{metadata::Metadata, play::build_play_embed},
+    err::ErrorKind,
+    voice::{
+        metadata::{title_of, Metadata, RequestedBy},
+        play::build_play_embed,
+    },
     AppError, Context,
 };
 
-use super::get_or_join_call;
+use super::{
+    controls::can_control,
+    get_or_join_call,
+    nowplaying::{self, NowPlaying},
+};
 
 #[poise::command(
     category = "Music",
     slash_command,
     prefix_command,
     aliases("s"),
-    guild_only
+    guild_only,
+    check = "can_control"
 )]
 pub async fn skip(ctx: Context<'_>) -> Result<(), AppError> {
     let manager = songbird::get(ctx.serenity_context())
@@ -32,6 +42,7 @@ pub async fn skip(ctx: Context<'_>) -> Result<(), AppError> {
             ctx.say(format!("Failed to skip: {:?}", result)).await?;
             return Ok(());
         }
+        ctx.data().now_playing.refresh(guild_id).await;
         let current = handler.queue().current();
         if let Some(current) = current {
             if let Some(metadata) = current.typemap().read().await.get::<Metadata>() {
@@ -46,7 +57,7 @@ pub async fn skip(ctx: Context<'_>) -> Result<(), AppError> {
             ctx.say("Couldn't get metadata for this track").await?;
         }
     } else {
-        ctx.say("Not in a voice channel!").await?;
+        return Err(ErrorKind::NotInVoice.into());
     }
 
     Ok(())
@@ -68,30 +79,27 @@ pub async fn now_playing(ctx: Context<'_>) -> Result<(), AppError> {
     let (guild_id, channel_id) = super::guild_info(ctx).await?;
 
     if let Ok(handler_lock) = get_or_join_call(&manager, ctx, guild_id, channel_id).await {
-        let handler = handler_lock.lock().await;
-
-        let current = handler.queue().current();
+        let queue = handler_lock.lock().await.queue().clone();
 
-        if let Some(current) = current {
-            if let Some(metadata) = current.typemap().read().await.get::<Metadata>() {
-                let position = current.get_info().await.map(|i| i.position).ok();
-                let embed = build_play_embed(metadata, true, position).await;
-                ctx.send(CreateReply::default().embed(embed)).await?;
-            } else if let Ok(info) = current.get_info().await {
-                ctx.say(format!(
-                    "Current position is: {} / {}",
-                    d2hms(info.play_time),
-                    d2hms(info.position)
-                ))
-                .await?;
-            } else {
-                ctx.say("No metadata available!").await?;
-            }
+        if let Some(current) = queue.current() {
+            let view = NowPlaying::render(&current).await;
+            let reply = ctx.send(nowplaying::reply(view)).await?;
+            let message = reply.message().await?;
+            ctx.data()
+                .now_playing
+                .start(
+                    ctx.data().clone(),
+                    ctx.serenity_context().http.clone(),
+                    guild_id,
+                    (message.channel_id, message.id),
+                    queue,
+                )
+                .await;
         } else {
             ctx.say("No track playing!").await?;
         }
     } else {
-        ctx.say("Not in a voice channel!").await?;
+        return Err(ErrorKind::NotInVoice.into());
     }
 
     Ok(())
@@ -116,73 +124,205 @@ pub async fn queue(ctx: Context<'_>) -> Result<(), AppError> {
         let handler = handler_lock.lock().await;
 
         let queue = handler.queue().current_queue();
+        if queue.is_empty() {
+            ctx.say("Queue is empty").await?;
+            return Ok(());
+        }
 
         let mut msg = String::new();
         let from = 0;
         let to = min(from + 10, queue.len());
         msg.push_str(&format!(
             "Queue, page {} of {}\n",
-            (from + 9) / 10 + 1,
-            (queue.len() + 9) / 10
+            from.div_ceil(10) + 1,
+            queue.len().div_ceil(10)
         ));
         for (i, track) in queue[from..to].iter().enumerate() {
-            if let Some(metadata) = track.typemap().read().await.get::<Metadata>() {
-                let title = metadata.title.clone().unwrap_or("This track".to_string());
-                msg.push_str(&format!("{}. {}\n", i + 1, title));
+            let typemap = track.typemap().read().await;
+            if typemap.contains_key::<Metadata>() {
+                let title = title_of(&typemap).unwrap_or("This track".to_string());
+                match typemap.get::<RequestedBy>() {
+                    Some(requester) => msg.push_str(&format!(
+                        "{}. {} - requested by {}\n",
+                        i + 1,
+                        title,
+                        requester.name
+                    )),
+                    None => msg.push_str(&format!("{}. {}\n", i + 1, title)),
+                }
             }
         }
 
-        if msg.is_empty() {
-            ctx.say("Queue is empty").await?;
-        } else {
-            ctx.say(msg).await?;
-        }
+        ctx.say(msg).await?;
     } else {
-        ctx.say("Not in a voice channel!").await?;
+        return Err(ErrorKind::NotInVoice.into());
     }
 
     Ok(())
 }
 
+/// Takes the track at `index` out of the queue. The playing track is at 0 and can only be
+/// skipped.
+pub fn remove_at(queue: &TrackQueue, index: usize) -> Result<TrackHandle, AppError> {
+    if index == 0 {
+        return Err(
+            ErrorKind::InvalidValue("the playing track can only be skipped".to_owned()).into(),
+        );
+    }
+    let removed = queue
+        .modify_queue(|queue| queue.remove(index))
+        .ok_or_else(|| no_track(index))?;
+    // it never started, but its input may already be loading
+    let _ = removed.stop();
+    Ok(removed.handle())
+}
+
+/// Moves an upcoming track from `from` to `to`, both counting the playing track as 0.
+pub fn move_track(queue: &TrackQueue, from: usize, to: usize) -> Result<TrackHandle, AppError> {
+    if from == 0 || to == 0 {
+        return Err(ErrorKind::InvalidValue("the playing track can't be moved".to_owned()).into());
+    }
+    queue.modify_queue(|queue| {
+        if to >= queue.len() {
+            return Err(no_track(to));
+        }
+        let track = queue.remove(from).ok_or_else(|| no_track(from))?;
+        let handle = track.handle();
+        queue.insert(to, track);
+        Ok(handle)
+    })
+}
+
+fn no_track(index: usize) -> AppError {
+    ErrorKind::InvalidValue(format!("there's no track at position {}", index)).into()
+}
+
+async fn track_title(track: &TrackHandle) -> String {
+    title_of(&*track.typemap().read().await).unwrap_or("the track".to_owned())
+}
+
+/// Removes a track from the queue
 #[poise::command(
     category = "Music",
     slash_command,
     prefix_command,
     aliases("rm"),
-    guild_only
+    guild_only,
+    check = "can_control"
 )]
 pub async fn remove(
     ctx: Context<'_>,
     #[description = "The index of the track to remove"] index: usize,
 ) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+    let queue = current_queue(ctx, guild_id).await?;
+
+    let removed = remove_at(&queue, index)?;
+    ctx.data().now_playing.refresh(guild_id).await;
+    ctx.say(format!(
+        "Successfully removed {} at index {}",
+        track_title(&removed).await,
+        index
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Moves a track to another spot in the queue
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    rename = "move",
+    aliases("mv"),
+    guild_only,
+    check = "can_control"
+)]
+pub async fn move_cmd(
+    ctx: Context<'_>,
+    #[description = "The index of the track to move"] from: usize,
+    #[description = "Where it should go"] to: usize,
+) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+    let queue = current_queue(ctx, guild_id).await?;
+
+    let moved = move_track(&queue, from, to)?;
+    ctx.data().now_playing.refresh(guild_id).await;
+    ctx.say(format!(
+        "Moved {} to index {}",
+        track_title(&moved).await,
+        to
+    ))
+    .await?;
+
+    Ok(())
+}
+
+async fn current_queue(ctx: Context<'_>, guild_id: GuildId) -> Result<TrackQueue, AppError> {
     let manager = songbird::get(ctx.serenity_context())
         .await
         .expect("Songbird Voice client placed in at initialisation.")
         .clone();
+    let call = manager.get(guild_id).ok_or(ErrorKind::NotInVoice)?;
+    let queue = call.lock().await.queue().clone();
+    Ok(queue)
+}
 
-    let (guild_id, channel_id) = super::guild_info(ctx).await?;
+#[cfg(test)]
+mod tests {
+    use songbird::{tracks::Track, Driver};
 
-    if let Ok(handler_lock) = get_or_join_call(&manager, ctx, guild_id, channel_id).await {
-        let handler = handler_lock.lock().await;
+    use super::*;
 
-        let queue = handler.queue().modify_queue(|queue| queue.remove(index));
+    /// A queue of `n` silent tracks, and their handles in order.
+    async fn queue_of(n: usize, driver: &mut Driver) -> (TrackQueue, Vec<TrackHandle>) {
+        let queue = TrackQueue::new();
+        let handles = (0..n)
+            .map(|_| queue.add_with_preload(Track::from(vec![0u8; 16]), driver, None))
+            .collect();
+        (queue, handles)
+    }
 
-        if let Some(current) = queue {
-            if let Some(metadata) = current.typemap().read().await.get::<Metadata>() {
-                ctx.say(format!(
-                    "Successfully removed {} at index {}",
-                    metadata.title.clone().unwrap_or("the track".to_owned()),
-                    index
-                ))
-                .await?;
-            }
-        } else {
-            ctx.say(format!("Failed to remove the track at index {}", index))
-                .await?;
-        }
-    } else {
-        ctx.say("Not in a voice channel!").await?;
+    fn order(queue: &TrackQueue, handles: &[TrackHandle]) -> Vec<usize> {
+        queue
+            .current_queue()
+            .iter()
+            .map(|t| handles.iter().position(|h| h.uuid() == t.uuid()).unwrap())
+            .collect()
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn removes_upcoming_tracks() {
+        let mut driver = Driver::default();
+        let (queue, handles) = queue_of(4, &mut driver).await;
+
+        let removed = remove_at(&queue, 2).unwrap();
+        assert_eq!(removed.uuid(), handles[2].uuid());
+        assert_eq!(order(&queue, &handles), [0, 1, 3]);
+
+        assert!(remove_at(&queue, 0).is_err());
+        assert!(remove_at(&queue, 3).is_err());
+        assert_eq!(order(&queue, &handles), [0, 1, 3]);
+    }
+
+    #[tokio::test]
+    async fn moves_upcoming_tracks() {
+        let mut driver = Driver::default();
+        let (queue, handles) = queue_of(4, &mut driver).await;
+
+        let moved = move_track(&queue, 3, 1).unwrap();
+        assert_eq!(moved.uuid(), handles[3].uuid());
+        assert_eq!(order(&queue, &handles), [0, 3, 1, 2]);
+
+        move_track(&queue, 1, 3).unwrap();
+        assert_eq!(order(&queue, &handles), [0, 1, 2, 3]);
+
+        // the playing track stays put, and nothing moves past the end
+        assert!(move_track(&queue, 0, 2).is_err());
+        assert!(move_track(&queue, 2, 0).is_err());
+        assert!(move_track(&queue, 4, 1).is_err());
+        assert!(move_track(&queue, 1, 4).is_err());
+        assert_eq!(order(&queue, &handles), [0, 1, 2, 3]);
+    }
 }
@@ -0,0 +1,302 @@
+use std::{
+    io::{self, SeekFrom},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::TryStreamExt;
+use reqwest::{header::CONTENT_TYPE, Client as HttpClient};
+use serenity::async_trait;
+use songbird::input::{
+    AsyncAdapterStream, AsyncMediaSource, AudioStream, AudioStreamError, AuxMetadata, Compose,
+    Input,
+};
+use symphonia::core::{io::MediaSource, probe::Hint};
+use tokio::{
+    io::{AsyncRead, AsyncSeek, ReadBuf},
+    sync::mpsc::UnboundedSender,
+};
+use tokio_util::io::StreamReader;
+
+/// An Icecast/Shoutcast stream which asks for inline ICY metadata and reports every
+/// `StreamTitle` it sees.
+pub struct IcyStream {
+    http: HttpClient,
+    url: String,
+    titles: UnboundedSender<String>,
+}
+
+impl IcyStream {
+    pub fn new(http: HttpClient, url: String, titles: UnboundedSender<String>) -> Self {
+        Self { http, url, titles }
+    }
+}
+
+impl From<IcyStream> for Input {
+    fn from(val: IcyStream) -> Self {
+        Input::Lazy(Box::new(val))
+    }
+}
+
+#[async_trait]
+impl Compose for IcyStream {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let response = self
+            .http
+            .get(&self.url)
+            .header("Icy-MetaData", "1")
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+
+        let metaint = response
+            .headers()
+            .get("icy-metaint")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok());
+
+        let mut hint = Hint::new();
+        if let Some(content_type) = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+        {
+            hint.mime_type(content_type);
+            if let Some(ext) = extension_for_mime(content_type) {
+                hint.with_extension(ext);
+            }
+        }
+
+        let body = StreamReader::new(response.bytes_stream().map_err(io::Error::other));
+        let reader = IcyReader {
+            inner: Box::new(body),
+            demuxer: metaint.map(IcyDemuxer::new),
+            titles: self.titles.clone(),
+            scratch: vec![0; 16 * 1024],
+            pending: Vec::new(),
+            pending_pos: 0,
+        };
+
+        Ok(AudioStream {
+            input: Box::new(AsyncAdapterStream::new(Box::new(reader), 64 * 1024)),
+            hint: Some(hint),
+        })
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        Ok(AuxMetadata {
+            source_url: Some(self.url.clone()),
+            ..Default::default()
+        })
+    }
+}
+
+fn extension_for_mime(mime: &str) -> Option<&'static str> {
+    match mime.split(';').next()?.trim() {
+        "audio/mpeg" | "audio/mp3" => Some("mp3"),
+        "audio/aac" | "audio/aacp" | "audio/x-aac" => Some("aac"),
+        "audio/ogg" | "application/ogg" => Some("ogg"),
+        "audio/flac" => Some("flac"),
+        _ => None,
+    }
+}
+
+/// Splits ICY metadata blocks out of a stream, leaving just the audio.
+pub struct IcyDemuxer {
+    metaint: usize,
+    state: DemuxState,
+    meta: Vec<u8>,
+}
+
+enum DemuxState {
+    /// Audio bytes left before the next metadata block.
+    Audio(usize),
+    /// Waiting on the length byte of a metadata block.
+    Length,
+    /// Metadata bytes left in the current block.
+    Meta(usize),
+}
+
+impl IcyDemuxer {
+    pub fn new(metaint: usize) -> Self {
+        Self {
+            metaint,
+            state: DemuxState::Audio(metaint),
+            meta: Vec::new(),
+        }
+    }
+
+    /// Feeds raw stream bytes in, appending audio to `audio`.
+    /// Returns the last stream title found in this chunk, if any.
+    pub fn push(&mut self, mut input: &[u8], audio: &mut Vec<u8>) -> Option<String> {
+        let mut title = None;
+
+        while !input.is_empty() {
+            match self.state {
+                DemuxState::Audio(remaining) => {
+                    let n = remaining.min(input.len());
+                    audio.extend_from_slice(&input[..n]);
+                    input = &input[n..];
+                    self.state = if n == remaining {
+                        DemuxState::Length
+                    } else {
+                        DemuxState::Audio(remaining - n)
+                    };
+                }
+                DemuxState::Length => {
+                    let len = input[0] as usize * 16;
+                    input = &input[1..];
+                    self.meta.clear();
+                    self.state = if len == 0 {
+                        DemuxState::Audio(self.metaint)
+                    } else {
+                        DemuxState::Meta(len)
+                    };
+                }
+                DemuxState::Meta(remaining) => {
+                    let n = remaining.min(input.len());
+                    self.meta.extend_from_slice(&input[..n]);
+                    input = &input[n..];
+                    if n == remaining {
+                        title = parse_stream_title(&self.meta).or(title);
+                        self.state = DemuxState::Audio(self.metaint);
+                    } else {
+                        self.state = DemuxState::Meta(remaining - n);
+                    }
+                }
+            }
+        }
+
+        title
+    }
+}
+
+/// Pulls `StreamTitle` out of a metadata block like `StreamTitle='Artist - Song';StreamUrl='';`.
+pub fn parse_stream_title(meta: &[u8]) -> Option<String> {
+    let meta = String::from_utf8_lossy(meta);
+    let meta = meta.trim_end_matches('\0');
+    let start = meta.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &meta[start..];
+    let end = rest.find("';").unwrap_or(rest.len());
+    let title = rest[..end].trim();
+    (!title.is_empty()).then(|| title.to_owned())
+}
+
+struct IcyReader {
+    inner: Box<dyn AsyncRead + Send + Sync + Unpin>,
+    demuxer: Option<IcyDemuxer>,
+    titles: UnboundedSender<String>,
+    scratch: Vec<u8>,
+    pending: Vec<u8>,
+    pending_pos: usize,
+}
+
+impl AsyncRead for IcyReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        // no metadata interval means there's nothing to strip
+        if this.demuxer.is_none() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        while this.pending_pos >= this.pending.len() {
+            let mut scratch = ReadBuf::new(&mut this.scratch);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut scratch) {
+                Poll::Ready(Ok(())) => {}
+                other => return other,
+            }
+
+            let filled = scratch.filled();
+            if filled.is_empty() {
+                // end of stream
+                return Poll::Ready(Ok(()));
+            }
+
+            this.pending.clear();
+            this.pending_pos = 0;
+            let demuxer = this.demuxer.as_mut().expect("checked above");
+            if let Some(title) = demuxer.push(filled, &mut this.pending) {
+                // nobody listening any more is fine, the audio still plays
+                let _ = this.titles.send(title);
+            }
+        }
+
+        let n = buf.remaining().min(this.pending.len() - this.pending_pos);
+        buf.put_slice(&this.pending[this.pending_pos..][..n]);
+        this.pending_pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for IcyReader {
+    fn start_seek(self: Pin<&mut Self>, _position: SeekFrom) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Err(io::ErrorKind::Unsupported.into()))
+    }
+}
+
+#[async_trait]
+impl AsyncMediaSource for IcyReader {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    async fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demuxer_strips_metadata_across_chunks() {
+        let meta = b"StreamTitle='Artist - Song';\0\0\0\0";
+        let mut stream = b"abcd".to_vec();
+        stream.push(2);
+        stream.extend_from_slice(meta);
+        stream.extend_from_slice(b"efgh");
+        stream.push(0);
+        stream.extend_from_slice(b"ij");
+
+        let mut demuxer = IcyDemuxer::new(4);
+        let mut audio = Vec::new();
+        let mut titles = Vec::new();
+        for chunk in stream.chunks(3) {
+            titles.extend(demuxer.push(chunk, &mut audio));
+        }
+
+        assert_eq!(audio, b"abcdefghij");
+        assert_eq!(titles, vec!["Artist - Song".to_owned()]);
+    }
+
+    #[test]
+    fn parses_stream_title() {
+        assert_eq!(
+            parse_stream_title(b"StreamTitle='It's Me - Hi';StreamUrl='';").as_deref(),
+            Some("It's Me - Hi")
+        );
+        assert_eq!(parse_stream_title(b"StreamTitle='';"), None);
+        assert_eq!(parse_stream_title(b"StreamUrl='x';"), None);
+    }
+}
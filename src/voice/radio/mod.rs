@@ -0,0 +1,313 @@
+use std::{collections::HashMap, sync::Arc};
+
+use poise::CreateReply;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, EditMessage, GuildId, Http, MessageId};
+use songbird::{
+    input::{AuxMetadata, HlsRequest, Input},
+    tracks::{Track, TrackHandle},
+};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{err::ErrorKind, helpers::get_http_client, store::JsonStore, AppError, Context};
+
+use super::{
+    controls::can_control,
+    fairness::{check_limits, rebalance},
+    filters::source::FilteredInput,
+    get_or_join_call,
+    metadata::{Metadata, RequestedBy, Requester},
+    play::build_play_embed,
+    source::SourceKind,
+};
+
+pub mod icy;
+
+/// A saved internet radio station.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Station {
+    pub name: String,
+    pub url: String,
+}
+
+pub type RadioPresets = JsonStore<HashMap<GuildId, Vec<Station>>>;
+
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    subcommands("play", "save", "remove", "list"),
+    subcommand_required,
+    guild_only
+)]
+pub async fn radio(_ctx: Context<'_>) -> Result<(), AppError> {
+    Ok(())
+}
+
+async fn autocomplete_station(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+    let partial = partial.to_lowercase();
+    ctx.data()
+        .radio
+        .read()
+        .await
+        .get(&guild_id)
+        .map(|stations| {
+            stations
+                .iter()
+                .filter(|s| s.name.to_lowercase().contains(&partial))
+                .map(|s| s.name.clone())
+                .take(25)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Plays an internet radio station or live stream
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn play(
+    ctx: Context<'_>,
+    #[description = "A stream URL or the name of a saved station"]
+    #[autocomplete = "autocomplete_station"]
+    station: String,
+) -> Result<(), AppError> {
+    let (guild_id, channel_id) = super::guild_info(ctx).await?;
+
+    let preset = ctx
+        .data()
+        .radio
+        .read()
+        .await
+        .get(&guild_id)
+        .and_then(|stations| {
+            stations
+                .iter()
+                .find(|s| s.name.eq_ignore_ascii_case(&station))
+                .cloned()
+        });
+    let station = match preset {
+        Some(preset) => preset,
+        None if is_stream_url(&station) => Station {
+            name: station.clone(),
+            url: station,
+        },
+        None => {
+            ctx.say("That's not a stream URL or a saved station")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let settings = ctx.data().settings.get(guild_id).await;
+    if !settings.allowed_sources.contains(&SourceKind::Radio) {
+        return Err(ErrorKind::PermissionDenied("play radio in this server".to_owned()).into());
+    }
+
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let Ok(handler_lock) = get_or_join_call(&manager, ctx, guild_id, channel_id).await else {
+        return Err(ErrorKind::NotInVoice.into());
+    };
+
+    ctx.defer().await?;
+
+    let http = get_http_client(ctx.serenity_context()).await;
+    let filters = ctx.data().playback.filters(guild_id).await;
+
+    let (titles_tx, titles_rx) = mpsc::unbounded_channel();
+    let input: Input = if is_hls(&station.url) {
+        HlsRequest::new(http, station.url.clone()).into()
+    } else {
+        icy::IcyStream::new(http, station.url.clone(), titles_tx).into()
+    };
+
+    let metadata = AuxMetadata {
+        title: Some(station.name.clone()),
+        album: Some(station.name.clone()),
+        source_url: Some(station.url.clone()),
+        ..Default::default()
+    };
+
+    let mut handler = handler_lock.lock().await;
+    let requester = Requester::new(ctx.author());
+    check_limits(&settings, handler.queue(), requester.id, None).await?;
+
+    let content = match handler.queue().len() {
+        0 => format!("Tuning in to {}.", station.name),
+        n => format!("{} will play after {} more tracks.", station.name, n),
+    };
+
+    let embed = build_play_embed(&metadata, true, None).await;
+    let reply = ctx
+        .send(CreateReply::default().content(content).embed(embed))
+        .await?;
+    let message = reply.message().await.ok().map(|m| (m.channel_id, m.id));
+
+    let track = handler
+        .enqueue(Track::from(FilteredInput::wrap(input, filters)).volume(settings.volume_scale()))
+        .await;
+    {
+        let mut typemap = track.typemap().write().await;
+        typemap.insert::<Metadata>(metadata);
+        typemap.insert::<RequestedBy>(requester);
+    }
+    if settings.fair_queue {
+        rebalance(handler.queue()).await;
+    }
+    drop(handler);
+
+    watch_titles(
+        titles_rx,
+        track,
+        ctx.serenity_context().http.clone(),
+        message,
+    );
+
+    Ok(())
+}
+
+/// Saves a station under a name for this server
+#[poise::command(slash_command, prefix_command, guild_only, check = "can_control")]
+pub async fn save(
+    ctx: Context<'_>,
+    #[description = "Name for the station"] name: String,
+    #[description = "The stream URL"] url: String,
+) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+
+    if !is_stream_url(&url) {
+        ctx.say("That doesn't look like a stream URL").await?;
+        return Ok(());
+    }
+
+    let replaced = ctx
+        .data()
+        .radio
+        .update(|presets| {
+            let stations = presets.entry(guild_id).or_default();
+            let before = stations.len();
+            stations.retain(|s| !s.name.eq_ignore_ascii_case(&name));
+            let replaced = stations.len() != before;
+            stations.push(Station {
+                name: name.clone(),
+                url,
+            });
+            replaced
+        })
+        .await?;
+
+    if replaced {
+        ctx.say(format!("Updated station {}", name)).await?;
+    } else {
+        ctx.say(format!("Saved station {}", name)).await?;
+    }
+    Ok(())
+}
+
+/// Removes a saved station
+#[poise::command(slash_command, prefix_command, guild_only, check = "can_control")]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "The station to remove"]
+    #[autocomplete = "autocomplete_station"]
+    name: String,
+) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+
+    let removed = ctx
+        .data()
+        .radio
+        .update(|presets| {
+            let stations = presets.entry(guild_id).or_default();
+            let before = stations.len();
+            stations.retain(|s| !s.name.eq_ignore_ascii_case(&name));
+            stations.len() != before
+        })
+        .await?;
+
+    if removed {
+        ctx.say(format!("Removed station {}", name)).await?;
+    } else {
+        ctx.say(format!("No station called {}", name)).await?;
+    }
+    Ok(())
+}
+
+/// Lists this server's saved stations
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn list(ctx: Context<'_>) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+
+    let msg = match ctx.data().radio.read().await.get(&guild_id) {
+        Some(stations) if !stations.is_empty() => {
+            let mut msg = String::from("Saved stations:\n");
+            for station in stations {
+                msg.push_str(&format!("- **{}**: <{}>\n", station.name, station.url));
+            }
+            msg
+        }
+        _ => "No saved stations yet, add one with `/radio save`".to_owned(),
+    };
+
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+fn is_stream_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+fn is_hls(url: &str) -> bool {
+    url.split(['?', '#'])
+        .next()
+        .is_some_and(|path| path.ends_with(".m3u8"))
+}
+
+/// Keeps the track's metadata and its announcement in sync with what the station is playing.
+fn watch_titles(
+    mut titles: mpsc::UnboundedReceiver<String>,
+    track: TrackHandle,
+    http: Arc<Http>,
+    message: Option<(ChannelId, MessageId)>,
+) {
+    tokio::spawn(async move {
+        // the channel closes once the stream is dropped
+        while let Some(stream_title) = titles.recv().await {
+            info!("Radio now playing: {}", stream_title);
+
+            let metadata = {
+                let mut typemap = track.typemap().write().await;
+                let Some(metadata) = typemap.get_mut::<Metadata>() else {
+                    break;
+                };
+                match stream_title.split_once(" - ") {
+                    Some((artist, title)) => {
+                        metadata.artist = Some(artist.trim().to_owned());
+                        metadata.title = Some(title.trim().to_owned());
+                    }
+                    None => {
+                        metadata.artist = None;
+                        metadata.title = Some(stream_title.clone());
+                    }
+                }
+                metadata.clone()
+            };
+
+            if let Some((channel_id, message_id)) = message {
+                let embed = build_play_embed(&metadata, true, None).await;
+                if let Err(e) = channel_id
+                    .edit_message(&http, message_id, EditMessage::new().embed(embed))
+                    .await
+                {
+                    warn!("Failed to update radio message: {}", e);
+                }
+            }
+        }
+    });
+}
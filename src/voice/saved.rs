@@ -0,0 +1,179 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId};
+use songbird::Call;
+use tracing::{info, warn};
+
+use crate::{
+    err::{AppError, ErrorKind},
+    helpers::{d2hms, get_http_client},
+    store::JsonStore,
+    Context,
+};
+
+use super::{
+    controls::can_control,
+    fairness::check_limits,
+    get_or_join_call,
+    metadata::{title_of, RequestedBy, Requester},
+    play::enqueue,
+    source::{resolve, OriginalSource, TrackSource},
+};
+
+/// Queues saved when the bot shut down, by guild.
+pub type SavedQueues = JsonStore<HashMap<GuildId, SavedQueue>>;
+
+/// What a guild was listening to when the bot shut down.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedQueue {
+    pub voice_channel: Option<ChannelId>,
+    pub text_channel: Option<ChannelId>,
+    /// How far into the first track playback had got.
+    pub position: Duration,
+    pub tracks: Vec<SavedTrack>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedTrack {
+    pub source: TrackSource,
+    pub title: Option<String>,
+    pub requester: Option<Requester>,
+}
+
+impl SavedQueue {
+    /// Captures the call's queue. Tracks that can't be requested again, like radio streams, are
+    /// left out.
+    pub async fn capture(call: &Call, text_channel: Option<ChannelId>) -> Self {
+        let queue = call.queue().current_queue();
+
+        let mut position = Duration::ZERO;
+        let mut tracks = Vec::new();
+        for (i, track) in queue.iter().enumerate() {
+            let typemap = track.typemap().read().await;
+            let Some(source) = typemap.get::<OriginalSource>().cloned() else {
+                continue;
+            };
+            if i == 0 {
+                position = track
+                    .get_info()
+                    .await
+                    .map(|info| info.position)
+                    .unwrap_or_default();
+            }
+            tracks.push(SavedTrack {
+                source,
+                title: title_of(&typemap),
+                requester: typemap.get::<RequestedBy>().cloned(),
+            });
+        }
+
+        Self {
+            voice_channel: call.current_channel().map(|c| ChannelId::new(c.0.get())),
+            text_channel,
+            position,
+            tracks,
+        }
+    }
+}
+
+/// Queues up whatever was playing here when the bot last shut down
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    guild_only,
+    check = "can_control"
+)]
+pub async fn restore(ctx: Context<'_>) -> Result<(), AppError> {
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let (guild_id, channel_id) = super::guild_info(ctx).await?;
+    let Some(saved) = ctx.data().saved.read().await.get(&guild_id).cloned() else {
+        ctx.say("There's no saved queue to restore").await?;
+        return Ok(());
+    };
+
+    let Ok(handler_lock) = get_or_join_call(&manager, ctx, guild_id, channel_id).await else {
+        return Err(ErrorKind::NotInVoice.into());
+    };
+    ctx.data()
+        .saved
+        .update(|saved| saved.remove(&guild_id))
+        .await?;
+    ctx.defer().await?;
+
+    let http = get_http_client(ctx.serenity_context()).await;
+    let settings = ctx.data().settings.get(guild_id).await;
+
+    let mut restored = Vec::new();
+    let mut stopped = None;
+    for track in saved.tracks {
+        if !settings.allowed_sources.contains(&track.source.kind()) {
+            continue;
+        }
+        let resolved = resolve(
+            http.clone(),
+            track.source.clone(),
+            settings.normalize,
+            &ctx.data().cache,
+            &ctx.data().config.ytdl,
+        )
+        .await;
+        let requester = track
+            .requester
+            .unwrap_or_else(|| Requester::new(ctx.author()));
+
+        let mut handler = handler_lock.lock().await;
+        // the saved queue gets the same limits as anything else being queued
+        if let Err(e) = check_limits(
+            &settings,
+            handler.queue(),
+            requester.id,
+            resolved.metadata.as_ref().and_then(|m| m.duration),
+        )
+        .await
+        {
+            stopped = Some(e);
+            break;
+        }
+        restored.push(
+            enqueue(
+                ctx.data(),
+                guild_id,
+                &mut handler,
+                track.source,
+                resolved,
+                requester,
+                false,
+            )
+            .await,
+        );
+    }
+
+    let mut msg = format!("Restored {} tracks", restored.len());
+    if let Some(e) = stopped {
+        msg.push_str(&format!(", then stopped: {}", e.message()));
+    }
+
+    // pick the first track back up where it left off, as long as it's the one playing
+    if let Some(first) = restored.first() {
+        let playing = handler_lock.lock().await.queue().current();
+        if !saved.position.is_zero() && playing.is_some_and(|t| t.uuid() == first.uuid()) {
+            if let Err(e) = first.seek_async(saved.position).await {
+                warn!("Couldn't seek restored track in {}: {}", guild_id, e);
+                msg.push_str(&format!(
+                    ". The first one couldn't skip ahead to {}, so it's starting over",
+                    d2hms(saved.position)
+                ));
+            }
+        }
+    }
+
+    info!("Restored {} tracks in guild {}", restored.len(), guild_id);
+    ctx.say(msg).await?;
+    Ok(())
+}
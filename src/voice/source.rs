@@ -0,0 +1,433 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::{Client as HttpClient, Url};
+use serde::{Deserialize, Serialize};
+use songbird::{
+    input::{AuxMetadata, Compose, File, HttpRequest, Input},
+    typemap::TypeMapKey,
+};
+use symphonia::core::probe::Hint;
+use tracing::{info_span, Instrument};
+
+use crate::{config::YtdlConfig, metrics::metrics};
+
+use super::{
+    loudness,
+    metadata::Cover,
+    prefetch::{CachedInput, TrackCache},
+    tags,
+};
+
+/// File extensions we can decode without going through yt-dlp.
+pub const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "flac", "ogg", "opus", "wav"];
+
+/// Where a requested track is going to come from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TrackSource {
+    /// Anything yt-dlp knows how to resolve.
+    YoutubeDl(String),
+    /// A direct link to an audio file, including Discord attachments.
+    Http { url: String, extension: String },
+    /// A file inside the configured media directory.
+    Local(PathBuf),
+}
+
+/// The kinds of source a server can allow tracks to come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    #[serde(rename = "youtube")]
+    YoutubeDl,
+    Http,
+    Local,
+    Radio,
+}
+
+impl SourceKind {
+    pub const ALL: [SourceKind; 4] = [
+        SourceKind::YoutubeDl,
+        SourceKind::Http,
+        SourceKind::Local,
+        SourceKind::Radio,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SourceKind::YoutubeDl => "youtube",
+            SourceKind::Http => "http",
+            SourceKind::Local => "local",
+            SourceKind::Radio => "radio",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        Self::ALL.into_iter().find(|s| s.name() == name)
+    }
+}
+
+impl TrackSource {
+    pub fn kind(&self) -> SourceKind {
+        match self {
+            TrackSource::YoutubeDl(_) => SourceKind::YoutubeDl,
+            TrackSource::Http { .. } => SourceKind::Http,
+            TrackSource::Local(_) => SourceKind::Local,
+        }
+    }
+
+    /// Works out the source for whatever the user typed. Anything that isn't a URL is looked up
+    /// in `media_dir`, if there is one.
+    pub fn parse(song: &str, media_dir: Option<&Path>) -> Result<Self> {
+        if song.starts_with("https://") {
+            let url = Url::parse(song).map_err(|_| anyhow!("Invalid URL"))?;
+            return Ok(match audio_extension(url.path()) {
+                Some(extension) => TrackSource::Http {
+                    url: song.to_owned(),
+                    extension,
+                },
+                None => TrackSource::YoutubeDl(song.to_owned()),
+            });
+        }
+
+        let Some(media_dir) = media_dir else {
+            return Err(anyhow!("Invalid URL"));
+        };
+
+        Ok(TrackSource::Local(resolve_local(media_dir, song)?))
+    }
+
+    /// The first YouTube result for `query`, for songs we only know the name of.
+    pub fn search(query: &str) -> Self {
+        TrackSource::YoutubeDl(format!("ytsearch1:{}", query))
+    }
+
+    /// A probe hint based on the file extension, if we know it.
+    pub fn hint(&self) -> Hint {
+        let mut hint = Hint::new();
+        match self {
+            TrackSource::Http { extension, .. } => {
+                hint.with_extension(extension);
+            }
+            TrackSource::Local(path) => {
+                if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+                    hint.with_extension(ext);
+                }
+            }
+            TrackSource::YoutubeDl(_) => {}
+        }
+        hint
+    }
+
+    /// A title to fall back on when a file has no tags.
+    pub fn file_name(&self) -> Option<String> {
+        let name = match self {
+            TrackSource::Http { url, .. } => Url::parse(url)
+                .ok()?
+                .path_segments()?
+                .next_back()
+                .map(|s| {
+                    urlencoding::decode(s)
+                        .map(|s| s.into_owned())
+                        .unwrap_or(s.to_owned())
+                })?,
+            TrackSource::Local(path) => path.file_name()?.to_string_lossy().into_owned(),
+            TrackSource::YoutubeDl(_) => return None,
+        };
+
+        Some(match name.rsplit_once('.') {
+            Some((stem, _)) => stem.to_owned(),
+            None => name,
+        })
+    }
+}
+
+/// Where a queued track came from, so it can be loaded again.
+pub struct OriginalSource;
+
+impl TypeMapKey for OriginalSource {
+    type Value = TrackSource;
+}
+
+fn audio_extension(path: &str) -> Option<String> {
+    let (_, ext) = path.rsplit_once('.')?;
+    let ext = ext.to_ascii_lowercase();
+    AUDIO_EXTENSIONS.contains(&ext.as_str()).then_some(ext)
+}
+
+/// Resolves `relative` inside `media_dir`, refusing anything that escapes it.
+fn resolve_local(media_dir: &Path, relative: &str) -> Result<PathBuf> {
+    let root = media_dir.canonicalize()?;
+    let path = root
+        .join(relative.trim_start_matches('/'))
+        .canonicalize()
+        .map_err(|_| anyhow!("No such file in the media directory"))?;
+
+    if !path.starts_with(&root) || !path.is_file() {
+        return Err(anyhow!("No such file in the media directory"));
+    }
+    if audio_extension(&path.to_string_lossy()).is_none() {
+        return Err(anyhow!("That file isn't a supported audio format"));
+    }
+
+    Ok(path)
+}
+
+/// Lists playable files in the media directory whose path contains `partial`.
+pub fn list_local(media_dir: &Path, partial: &str, limit: usize) -> Vec<String> {
+    let partial = partial.to_lowercase();
+    let mut found = Vec::new();
+    let mut dirs = vec![media_dir.to_owned()];
+
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            // linked directories could loop forever or lead out of the media directory
+            if file_type.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let Ok(relative) = path.strip_prefix(media_dir) else {
+                continue;
+            };
+            // and linked files are only offered if they'd actually be played
+            if file_type.is_symlink()
+                && resolve_local(media_dir, &relative.to_string_lossy()).is_err()
+            {
+                continue;
+            }
+            let relative = relative.to_string_lossy();
+            if audio_extension(&relative).is_some() && relative.to_lowercase().contains(&partial) {
+                found.push(relative.into_owned());
+            }
+        }
+    }
+
+    // sorted before cutting it short, so the same files come up every time
+    found.sort();
+    found.truncate(limit);
+    found
+}
+
+/// A track that's been looked up and is ready to be enqueued.
+pub struct Resolved {
+    pub input: Input,
+    pub metadata: Option<AuxMetadata>,
+    pub cover: Option<Cover>,
+    /// Normalization gain; only looked up when `normalize` was asked for.
+    pub gain: f32,
+    /// Set for tracks that go through the download cache.
+    pub prefetch_url: Option<String>,
+}
+
+/// Builds the input for a source and gathers whatever metadata it has.
+pub async fn resolve(
+    http: HttpClient,
+    source: TrackSource,
+    normalize: bool,
+    cache: &Arc<TrackCache>,
+    ytdl: &'static YtdlConfig,
+) -> Resolved {
+    let kind = source.kind();
+    let started = Instant::now();
+    let resolved = resolve_inner(http, source, normalize, cache, ytdl).await;
+    metrics().resolved(kind.name(), started.elapsed());
+    resolved
+}
+
+async fn resolve_inner(
+    http: HttpClient,
+    source: TrackSource,
+    normalize: bool,
+    cache: &Arc<TrackCache>,
+    ytdl: &'static YtdlConfig,
+) -> Resolved {
+    let hint = source.hint();
+    let file_name = source.file_name();
+
+    let (input, tags): (Input, _) = match source {
+        TrackSource::YoutubeDl(url) => {
+            let gain = if normalize {
+                loudness::normalization_gain(&http, &url).await
+            } else {
+                1.0
+            };
+            let mut src = ytdl.source(http, url.clone());
+            let metadata = match src
+                .aux_metadata()
+                .instrument(info_span!("ytdl_metadata", url = %url))
+                .await
+            {
+                Ok(metadata) => Some(metadata),
+                Err(_) => {
+                    metrics().ytdl_failed();
+                    None
+                }
+            };
+            return Resolved {
+                input: CachedInput::new(src, url.clone(), cache.clone()).into(),
+                metadata,
+                cover: None,
+                gain,
+                prefetch_url: Some(url),
+            };
+        }
+        TrackSource::Http { url, .. } => {
+            let mut tags = tags::from_url(&http, &url, hint).await;
+            tags.metadata.source_url = Some(url.clone());
+            (HttpRequest::new(http, url).into(), tags)
+        }
+        TrackSource::Local(path) => {
+            let tags = tags::from_path(&path, hint).await;
+            (File::new(path).into(), tags)
+        }
+    };
+
+    let mut metadata = tags.metadata;
+    if metadata.title.is_none() {
+        metadata.title = file_name;
+    }
+
+    let gain = match tags.replay_gain {
+        Some(db) if normalize => loudness::gain_for_replay_gain(db),
+        _ => 1.0,
+    };
+
+    Resolved {
+        input,
+        metadata: Some(metadata),
+        cover: tags.cover,
+        gain,
+        prefetch_url: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// A media directory with a couple of songs in it, and a secret file next to it.
+    fn media_dir(name: &str) -> (PathBuf, PathBuf) {
+        let base = std::env::temp_dir().join(format!("marine-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let media = base.join("media");
+        fs::create_dir_all(media.join("album")).unwrap();
+        fs::write(media.join("song.mp3"), b"").unwrap();
+        fs::write(media.join("album/Track One.FLAC"), b"").unwrap();
+        fs::write(media.join("notes.txt"), b"").unwrap();
+        fs::write(base.join("secret.mp3"), b"").unwrap();
+        (base, media)
+    }
+
+    #[test]
+    fn parses_what_was_typed() {
+        assert_eq!(
+            TrackSource::parse("https://youtu.be/dQw4w9WgXcQ", None).unwrap(),
+            TrackSource::YoutubeDl("https://youtu.be/dQw4w9WgXcQ".to_owned())
+        );
+        assert_eq!(
+            TrackSource::parse(
+                "https://cdn.discordapp.com/attachments/1/2/song.MP3?ex=1",
+                None
+            )
+            .unwrap(),
+            TrackSource::Http {
+                url: "https://cdn.discordapp.com/attachments/1/2/song.MP3?ex=1".to_owned(),
+                extension: "mp3".to_owned(),
+            }
+        );
+        assert!(TrackSource::parse("http://example.com/song.mp3", None).is_err());
+        assert!(TrackSource::parse("song.mp3", None).is_err());
+    }
+
+    #[test]
+    fn knows_audio_extensions() {
+        assert_eq!(audio_extension("/a/b/song.Opus").as_deref(), Some("opus"));
+        assert_eq!(audio_extension("album.v2/song.wav").as_deref(), Some("wav"));
+        assert_eq!(audio_extension("/watch"), None);
+        assert_eq!(audio_extension("notes.txt"), None);
+        assert_eq!(audio_extension("mp3"), None);
+    }
+
+    #[test]
+    fn resolves_files_inside_the_media_dir() {
+        let (base, media) = media_dir("resolve");
+        let root = media.canonicalize().unwrap();
+
+        assert_eq!(
+            TrackSource::parse("album/Track One.FLAC", Some(&media)).unwrap(),
+            TrackSource::Local(root.join("album/Track One.FLAC"))
+        );
+        // a leading slash is still relative to the media directory
+        assert_eq!(
+            resolve_local(&media, "/song.mp3").unwrap(),
+            root.join("song.mp3")
+        );
+        assert_eq!(
+            resolve_local(&media, "album/../song.mp3").unwrap(),
+            root.join("song.mp3")
+        );
+
+        assert!(resolve_local(&media, "missing.mp3").is_err());
+        assert!(resolve_local(&media, "album").is_err());
+        assert!(resolve_local(&media, "notes.txt").is_err());
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn refuses_paths_that_escape_the_media_dir() {
+        let (base, media) = media_dir("escape");
+        let secret = base.join("secret.mp3");
+
+        assert!(resolve_local(&media, "../secret.mp3").is_err());
+        assert!(resolve_local(&media, "album/../../secret.mp3").is_err());
+        assert!(resolve_local(&media, &secret.to_string_lossy()).is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&secret, media.join("linked.mp3")).unwrap();
+            std::os::unix::fs::symlink(&base, media.join("outside")).unwrap();
+            std::os::unix::fs::symlink(media.join("song.mp3"), media.join("alias.mp3")).unwrap();
+
+            assert!(resolve_local(&media, "linked.mp3").is_err());
+            assert!(resolve_local(&media, "outside/secret.mp3").is_err());
+            assert!(resolve_local(&media, "alias.mp3").is_ok());
+
+            let listed = list_local(&media, "", 10);
+            assert!(listed.contains(&"alias.mp3".to_owned()), "{:?}", listed);
+            assert!(!listed
+                .iter()
+                .any(|p| p.contains("secret") || p.contains("linked")));
+        }
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn lists_matching_audio_files() {
+        let (base, media) = media_dir("list");
+
+        assert_eq!(
+            list_local(&media, "", 10),
+            ["album/Track One.FLAC", "song.mp3"]
+        );
+        assert_eq!(
+            list_local(&media, "track one", 10),
+            ["album/Track One.FLAC"]
+        );
+        assert_eq!(list_local(&media, "", 1), ["album/Track One.FLAC"]);
+        assert!(list_local(&media, "nothing", 10).is_empty());
+        fs::remove_dir_all(base).unwrap();
+    }
+}
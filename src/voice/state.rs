@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use serenity::all::{ChannelId, GuildId};
+use tokio::sync::RwLock;
+
+use super::filters::SharedFilters;
+
+/// Playback preferences that stick around for a guild between tracks.
+#[derive(Debug, Clone, Default)]
+pub struct GuildPlayback {
+    /// Audio filters, shared with every track currently being decoded for this guild.
+    pub filters: SharedFilters,
+    /// The channel the bot was summoned from, where it talks about playback.
+    pub channel: Option<ChannelId>,
+}
+
+#[derive(Default)]
+pub struct PlaybackState {
+    guilds: RwLock<HashMap<GuildId, GuildPlayback>>,
+}
+
+impl PlaybackState {
+    pub async fn get(&self, guild_id: GuildId) -> GuildPlayback {
+        self.guilds
+            .read()
+            .await
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The guild's filter settings. Changes made through this are picked up by playing tracks.
+    pub async fn filters(&self, guild_id: GuildId) -> SharedFilters {
+        self.guilds
+            .write()
+            .await
+            .entry(guild_id)
+            .or_default()
+            .filters
+            .clone()
+    }
+
+    /// Applies `f` to the guild's preferences and returns the updated copy.
+    pub async fn update(
+        &self,
+        guild_id: GuildId,
+        f: impl FnOnce(&mut GuildPlayback),
+    ) -> GuildPlayback {
+        let mut guilds = self.guilds.write().await;
+        let entry = guilds.entry(guild_id).or_default();
+        f(entry);
+        entry.clone()
+    }
+}
@@ -0,0 +1,181 @@
+use std::{io::Cursor, path::Path, time::Duration};
+
+use anyhow::Result;
+use reqwest::{header::RANGE, Client as HttpClient};
+use songbird::input::{codecs::PROBE, AuxMetadata};
+use symphonia::core::{
+    formats::FormatOptions,
+    io::{MediaSource, MediaSourceStream},
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey, Value},
+    probe::Hint,
+};
+use tracing::info;
+
+use super::metadata::Cover;
+
+// Tags and cover art live at the start of most files, so we don't download the whole thing.
+const TAG_PREFETCH_BYTES: u64 = 1024 * 1024;
+
+/// What we could learn from a file's embedded tags.
+#[derive(Debug, Default)]
+pub struct TagInfo {
+    pub metadata: AuxMetadata,
+    pub cover: Option<Cover>,
+    /// ReplayGain track gain, in dB.
+    pub replay_gain: Option<f32>,
+}
+
+/// Reads the tags of a remote file from its first megabyte.
+pub async fn from_url(http: &HttpClient, url: &str, hint: Hint) -> TagInfo {
+    let bytes = match fetch_head(http, url).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            info!("Couldn't fetch {} for tags: {}", url, e);
+            return TagInfo::default();
+        }
+    };
+
+    tokio::task::spawn_blocking(move || read_tags(Box::new(Cursor::new(bytes)), hint))
+        .await
+        .unwrap_or_default()
+}
+
+/// Reads the tags of a local file.
+pub async fn from_path(path: &Path, hint: Hint) -> TagInfo {
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || match std::fs::File::open(&path) {
+        Ok(file) => read_tags(Box::new(file), hint),
+        Err(e) => {
+            info!("Couldn't open {} for tags: {}", path.display(), e);
+            TagInfo::default()
+        }
+    })
+    .await
+    .unwrap_or_default()
+}
+
+async fn fetch_head(http: &HttpClient, url: &str) -> Result<Vec<u8>> {
+    let mut response = http
+        .get(url)
+        .header(RANGE, format!("bytes=0-{}", TAG_PREFETCH_BYTES - 1))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // servers that ignore the range header send everything, so stop reading early ourselves
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() as u64 >= TAG_PREFETCH_BYTES {
+            break;
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Probes a source and collects tags from both the probe (e.g. ID3) and container
+/// (e.g. MP4 atoms, Vorbis comments) metadata.
+pub fn read_tags(source: Box<dyn MediaSource>, hint: Hint) -> TagInfo {
+    let mss = MediaSourceStream::new(source, Default::default());
+    let mut probed = match PROBE.format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    ) {
+        Ok(probed) => probed,
+        Err(e) => {
+            info!("Couldn't probe file for tags: {}", e);
+            return TagInfo::default();
+        }
+    };
+
+    let mut info = TagInfo::default();
+
+    if let Some(track) = probed.format.default_track() {
+        let params = &track.codec_params;
+        info.metadata.sample_rate = params.sample_rate;
+        info.metadata.channels = params.channels.map(|c| c.count() as u8);
+        if let (Some(time_base), Some(frames)) = (params.time_base, params.n_frames) {
+            let time = time_base.calc_time(frames);
+            info.metadata.duration =
+                Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac));
+        }
+    }
+
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        apply_revision(&mut info, revision);
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        apply_revision(&mut info, revision);
+    }
+
+    info
+}
+
+fn apply_revision(info: &mut TagInfo, revision: &MetadataRevision) {
+    let meta = &mut info.metadata;
+
+    for tag in revision.tags() {
+        let value = match &tag.value {
+            Value::String(s) => s.trim().to_owned(),
+            other => other.to_string(),
+        };
+        if value.is_empty() {
+            continue;
+        }
+
+        match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => meta.title = Some(value),
+            Some(StandardTagKey::Artist) => meta.artist = Some(value),
+            Some(StandardTagKey::AlbumArtist) if meta.artist.is_none() => meta.artist = Some(value),
+            Some(StandardTagKey::Album) => meta.album = Some(value),
+            Some(StandardTagKey::Date) => meta.date = Some(value),
+            Some(StandardTagKey::ReplayGainTrackGain) => {
+                info.replay_gain = parse_replay_gain(&value);
+            }
+            _ => {}
+        }
+    }
+
+    // prefer the front cover, but take whatever picture there is
+    let visual = revision
+        .visuals()
+        .iter()
+        .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+        .or_else(|| revision.visuals().first());
+
+    if let Some(visual) = visual {
+        info.cover = Some(Cover {
+            data: visual.data.to_vec(),
+            media_type: visual.media_type.clone(),
+        });
+    }
+}
+
+/// Parses ReplayGain values like `-6.54 dB`.
+fn parse_replay_gain(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .trim_end_matches("dB")
+        .trim_end_matches("db")
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_replay_gain() {
+        assert_eq!(parse_replay_gain("-6.54 dB"), Some(-6.54));
+        assert_eq!(parse_replay_gain(" +2.10 dB "), Some(2.1));
+        assert_eq!(parse_replay_gain("-0.5db"), Some(-0.5));
+        assert_eq!(parse_replay_gain("3"), Some(3.0));
+        assert_eq!(parse_replay_gain("loud"), None);
+        assert_eq!(parse_replay_gain(""), None);
+    }
+}
@@ -0,0 +1,132 @@
+use reqwest::Client as HttpClient;
+use serenity::all::GuildId;
+use songbird::{tracks::TrackHandle, Songbird};
+
+use crate::{helpers::get_http_client, settings::GuildSettings, AppError, Context, Data};
+
+use super::{
+    controls::can_control,
+    loudness::{self, TrackGain},
+    metadata::Metadata,
+};
+
+/// Applies the guild's volume (and the track's normalization gain, if enabled) to a track.
+pub async fn apply_volume(track: &TrackHandle, settings: &GuildSettings) {
+    // the track may already have finished, nothing to do then
+    let _ = track.set_volume(track_volume(track, settings).await);
+}
+
+/// The volume a track should be playing at with the guild's current settings.
+pub async fn track_volume(track: &TrackHandle, settings: &GuildSettings) -> f32 {
+    let gain = if settings.normalize {
+        track
+            .typemap()
+            .read()
+            .await
+            .get::<TrackGain>()
+            .copied()
+            .unwrap_or(1.0)
+    } else {
+        1.0
+    };
+
+    settings.volume_scale() * gain
+}
+
+/// Changes and saves the guild's volume and normalization, and applies them to everything queued.
+pub async fn set_volume(
+    data: &Data,
+    manager: &Songbird,
+    http: &HttpClient,
+    guild_id: GuildId,
+    level: Option<u16>,
+    normalize: Option<bool>,
+) -> Result<GuildSettings, AppError> {
+    let (settings, _) = data
+        .settings
+        .update(guild_id, |settings| {
+            if let Some(level) = level {
+                settings.default_volume = level.min(200);
+            }
+            if let Some(normalize) = normalize {
+                settings.normalize = normalize;
+            }
+        })
+        .await?;
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let tracks = handler_lock.lock().await.queue().current_queue();
+
+        for track in tracks {
+            // tracks queued while normalization was off never had their loudness looked up
+            if settings.normalize && !track.typemap().read().await.contains_key::<TrackGain>() {
+                let source_url = track
+                    .typemap()
+                    .read()
+                    .await
+                    .get::<Metadata>()
+                    .and_then(|m| m.source_url.clone());
+                if let Some(url) = source_url {
+                    let gain = loudness::normalization_gain(http, &url).await;
+                    track.typemap().write().await.insert::<TrackGain>(gain);
+                }
+            }
+            apply_volume(&track, &settings).await;
+        }
+    }
+
+    Ok(settings)
+}
+
+#[poise::command(
+    category = "Music",
+    slash_command,
+    prefix_command,
+    aliases("vol"),
+    guild_only
+)]
+pub async fn volume(
+    ctx: Context<'_>,
+    #[description = "Volume in percent"]
+    #[min = 0]
+    #[max = 200]
+    level: Option<u16>,
+    #[description = "Level out tracks using their loudness metadata"] normalize: Option<bool>,
+) -> Result<(), AppError> {
+    let (guild_id, _) = super::guild_info(ctx).await?;
+
+    if level.is_none() && normalize.is_none() {
+        let settings = ctx.data().settings.get(guild_id).await;
+        ctx.say(format!(
+            "Volume is {}%, normalization is {}",
+            settings.default_volume,
+            if settings.normalize { "on" } else { "off" }
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    if let Some(level) = level {
+        if level > 200 {
+            ctx.say("Volume has to be between 0 and 200").await?;
+            return Ok(());
+        }
+    }
+
+    can_control(ctx).await?;
+    let manager = songbird::get(ctx.serenity_context())
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let http = get_http_client(ctx.serenity_context()).await;
+    let settings = set_volume(ctx.data(), &manager, &http, guild_id, level, normalize).await?;
+
+    ctx.say(format!(
+        "Volume set to {}%, normalization is {}",
+        settings.default_volume,
+        if settings.normalize { "on" } else { "off" }
+    ))
+    .await?;
+
+    Ok(())
+}